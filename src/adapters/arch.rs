@@ -1,79 +1,218 @@
-use fs2::FileExt;
-use std::fs::OpenOptions;
-use std::path::Path;
-
-pub fn pm_lock_message(root: &Path) -> Option<String> {
-    // pacman DB lock
-    let lock_path = "/var/lib/pacman/db.lck";
-    let p = if root == Path::new("/") {
-        Path::new(lock_path).to_path_buf()
-    } else {
-        root.join(lock_path.trim_start_matches('/'))
-    };
-    if !p.exists() {
-        return None;
+//! pacman database lock (`/var/lib/pacman/db.lck`).
+//!
+//! pacman locks its database by creating `db.lck` exclusively and deleting it when done; an
+//! existing file means a package manager is running. Committing operations take the lock the
+//! same way for their whole duration, so pacman cannot start while `/usr/bin` is being
+//! rewritten, and give it up only around their own pacman runs ([`unlocked`]). The file holds
+//! our PID: a lock left behind by a crashed oxidizr-arch is removed on the next run, while a
+//! lock of any other owner is reported as busy.
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde_json::json;
+
+/// Lock file location, relative to the target root.
+const LOCK_REL: &str = "var/lib/pacman/db.lck";
+
+/// Locks this process holds (released by [`unlocked`] around pacman runs).
+static HELD: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Locks another process took while we had released them: never ours to remove again, and no
+/// further pacman runs are allowed under them.
+static LOST: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+const BUSY: &str =
+    "Package manager busy (pacman db.lck detected); retry after current operation finishes.";
+
+/// The pacman database lock, held until dropped.
+#[derive(Debug)]
+pub struct DbLock {
+    path: Option<PathBuf>,
+}
+
+impl DbLock {
+    /// Take the lock under `root`. Roots without a pacman database need no lock.
+    pub fn acquire(root: &Path) -> Result<DbLock, String> {
+        let path = root.join(LOCK_REL);
+        if !path.parent().is_some_and(Path::is_dir) {
+            return Ok(DbLock { path: None });
+        }
+        match create(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let Some(pid) = stale_owner(&path) else {
+                    return Err(format!(
+                        "{} If no package manager is running, remove {}.",
+                        BUSY,
+                        path.display()
+                    ));
+                };
+                fs::remove_file(&path)
+                    .map_err(|e| format!("cannot remove stale {}: {e}", path.display()))?;
+                eprintln!(
+                    "{}",
+                    json!({"event":"pm.lock_stale_removed", "path": path, "pid": pid})
+                );
+                create(&path).map_err(|_| BUSY.to_string())?;
+            }
+            Err(e) => return Err(format!("cannot create {}: {e}", path.display())),
+        }
+        HELD.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(path.clone());
+        Ok(DbLock { path: Some(path) })
     }
-    if let Ok(f) = OpenOptions::new().read(true).write(true).open(&p) {
-        match f.try_lock_exclusive() {
-            Ok(_) => {
-                let _ = f.unlock();
-                None
+}
+
+impl Drop for DbLock {
+    fn drop(&mut self) {
+        if let Some(p) = self.path.take() {
+            LOST.lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .retain(|l| *l != p);
+            let mut held = HELD.lock().unwrap_or_else(|e| e.into_inner());
+            // A lost lock belongs to whoever took it
+            if held.contains(&p) && owned(&p) {
+                let _ = fs::remove_file(&p);
             }
-            Err(_) => Some("Package manager busy (pacman db.lck detected); retry after current operation finishes.".to_string()),
+            held.retain(|h| *h != p);
         }
-    } else {
-        // If we cannot open the file, be conservative and warn
-        Some("Package manager may be busy (pacman db.lck present)".to_string())
+    }
+}
+
+/// Run `f` (a pacman invocation on `root`) with our database lock released, taking it back
+/// afterwards. Fails when another process took the lock in between; the lock is then lost and
+/// every later call is refused, so we never run pacman next to the new owner.
+pub fn unlocked<T>(root: &Path, f: impl FnOnce() -> T) -> Result<T, String> {
+    let path = root.join(LOCK_REL);
+    if LOST
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains(&path)
+    {
+        return Err(format!(
+            "pacman database lock {} was lost to another process; not running pacman",
+            path.display()
+        ));
+    }
+    let held = HELD
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains(&path)
+        .then_some(path);
+    if let Some(p) = &held {
+        if owned(p) {
+            let _ = fs::remove_file(p);
+        }
+    }
+    let out = f();
+    if let Some(p) = &held {
+        if create(p).is_err() {
+            HELD.lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .retain(|h| h != p);
+            LOST.lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(p.clone());
+            return Err(format!(
+                "pacman database lock {} was taken by another process during our pacman run",
+                p.display()
+            ));
+        }
+    }
+    Ok(out)
+}
+
+/// Create the lock file exclusively, recording our PID.
+fn create(path: &Path) -> std::io::Result<()> {
+    let mut f = OpenOptions::new().write(true).create_new(true).open(path)?;
+    writeln!(f, "{}", std::process::id())
+}
+
+/// Whether the lock file at `path` records our PID.
+fn owned(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|s| s.trim() == std::process::id().to_string())
+}
+
+/// PID of a dead oxidizr-arch that left the lock at `path`; `None` for pacman's (empty) lock
+/// or a live owner.
+fn stale_owner(path: &Path) -> Option<u32> {
+    let pid: u32 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    let ours = fs::read_to_string("/proc/self/comm").ok()?;
+    match fs::read_to_string(format!("/proc/{}/comm", pid)) {
+        // A live process with our name still owns it
+        Ok(comm) if comm == ours => None,
+        _ => Some(pid),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::{self};
 
-    fn mk_lock_path(root: &Path) -> std::path::PathBuf {
-        let rel = "var/lib/pacman";
-        let p = root.join(rel);
+    fn mk_lock_path(root: &Path) -> PathBuf {
+        let p = root.join("var/lib/pacman");
         let _ = fs::create_dir_all(&p);
         p.join("db.lck")
     }
 
     #[test]
-    fn test_pacman_lock_guard_when_db_lck_present_and_locked() {
+    fn test_lock_refused_while_pacman_holds_it() {
         let t = tempfile::tempdir().unwrap();
         let lock = mk_lock_path(t.path());
-        let f = OpenOptions::new()
-            .create(true)
-            .truncate(false)
-            .read(true)
-            .write(true)
-            .open(&lock)
-            .unwrap();
-        // Hold an exclusive lock to simulate pacman running
-        f.lock_exclusive().unwrap();
-        let msg = pm_lock_message(t.path());
-        // Release lock after check
-        let _ = f.unlock();
-        assert!(msg.is_some(), "expected lock message when db.lck is held");
+        // pacman's lock: an empty file
+        fs::write(&lock, b"").unwrap();
+        let err = DbLock::acquire(t.path()).unwrap_err();
+        assert!(err.contains(BUSY), "{err}");
+        assert!(lock.exists(), "a foreign lock is never removed");
     }
 
     #[test]
-    fn test_pacman_lock_guard_when_db_lck_present_but_unlocked() {
+    fn test_lock_held_released_and_stale_removed() {
         let t = tempfile::tempdir().unwrap();
         let lock = mk_lock_path(t.path());
-        let _f = OpenOptions::new()
-            .create(true)
-            .truncate(false)
-            .read(true)
-            .write(true)
-            .open(&lock)
-            .unwrap();
-        // No lock taken; pm_lock_message should be able to lock and thus return None
-        let msg = pm_lock_message(t.path());
-        assert!(
-            msg.is_none(),
-            "no message when db.lck exists but is not locked"
-        );
+        // Left by a crashed run whose PID is gone
+        fs::write(&lock, format!("{}\n", u32::MAX)).unwrap();
+        {
+            let _held = DbLock::acquire(t.path()).unwrap();
+            assert_eq!(
+                fs::read_to_string(&lock).unwrap().trim(),
+                std::process::id().to_string()
+            );
+            // A second acquisition in a live process is refused
+            assert!(DbLock::acquire(t.path()).is_err());
+            let seen = unlocked(t.path(), || lock.exists()).unwrap();
+            assert!(!seen, "released around pacman runs");
+            assert!(lock.exists(), "taken back afterwards");
+        }
+        assert!(!lock.exists(), "released on drop");
+
+        // Roots without a pacman database need no lock
+        let bare = tempfile::tempdir().unwrap();
+        drop(DbLock::acquire(bare.path()).unwrap());
+    }
+
+    #[test]
+    fn test_lock_taken_during_pacman_run_is_left_alone() {
+        let t = tempfile::tempdir().unwrap();
+        let lock = mk_lock_path(t.path());
+        let held = DbLock::acquire(t.path()).unwrap();
+        // pacman started by someone else while ours was released
+        let err = unlocked(t.path(), || fs::write(&lock, b"").unwrap()).unwrap_err();
+        assert!(err.contains("taken by another process"), "{err}");
+
+        // The rollback's pacman run is refused instead of deleting their lock
+        let mut ran = false;
+        let err = unlocked(t.path(), || ran = true).unwrap_err();
+        assert!(err.contains("lost"), "{err}");
+        assert!(!ran);
+        assert!(lock.exists());
+
+        drop(held);
+        assert!(lock.exists(), "a lost lock is not removed on drop");
+        assert_eq!(fs::read_to_string(&lock).unwrap(), "");
     }
 }
@@ -1,13 +1,23 @@
 use std::path::Path;
-use std::process::Command;
 
 use oxidizr_cli_core::dest_dir_path;
 use oxidizr_cli_core::{static_fallback_applets, DistroAdapter, PackageKind};
 
+use crate::adapters::pacman_db;
+
 pub struct ArchAdapter;
 
 impl DistroAdapter for ArchAdapter {
     fn enumerate_package_commands(&self, root: &Path, pkg: PackageKind) -> Vec<String> {
+        let name = match pkg {
+            PackageKind::Coreutils => "coreutils",
+            PackageKind::Findutils => "findutils",
+            PackageKind::Sudo => "sudo",
+        };
+        // Prefer the package's file list from the local pacman DB (live or --root tree)
+        if let Some(files) = pacman_db::open(root).files(name) {
+            return commands_from_files(&files);
+        }
         if root != Path::new("/") {
             // Hermetic fallback for tests: enumerate all names under <root>/usr/bin
             let dest = dest_dir_path();
@@ -29,37 +39,56 @@ impl DistroAdapter for ArchAdapter {
             names.dedup();
             return names;
         }
-        let name = match pkg {
-            PackageKind::Coreutils => "coreutils",
-            PackageKind::Findutils => "findutils",
-            PackageKind::Sudo => "sudo",
-        };
-        let out = match Command::new("pacman").args(["-Ql", name]).output() {
-            Ok(o) => o,
-            Err(_) => return Vec::new(),
-        };
-        if !out.status.success() {
-            return Vec::new();
-        }
-        let s = String::from_utf8_lossy(&out.stdout);
-        let mut names = Vec::new();
-        for line in s.lines() {
-            // Expected format: "pkg /path"
-            let mut parts = line.split_whitespace();
-            let _pkg = parts.next();
-            if let Some(path) = parts.next() {
-                if let Some(n) = path
-                    .strip_prefix("/usr/bin/")
-                    .or_else(|| path.strip_prefix("/bin/"))
-                {
-                    if !n.is_empty() && !n.ends_with('/') {
-                        names.push(n.to_string());
-                    }
-                }
+        Vec::new()
+    }
+}
+
+/// Command names under /usr/bin (and legacy /bin) from a package file list.
+fn commands_from_files(files: &[String]) -> Vec<String> {
+    let mut names = Vec::new();
+    for path in files {
+        if let Some(n) = path
+            .strip_prefix("/usr/bin/")
+            .or_else(|| path.strip_prefix("/bin/"))
+        {
+            if !n.is_empty() && !n.ends_with('/') {
+                names.push(n.to_string());
             }
         }
-        names.sort();
-        names.dedup();
-        names
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::pacman_db::tests::write_pkg;
+
+    #[test]
+    fn test_enumerate_reads_local_db_under_non_live_root() {
+        let t = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(t.path().join("usr/bin")).unwrap();
+        // A stray file under usr/bin must not leak in when the DB knows the package
+        std::fs::write(t.path().join("usr/bin/cat"), b"x").unwrap();
+        write_pkg(
+            t.path(),
+            "findutils",
+            "4.10.0-1",
+            "",
+            &[
+                "usr/",
+                "usr/bin/",
+                "usr/bin/find",
+                "usr/bin/xargs",
+                "usr/bin/locate",
+            ],
+        );
+        let names = ArchAdapter.enumerate_package_commands(t.path(), PackageKind::Findutils);
+        assert_eq!(names, vec!["find", "locate", "xargs"]);
+        // Packages missing from the DB fall back to the hermetic /usr/bin scan
+        let names = ArchAdapter.enumerate_package_commands(t.path(), PackageKind::Coreutils);
+        assert_eq!(names, vec!["cat"]);
     }
 }
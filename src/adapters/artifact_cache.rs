@@ -0,0 +1,206 @@
+//! Cached artifacts of a replacement package: its archives (and signatures) in pacman's
+//! package cache and our own cache under `/var/cache/oxidizr-arch`, and the build directories
+//! AUR helpers and our `makepkg` builds leave behind. `restore` removes them once the
+//! package is uninstalled.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::adapters::aur::{self, AurHelper};
+use crate::adapters::aur_build::BUILD_DIR_PREFIX;
+use crate::adapters::pacman_cache::{archive_name_version, cache_dirs};
+use crate::util::paths::ensure_under_root;
+
+/// Archives we copy or build ourselves (provides shims, archives copied into a sysroot),
+/// relative to the target root.
+pub const OWN_CACHE_REL: &str = "var/cache/oxidizr-arch";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Artifact {
+    pub path: PathBuf,
+    /// Apparent size, summed over the tree for directories
+    pub bytes: u64,
+    /// `package` for archives and signatures, else the builder (`makepkg`, `paru`, ...)
+    pub kind: &'static str,
+}
+
+/// Cached artifacts of `pkg` under `root`.
+pub fn find(root: &Path, pkg: &str) -> Vec<Artifact> {
+    let mut dirs = cache_dirs(root);
+    dirs.push(root.join(OWN_CACHE_REL));
+    let mut found: Vec<Artifact> = dirs
+        .iter()
+        .filter_map(|d| fs::read_dir(d).ok())
+        .flat_map(|rd| rd.flatten())
+        .filter(|e| {
+            e.file_name().to_str().is_some_and(|n| {
+                let archive = n.strip_suffix(".sig").unwrap_or(n);
+                archive_name_version(archive).is_some_and(|(name, _)| name == pkg)
+            })
+        })
+        .map(|e| artifact(e.path(), "package"))
+        .collect();
+
+    for helper in aur::REGISTRY {
+        found.extend(
+            expand(root, helper)
+                .into_iter()
+                .map(|d| d.join(pkg))
+                .filter(|d| d.is_dir())
+                .map(|d| artifact(d, helper.name)),
+        );
+    }
+    let prefix = format!("{}{}-", BUILD_DIR_PREFIX, pkg);
+    let tmp = ensure_under_root(root, &std::env::temp_dir());
+    found.extend(
+        children(&tmp)
+            .into_iter()
+            .filter(|p| {
+                p.is_dir()
+                    && p.file_name()
+                        .and_then(|n| n.to_str())
+                        .and_then(|n| n.strip_prefix(&prefix))
+                        .is_some_and(|stamp| stamp.bytes().all(|c| c.is_ascii_digit()))
+            })
+            .map(|d| artifact(d, "makepkg")),
+    );
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    found.dedup_by(|a, b| a.path == b.path);
+    found
+}
+
+/// Remove `artifacts`; a path already gone is not an error.
+pub fn remove(artifacts: &[Artifact]) -> Result<(), String> {
+    for a in artifacts {
+        let res = if a.path.is_dir() {
+            fs::remove_dir_all(&a.path)
+        } else {
+            fs::remove_file(&a.path)
+        };
+        match res {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("cannot remove {}: {e}", a.path.display()));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Human-readable size, like pacman's (`12.3 MiB`).
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn artifact(path: PathBuf, kind: &'static str) -> Artifact {
+    Artifact {
+        bytes: size_of(&path),
+        path,
+        kind,
+    }
+}
+
+/// Apparent size of a file or a directory tree; symlinks are not followed.
+fn size_of(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    children(path).iter().map(|p| size_of(p)).sum()
+}
+
+fn children(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|rd| rd.flatten().map(|e| e.path()).collect())
+        .unwrap_or_default()
+}
+
+/// The helper's build cache directories under `root`.
+fn expand(root: &Path, helper: &AurHelper) -> Vec<PathBuf> {
+    if let Some(rel) = helper.build_cache.strip_prefix("~/") {
+        let mut homes = vec![root.join("root")];
+        homes.extend(children(&root.join("home")));
+        return homes.into_iter().map(|h| h.join(rel)).collect();
+    }
+    let path = ensure_under_root(root, Path::new(helper.build_cache));
+    let Some(prefix) = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.strip_suffix('*'))
+    else {
+        return vec![path];
+    };
+    let parent = path.parent().unwrap_or(root);
+    children(parent)
+        .into_iter()
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(prefix))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_archives_and_build_dirs() {
+        let t = tempfile::tempdir().unwrap();
+        let root = t.path();
+        let write = |rel: &str, len: usize| {
+            let p = root.join(rel);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(p, vec![0u8; len]).unwrap();
+        };
+        write(
+            "var/cache/pacman/pkg/sudo-rs-0.2.4-1-x86_64.pkg.tar.zst",
+            2048,
+        );
+        write(
+            "var/cache/pacman/pkg/sudo-rs-0.2.4-1-x86_64.pkg.tar.zst.sig",
+            10,
+        );
+        write("var/cache/pacman/pkg/sudo-1.9.16-1-x86_64.pkg.tar.zst", 5);
+        write("home/alice/.cache/paru/clone/sudo-rs/PKGBUILD", 100);
+        write("home/alice/.cache/paru/clone/sudo-rs/src/main.rs", 50);
+        write("home/alice/.cache/yay/uutils-findutils-bin/PKGBUILD", 1);
+        write("var/tmp/pamac-build-bob/sudo-rs/PKGBUILD", 7);
+
+        let found = find(root, "sudo-rs");
+        let kinds: Vec<(&str, u64)> = found.iter().map(|a| (a.kind, a.bytes)).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("paru", 150),
+                ("package", 2048),
+                ("package", 10),
+                ("pamac", 7)
+            ]
+        );
+        assert_eq!(human_size(150), "150 B");
+        assert_eq!(human_size(2048 + 512), "2.5 KiB");
+
+        remove(&found).unwrap();
+        assert!(find(root, "sudo-rs").is_empty());
+        assert!(root
+            .join("var/cache/pacman/pkg/sudo-1.9.16-1-x86_64.pkg.tar.zst")
+            .exists());
+        assert_eq!(find(root, "uutils-findutils-bin").len(), 1);
+    }
+}
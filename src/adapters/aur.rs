@@ -0,0 +1,159 @@
+//! AUR helper registry: which helpers we know, their non-interactive install flags, and the
+//! preference order (`OXI_AUR_HELPERS=yay,paru`, default paru, yay, pikaur, aura, pamac).
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::adapters::{arch, pacman_db, pm};
+
+/// Environment variable overriding the helper preference order (comma-separated names).
+pub const HELPERS_ENV: &str = "OXI_AUR_HELPERS";
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AurHelper {
+    pub name: &'static str,
+    /// Arguments before the package name for a non-interactive install
+    args: &'static [&'static str],
+    /// Directory holding the helper's per-package build directories: `~/` is each user's
+    /// home, and a trailing `*` matches per-user directories
+    pub build_cache: &'static str,
+}
+
+/// Known helpers, in the default preference order.
+pub const REGISTRY: &[AurHelper] = &[
+    AurHelper {
+        name: "paru",
+        args: &["-S", "--noconfirm", "--skipreview"],
+        build_cache: "~/.cache/paru/clone",
+    },
+    AurHelper {
+        name: "yay",
+        args: &[
+            "-S",
+            "--noconfirm",
+            "--answerdiff",
+            "None",
+            "--answerclean",
+            "None",
+        ],
+        build_cache: "~/.cache/yay",
+    },
+    AurHelper {
+        name: "pikaur",
+        args: &["-S", "--noconfirm"],
+        build_cache: "~/.cache/pikaur/build",
+    },
+    AurHelper {
+        name: "aura",
+        args: &["-A", "--noconfirm"],
+        build_cache: "/var/cache/aura/vcs",
+    },
+    AurHelper {
+        name: "pamac",
+        args: &["build", "--no-confirm"],
+        build_cache: "/var/tmp/pamac-build-*",
+    },
+];
+
+impl AurHelper {
+    pub fn by_name(name: &str) -> Option<&'static AurHelper> {
+        REGISTRY.iter().find(|h| h.name == name)
+    }
+
+    /// Helper arguments installing `pkg`.
+    pub fn install_args(&self, pkg: &str) -> Vec<String> {
+        let mut args: Vec<String> = self.args.iter().map(|a| a.to_string()).collect();
+        args.push(pkg.to_string());
+        args
+    }
+
+    /// Full command line, for messages.
+    pub fn command_line(&self, pkg: &str) -> String {
+        format!("{} {}", self.name, self.install_args(pkg).join(" "))
+    }
+
+    /// Run the helper for `pkg`. Emits `pm.install` with the same schema as pacman; returns
+    /// the error and the stderr tail on failure.
+    pub fn install(&self, root: &Path, pkg: &str) -> Result<(), (String, String)> {
+        let args = self.install_args(pkg);
+        // The helper runs pacman itself
+        let out = arch::unlocked(root, || {
+            Command::new(self.name)
+                .args(&args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+        })
+        .map_err(|e| (e, String::new()))?
+        .map_err(|e| (format!("failed to spawn {}: {e}", self.name), String::new()))?;
+        pacman_db::invalidate(root);
+        let stderr = String::from_utf8_lossy(&out.stderr).into_owned();
+        let code = out.status.code().unwrap_or(1);
+        pm::report("pm.install", self.name, &args, &[pkg], None, code, &stderr)
+            .map_err(|e| (e, stderr))
+    }
+}
+
+/// Preference order from `spec` (the value of `OXI_AUR_HELPERS`), or the registry order.
+pub fn parse_preference(spec: Option<&str>) -> Result<Vec<&'static AurHelper>, String> {
+    let Some(spec) = spec.filter(|s| !s.trim().is_empty()) else {
+        return Ok(REGISTRY.iter().collect());
+    };
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|n| {
+            AurHelper::by_name(n).ok_or_else(|| {
+                format!(
+                    "unknown AUR helper '{}' in {} (known: {})",
+                    n,
+                    HELPERS_ENV,
+                    REGISTRY
+                        .iter()
+                        .map(|h| h.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+        })
+        .collect()
+}
+
+/// The configured preference order.
+pub fn preference() -> Result<Vec<&'static AurHelper>, String> {
+    parse_preference(std::env::var(HELPERS_ENV).ok().as_deref())
+}
+
+/// Installed helpers, in preference order.
+pub fn detect() -> Result<Vec<&'static AurHelper>, String> {
+    Ok(preference()?
+        .into_iter()
+        .filter(|h| which::which(h.name).is_ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preference_order_and_flags() {
+        let all = parse_preference(None).unwrap();
+        assert_eq!(all[0].name, "paru");
+        assert_eq!(all.len(), REGISTRY.len());
+
+        let pref = parse_preference(Some("pamac, yay")).unwrap();
+        assert_eq!(
+            pref.iter().map(|h| h.name).collect::<Vec<_>>(),
+            ["pamac", "yay"]
+        );
+        assert_eq!(
+            pref[0].command_line("uutils-findutils-bin"),
+            "pamac build --no-confirm uutils-findutils-bin"
+        );
+        assert!(parse_preference(Some("trizen"))
+            .unwrap_err()
+            .contains("unknown AUR helper 'trizen'"));
+    }
+}
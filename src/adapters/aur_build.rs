@@ -0,0 +1,234 @@
+//! Build AUR packages as an unprivileged user and install the result as root.
+//!
+//! AUR helpers and `makepkg` refuse to run as root. When we are root, the build runs as the
+//! configured build user (`OXI_AUR_HELPER_USER`) or the user who invoked sudo (`SUDO_USER`),
+//! with privileges dropped in-process (uid/gid, supplementary groups cleared), inside a private
+//! build directory owned by that user. The resulting package archive is then installed with
+//! `pacman -U`. Output of every build step goes to `build.log` in the build directory; its path
+//! is reported in the `pm.build` event.
+
+use std::fs;
+use std::os::unix::fs::{chown, DirBuilderExt, MetadataExt};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+
+use crate::adapters::pm::{self, InstallOptions};
+
+/// Environment variable naming the build user (takes precedence over `SUDO_USER`).
+pub const BUILD_USER_ENV: &str = "OXI_AUR_HELPER_USER";
+
+/// Prefix of our build directories in the temporary directory: `oxidizr-build-<pkg>-<stamp>`.
+pub const BUILD_DIR_PREFIX: &str = "oxidizr-build-";
+
+/// Base URL of AUR package git repositories.
+const AUR_GIT: &str = "https://aur.archlinux.org";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildUser {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+}
+
+impl BuildUser {
+    /// The user to build as: only when running as root, from `OXI_AUR_HELPER_USER` or
+    /// `SUDO_USER` (ignoring root itself).
+    pub fn resolve() -> Result<Option<BuildUser>, String> {
+        if !running_as_root() {
+            return Ok(None);
+        }
+        let configured = std::env::var(BUILD_USER_ENV).ok().filter(|u| !u.is_empty());
+        let name = match configured.or_else(|| std::env::var("SUDO_USER").ok()) {
+            Some(n) if n != "root" && !n.is_empty() => n,
+            _ => return Ok(None),
+        };
+        let passwd = fs::read_to_string("/etc/passwd")
+            .map_err(|e| format!("cannot read /etc/passwd: {e}"))?;
+        lookup(&passwd, &name)
+            .map(Some)
+            .ok_or_else(|| format!("build user '{}' not found in /etc/passwd", name))
+    }
+
+    /// A command running as this user, with its identity and environment.
+    fn command(&self, program: &str) -> Command {
+        let mut cmd = Command::new(program);
+        cmd.uid(self.uid)
+            .gid(self.gid)
+            .env("HOME", &self.home)
+            .env("USER", &self.name)
+            .env("LOGNAME", &self.name)
+            .env_remove("SUDO_USER")
+            .env_remove("SUDO_UID")
+            .env_remove("SUDO_GID")
+            .stdin(Stdio::null());
+        cmd
+    }
+}
+
+fn running_as_root() -> bool {
+    fs::metadata("/proc/self").is_ok_and(|m| m.uid() == 0)
+}
+
+/// AUR git repository of `pkg`.
+pub fn aur_git_url(pkg: &str) -> String {
+    format!("{}/{}.git", AUR_GIT, pkg)
+}
+
+/// Find `name` in passwd(5) content.
+fn lookup(passwd: &str, name: &str) -> Option<BuildUser> {
+    passwd.lines().find_map(|l| {
+        let f: Vec<&str> = l.split(':').collect();
+        if f.len() < 7 || f[0] != name {
+            return None;
+        }
+        Some(BuildUser {
+            name: f[0].to_string(),
+            uid: f[2].parse().ok()?,
+            gid: f[3].parse().ok()?,
+            home: PathBuf::from(f[5]),
+        })
+    })
+}
+
+/// Built package archive in `dir` (`*.pkg.tar*`, not signatures); the last by name if several.
+fn find_archive(dir: &Path) -> Option<PathBuf> {
+    let mut hits: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.contains(".pkg.tar") && !n.ends_with(".sig"))
+        })
+        .collect();
+    hits.sort();
+    hits.pop()
+}
+
+/// Clone `pkg` from the AUR and run `makepkg` as `user` in a private build directory, then
+/// install the archive as root with `pacman -U`.
+pub fn build_and_install(root: &Path, pkg: &str, user: &BuildUser) -> Result<(), String> {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let dir = std::env::temp_dir().join(format!("{}{}-{}", BUILD_DIR_PREFIX, pkg, stamp));
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|e| format!("cannot create build directory {}: {e}", dir.display()))?;
+    chown(&dir, Some(user.uid), Some(user.gid))
+        .map_err(|e| format!("cannot hand {} to {}: {e}", dir.display(), user.name))?;
+    let log_path = dir.join("build.log");
+    let log = fs::File::create(&log_path)
+        .map_err(|e| format!("cannot create {}: {e}", log_path.display()))?;
+    chown(&log_path, Some(user.uid), Some(user.gid)).map_err(|e| e.to_string())?;
+
+    let src = dir.join(pkg);
+    let steps: [(&str, Vec<String>, &Path); 2] = [
+        (
+            "git",
+            vec![
+                "clone".into(),
+                "--depth".into(),
+                "1".into(),
+                aur_git_url(pkg),
+                src.display().to_string(),
+            ],
+            &dir,
+        ),
+        ("makepkg", vec!["--noconfirm".into()], &src),
+    ];
+    let mut code = 0;
+    let mut failed = None;
+    for (program, args, cwd) in &steps {
+        let out = log.try_clone().map_err(|e| e.to_string())?;
+        let err = log.try_clone().map_err(|e| e.to_string())?;
+        let status = user
+            .command(program)
+            .args(args)
+            .current_dir(cwd)
+            .stdout(out)
+            .stderr(err)
+            .status()
+            .map_err(|e| format!("failed to spawn {} as {}: {e}", program, user.name))?;
+        if !status.success() {
+            code = status.code().unwrap_or(1);
+            failed = Some(format!("{} {}", program, args.join(" ")));
+            break;
+        }
+    }
+    let archive = failed.is_none().then(|| find_archive(&src)).flatten();
+    eprintln!(
+        "{}",
+        json!({
+            "event":"pm.build",
+            "package": pkg,
+            "user": user.name,
+            "build_dir": dir,
+            "log": log_path,
+            "exit_code": code,
+            "archive": archive,
+        })
+    );
+    if let Some(step) = failed {
+        return Err(format!(
+            "building {} as {} failed: {} exited with code {} (log: {})",
+            pkg,
+            user.name,
+            step,
+            code,
+            log_path.display()
+        ));
+    }
+    let archive = archive.ok_or_else(|| {
+        format!(
+            "makepkg produced no package for {} (log: {})",
+            pkg,
+            log_path.display()
+        )
+    })?;
+    pm::get().install_files(root, &[archive], &[pkg], &InstallOptions::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_and_find_archive() {
+        let passwd = "root:x:0:0::/root:/bin/bash\nalice:x:1000:1000:Alice:/home/alice:/bin/zsh\n";
+        assert_eq!(
+            lookup(passwd, "alice"),
+            Some(BuildUser {
+                name: "alice".to_string(),
+                uid: 1000,
+                gid: 1000,
+                home: PathBuf::from("/home/alice"),
+            })
+        );
+        assert_eq!(lookup(passwd, "bob"), None);
+
+        let t = tempfile::tempdir().unwrap();
+        for f in [
+            "PKGBUILD",
+            "uutils-findutils-bin-0.7.0-1-x86_64.pkg.tar.zst",
+            "uutils-findutils-bin-0.7.0-1-x86_64.pkg.tar.zst.sig",
+        ] {
+            fs::write(t.path().join(f), b"").unwrap();
+        }
+        assert_eq!(
+            find_archive(t.path()),
+            Some(
+                t.path()
+                    .join("uutils-findutils-bin-0.7.0-1-x86_64.pkg.tar.zst")
+            )
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! The managed pacman include holding pinned replacement packages back from upgrades:
+//! `/etc/pacman.d/oxidizr-arch-ignore.conf` carries an `IgnorePkg` line for every pin and is
+//! included from the `[options]` section of `pacman.conf`.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::util::paths::ensure_under_root;
+
+/// Path of the managed include, inside the root.
+pub const INCLUDE_PATH: &str = "/etc/pacman.d/oxidizr-arch-ignore.conf";
+
+const HEADER: &str = "# Managed by oxidizr-arch: replacement packages pinned with `use --version`.\n# Do not edit; unpin with `oxidizr-arch use <package> --unpin`.\n";
+
+/// Rewrite the include for the pinned package `names` and register it in `pacman.conf`. Nothing
+/// is written while no package was ever pinned.
+pub fn sync(root: &Path, names: &[&str]) -> Result<(), String> {
+    let include = ensure_under_root(root, Path::new(INCLUDE_PATH));
+    if names.is_empty() && !include.exists() {
+        return Ok(());
+    }
+    let mut body = HEADER.to_string();
+    if !names.is_empty() {
+        body.push_str(&format!("IgnorePkg = {}\n", names.join(" ")));
+    }
+    if let Some(parent) = include.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("cannot create {}: {e}", parent.display()))?;
+    }
+    fs::write(&include, body).map_err(|e| format!("cannot write {}: {e}", include.display()))?;
+
+    let conf_path = root.join("etc/pacman.conf");
+    let registered = match fs::read_to_string(&conf_path) {
+        Ok(conf) => {
+            if let Some(updated) = with_include(&conf) {
+                fs::write(&conf_path, updated)
+                    .map_err(|e| format!("cannot write {}: {e}", conf_path.display()))?;
+            }
+            true
+        }
+        Err(_) => false,
+    };
+    if !registered {
+        eprintln!(
+            "[warn] {} not found; add `Include = {}` to its [options] section",
+            conf_path.display(),
+            INCLUDE_PATH
+        );
+    }
+    eprintln!(
+        "{}",
+        json!({
+            "event":"pm.ignorepkg",
+            "include": INCLUDE_PATH,
+            "packages": names,
+            "registered": registered,
+        })
+    );
+    Ok(())
+}
+
+/// `conf` with our `Include` added at the top of `[options]`; `None` when it is already there
+/// (or there is no `[options]` section to add it to).
+fn with_include(conf: &str) -> Option<String> {
+    let included = conf.lines().any(|l| {
+        l.split_once('=')
+            .is_some_and(|(k, v)| k.trim() == "Include" && v.trim() == INCLUDE_PATH)
+    });
+    if included {
+        return None;
+    }
+    let mut out = String::with_capacity(conf.len() + 64);
+    let mut added = false;
+    for line in conf.lines() {
+        out.push_str(line);
+        out.push('\n');
+        if !added && line.trim() == "[options]" {
+            out.push_str(&format!("Include = {}\n", INCLUDE_PATH));
+            added = true;
+        }
+    }
+    added.then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_registers_include_once() {
+        let t = tempfile::tempdir().unwrap();
+        let root = t.path();
+        // Never pinned: nothing is written
+        sync(root, &[]).unwrap();
+        assert!(!root.join("etc/pacman.d").exists());
+
+        fs::create_dir_all(root.join("etc")).unwrap();
+        let conf =
+            "#\n[options]\nHoldPkg = pacman glibc\n\n[core]\nInclude = /etc/pacman.d/mirrorlist\n";
+        fs::write(root.join("etc/pacman.conf"), conf).unwrap();
+        sync(root, &["uutils-coreutils", "sudo-rs"]).unwrap();
+        sync(root, &["uutils-coreutils"]).unwrap();
+        let conf = fs::read_to_string(root.join("etc/pacman.conf")).unwrap();
+        assert_eq!(conf.matches(INCLUDE_PATH).count(), 1);
+        assert!(
+            conf.contains("[options]\nInclude = /etc/pacman.d/oxidizr-arch-ignore.conf\nHoldPkg")
+        );
+        let include =
+            fs::read_to_string(root.join("etc/pacman.d/oxidizr-arch-ignore.conf")).unwrap();
+        assert!(include.ends_with("IgnorePkg = uutils-coreutils\n"));
+
+        // Unpinning everything keeps an include without IgnorePkg
+        sync(root, &[]).unwrap();
+        let include =
+            fs::read_to_string(root.join("etc/pacman.d/oxidizr-arch-ignore.conf")).unwrap();
+        assert!(!include.contains("IgnorePkg"));
+    }
+}
@@ -1,3 +1,17 @@
 pub mod arch;
 pub mod arch_adapter;
+pub mod artifact_cache;
+pub mod aur;
+pub mod aur_build;
+pub mod ignore_pkg;
+pub mod mtree;
+pub mod pacman_cache;
+pub mod pacman_db;
+pub mod pkg_archive;
+pub mod pm;
+pub mod pm_fake;
 pub mod preflight;
+pub mod providers;
+pub mod revdeps;
+pub mod shim;
+pub mod smoke;
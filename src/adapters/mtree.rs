@@ -0,0 +1,273 @@
+//! Integrity check of installed files against the package's `mtree` in the local database
+//! (gzip-compressed, written by pacman at install time), like `pacman -Qkk` but limited to the
+//! given paths: type, permissions, size and SHA-256 are compared.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use serde::Serialize;
+
+use crate::adapters::pacman_db;
+use crate::state::sha256_file;
+use crate::util::paths::ensure_under_root;
+
+/// One `mtree` entry, with `/set` defaults applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// `file`, `dir` or `link`
+    pub kind: String,
+    pub mode: Option<u32>,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+}
+
+/// A checked path that does not match its entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Mismatch {
+    pub path: String,
+    pub problems: Vec<String>,
+}
+
+/// Entries of the package's mtree keyed by absolute path (`/usr/bin/ls`); package metadata
+/// (`/.PKGINFO`, `/.BUILDINFO`, ...) is skipped.
+pub fn parse(text: &str) -> BTreeMap<String, Entry> {
+    let mut defaults: BTreeMap<String, String> = BTreeMap::new();
+    let mut entries = BTreeMap::new();
+    for line in text.lines() {
+        let mut tokens = line.split_whitespace();
+        let Some(first) = tokens.next() else {
+            continue;
+        };
+        if first.starts_with('#') {
+            continue;
+        }
+        if first == "/set" {
+            for kv in tokens {
+                if let Some((k, v)) = kv.split_once('=') {
+                    defaults.insert(k.to_string(), v.to_string());
+                }
+            }
+            continue;
+        }
+        if first == "/unset" {
+            for k in tokens {
+                defaults.remove(k);
+            }
+            continue;
+        }
+        let path = unescape(first.strip_prefix('.').unwrap_or(first));
+        if !path.starts_with('/') || path.starts_with("/.") {
+            continue;
+        }
+        let mut keys = defaults.clone();
+        for kv in tokens {
+            if let Some((k, v)) = kv.split_once('=') {
+                keys.insert(k.to_string(), v.to_string());
+            }
+        }
+        entries.insert(
+            path,
+            Entry {
+                kind: keys
+                    .get("type")
+                    .cloned()
+                    .unwrap_or_else(|| "file".to_string()),
+                mode: keys
+                    .get("mode")
+                    .and_then(|m| u32::from_str_radix(m, 8).ok()),
+                size: keys.get("size").and_then(|s| s.parse().ok()),
+                sha256: keys.get("sha256digest").cloned(),
+            },
+        );
+    }
+    entries
+}
+
+/// mtree escapes special characters in paths as `\ooo` octal.
+fn unescape(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            if let Some(b) = bytes
+                .get(i + 1..i + 4)
+                .and_then(|o| std::str::from_utf8(o).ok())
+                .and_then(|o| u8::from_str_radix(o, 8).ok())
+            {
+                out.push(b);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Read the mtree of installed package `pkg`; `None` when the package or its mtree is missing.
+pub fn read(root: &Path, pkg: &str) -> Result<Option<BTreeMap<String, Entry>>, String> {
+    let Some(path) = pacman_db::open(root).mtree_path(pkg) else {
+        return Ok(None);
+    };
+    let Ok(f) = fs::File::open(&path) else {
+        return Ok(None);
+    };
+    let mut text = String::new();
+    GzDecoder::new(f)
+        .read_to_string(&mut text)
+        .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    Ok(Some(parse(&text)))
+}
+
+/// Problems of the file at `path` under `root` against `entry`, in `pacman -Qkk` terms.
+pub fn check(root: &Path, path: &str, entry: &Entry) -> Vec<String> {
+    let host = ensure_under_root(root, Path::new(path));
+    let Ok(meta) = fs::symlink_metadata(&host) else {
+        return vec!["No such file or directory".to_string()];
+    };
+    let found = if meta.file_type().is_symlink() {
+        "link"
+    } else if meta.is_dir() {
+        "dir"
+    } else {
+        "file"
+    };
+    if found != entry.kind {
+        return vec![format!(
+            "Type mismatch (expected {}, found {})",
+            entry.kind, found
+        )];
+    }
+    let mut problems = Vec::new();
+    let mode = meta.permissions().mode() & 0o7777;
+    if let Some(want) = entry.mode.filter(|m| found != "link" && *m != mode) {
+        problems.push(format!(
+            "Permissions mismatch (expected {:o}, found {:o})",
+            want, mode
+        ));
+    }
+    if found != "file" {
+        return problems;
+    }
+    if let Some(want) = entry.size.filter(|s| *s != meta.len()) {
+        problems.push(format!(
+            "Size mismatch (expected {}, found {})",
+            want,
+            meta.len()
+        ));
+    }
+    if let Some(want) = &entry.sha256 {
+        match sha256_file(&host) {
+            Ok(have) if have == *want => {}
+            Ok(_) => problems.push("SHA256 checksum mismatch".to_string()),
+            Err(e) => problems.push(e),
+        }
+    }
+    problems
+}
+
+/// Check `paths` against the mtree of `pkg`; paths the package does not ship are skipped.
+/// `None` when the package has no mtree to check against.
+pub fn verify(root: &Path, pkg: &str, paths: &[String]) -> Result<Option<Vec<Mismatch>>, String> {
+    let Some(entries) = read(root, pkg)? else {
+        return Ok(None);
+    };
+    Ok(Some(
+        paths
+            .iter()
+            .filter_map(|p| {
+                let entry = entries.get(p)?;
+                let problems = check(root, p, entry);
+                (!problems.is_empty()).then(|| Mismatch {
+                    path: p.clone(),
+                    problems,
+                })
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use sha2::{Digest, Sha256};
+
+    use crate::adapters::pacman_db::tests::write_pkg;
+
+    #[test]
+    fn test_verify_against_gzipped_mtree() {
+        let t = tempfile::tempdir().unwrap();
+        let root = t.path();
+        write_pkg(
+            root,
+            "coreutils",
+            "9.5-1",
+            "",
+            &["usr/bin/ls", "usr/bin/cat"],
+        );
+        let bin = root.join("usr/bin");
+        fs::create_dir_all(&bin).unwrap();
+        let digest = |b: &[u8]| {
+            Sha256::digest(b)
+                .iter()
+                .map(|x| format!("{:02x}", x))
+                .collect::<String>()
+        };
+        let mtree = format!(
+            "#mtree\n/set type=file uid=0 gid=0 mode=644\n\
+             ./.PKGINFO time=1 size=10 sha256digest=00\n\
+             ./usr time=1 type=dir mode=755\n\
+             ./usr/bin/ls time=1 mode=755 size=6 sha256digest={}\n\
+             ./usr/bin/cat time=1 mode=755 size=7 sha256digest={}\n\
+             ./usr/bin/my\\040tool time=1 size=0\n",
+            digest(b"gnu-ls"),
+            digest(b"gnu-cat")
+        );
+        let entries = parse(&mtree);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries["/usr/bin/my tool"].mode, Some(0o644));
+        assert_eq!(entries["/usr"].kind, "dir");
+
+        let db = root.join("var/lib/pacman/local/coreutils-9.5-1/mtree");
+        let mut gz = GzEncoder::new(fs::File::create(&db).unwrap(), Compression::default());
+        gz.write_all(mtree.as_bytes()).unwrap();
+        gz.finish().unwrap();
+        pacman_db::invalidate(root);
+
+        fs::write(bin.join("ls"), "gnu-ls").unwrap();
+        fs::set_permissions(bin.join("ls"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(bin.join("cat"), "gnu-tac").unwrap();
+        fs::set_permissions(bin.join("cat"), fs::Permissions::from_mode(0o700)).unwrap();
+        std::os::unix::fs::symlink("/usr/bin/ls", bin.join("touch")).unwrap();
+
+        let paths: Vec<String> = ["/usr/bin/ls", "/usr/bin/cat", "/usr/bin/touch"]
+            .map(String::from)
+            .to_vec();
+        let mismatches = verify(root, "coreutils", &paths).unwrap().unwrap();
+        assert_eq!(
+            mismatches,
+            [Mismatch {
+                path: "/usr/bin/cat".to_string(),
+                problems: vec![
+                    "Permissions mismatch (expected 755, found 700)".to_string(),
+                    "SHA256 checksum mismatch".to_string()
+                ],
+            }]
+        );
+        assert_eq!(
+            check(root, "/usr/bin/touch", &entries["/usr/bin/ls"]),
+            ["Type mismatch (expected file, found link)"]
+        );
+        assert_eq!(verify(root, "findutils", &paths).unwrap(), None);
+    }
+}
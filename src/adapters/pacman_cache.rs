@@ -0,0 +1,130 @@
+//! Lookup of package archives in pacman's package cache (`CacheDir`, default
+//! `/var/cache/pacman/pkg`) and local package directories, used to install an exact version
+//! without a network.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::util::paths::ensure_under_root;
+use crate::util::vercmp::{vercmp, version_matches};
+
+const DEFAULT_CACHE_DIR: &str = "/var/cache/pacman/pkg";
+
+/// Cache directories under `root`: every `CacheDir` in `<root>/etc/pacman.conf`, or the default.
+pub fn cache_dirs(root: &Path) -> Vec<PathBuf> {
+    let conf = fs::read_to_string(root.join("etc/pacman.conf")).unwrap_or_default();
+    let mut dirs: Vec<PathBuf> = conf
+        .lines()
+        .filter_map(|l| {
+            let (key, value) = l.split_once('=')?;
+            (key.trim() == "CacheDir").then(|| value.trim())
+        })
+        .flat_map(|v| v.split_whitespace())
+        .map(|d| ensure_under_root(root, Path::new(d)))
+        .collect();
+    if dirs.is_empty() {
+        dirs.push(ensure_under_root(root, Path::new(DEFAULT_CACHE_DIR)));
+    }
+    dirs
+}
+
+/// Cached archive for `name` at exactly `version` (`pkgver-pkgrel`), if any.
+pub fn cached_archive(root: &Path, name: &str, version: &str) -> Option<PathBuf> {
+    cache_dirs(root)
+        .iter()
+        .find_map(|dir| matching_archive(dir, name, version))
+        .map(|(path, _)| path)
+}
+
+/// Package name and version (`pkgver-pkgrel`, with any epoch) from an archive file name
+/// `<name>-<pkgver>-<pkgrel>-<arch>.pkg.tar.<ext>`; `None` for other files and signatures.
+pub fn archive_name_version(file_name: &str) -> Option<(String, String)> {
+    if file_name.ends_with(".sig") {
+        return None;
+    }
+    let (base, _) = file_name.split_once(".pkg.tar")?;
+    let parts: Vec<&str> = base.rsplitn(4, '-').collect();
+    let [_arch, rel, ver, name] = parts[..] else {
+        return None;
+    };
+    Some((name.to_string(), format!("{}-{}", ver, rel)))
+}
+
+/// Archives of `name` in `dir` with their versions.
+fn archives_of(dir: &Path, name: &str) -> Vec<(PathBuf, String)> {
+    let Ok(rd) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    rd.flatten()
+        .filter_map(|e| {
+            let (n, v) = archive_name_version(e.file_name().to_str()?)?;
+            (n == name).then(|| (e.path(), v))
+        })
+        .collect()
+}
+
+/// Newest archive of `name` in `dir`, by package version.
+pub fn newest_archive(dir: &Path, name: &str) -> Option<(PathBuf, String)> {
+    archives_of(dir, name)
+        .into_iter()
+        .max_by(|a, b| vercmp(&a.1, &b.1))
+}
+
+/// Newest archive of `name` in `dir` whose version matches `spec` (`pkgver` or
+/// `pkgver-pkgrel`).
+pub fn matching_archive(dir: &Path, name: &str, spec: &str) -> Option<(PathBuf, String)> {
+    archives_of(dir, name)
+        .into_iter()
+        .filter(|(_, v)| version_matches(v, spec))
+        .max_by(|a, b| vercmp(&a.1, &b.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_archive_matches_exact_version() {
+        let t = tempfile::tempdir().unwrap();
+        let root = t.path();
+        let dir = root.join("var/cache/pacman/pkg");
+        fs::create_dir_all(&dir).unwrap();
+        for f in [
+            "coreutils-9.4-3-x86_64.pkg.tar.zst",
+            "coreutils-9.4-3-x86_64.pkg.tar.zst.sig",
+            "coreutils-9.5-1-x86_64.pkg.tar.zst",
+            "coreutils-extra-9.4-3-x86_64.pkg.tar.zst",
+        ] {
+            fs::write(dir.join(f), b"").unwrap();
+        }
+        assert_eq!(
+            cached_archive(root, "coreutils", "9.4-3"),
+            Some(dir.join("coreutils-9.4-3-x86_64.pkg.tar.zst"))
+        );
+        assert_eq!(cached_archive(root, "coreutils", "9.3-1"), None);
+
+        // CacheDir from pacman.conf replaces the default
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::write(
+            root.join("etc/pacman.conf"),
+            "[options]\nCacheDir = /srv/pkgcache/\n",
+        )
+        .unwrap();
+        assert_eq!(cache_dirs(root), vec![root.join("srv/pkgcache/")]);
+        assert_eq!(cached_archive(root, "coreutils", "9.4-3"), None);
+
+        // Newest by version, not by file name
+        fs::write(dir.join("coreutils-9.10-1-x86_64.pkg.tar.zst"), b"").unwrap();
+        assert_eq!(
+            newest_archive(&dir, "coreutils"),
+            Some((
+                dir.join("coreutils-9.10-1-x86_64.pkg.tar.zst"),
+                "9.10-1".to_string()
+            ))
+        );
+        assert_eq!(
+            archive_name_version("coreutils-9.4-3-x86_64.pkg.tar.zst.sig"),
+            None
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Native reader for pacman's local database (`<root>/var/lib/pacman/local`).
+//!
+//! Parses each package's `desc` eagerly and its `files` list on first use; both are cached
+//! per root for the lifetime of the process. Call [`invalidate`] after running pacman so the
+//! next lookup re-reads the database.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+const LOCAL_DB_REL: &str = "var/lib/pacman/local";
+const SYNC_DB_REL: &str = "var/lib/pacman/sync";
+
+/// One installed package, as recorded in its `desc` file.
+#[derive(Debug, Clone)]
+pub struct LocalPackage {
+    pub version: String,
+    dir: PathBuf,
+    fields: HashMap<String, Vec<String>>,
+}
+
+impl LocalPackage {
+    /// Raw values of a `%SECTION%` from `desc` (e.g. `DEPENDS`, `PROVIDES`).
+    pub fn field(&self, key: &str) -> &[String] {
+        self.fields.get(key).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LocalDb {
+    packages: BTreeMap<String, LocalPackage>,
+    files: Mutex<HashMap<String, Arc<Vec<String>>>>,
+}
+
+impl LocalDb {
+    fn load(root: &Path) -> Self {
+        let mut packages = BTreeMap::new();
+        if let Ok(rd) = fs::read_dir(root.join(LOCAL_DB_REL)) {
+            for ent in rd.flatten() {
+                let dir = ent.path();
+                let Ok(s) = fs::read_to_string(dir.join("desc")) else {
+                    continue;
+                };
+                let fields = parse_sections(&s);
+                let name = fields.get("NAME").and_then(|v| v.first()).cloned();
+                let version = fields.get("VERSION").and_then(|v| v.first()).cloned();
+                if let (Some(name), Some(version)) = (name, version) {
+                    packages.insert(
+                        name,
+                        LocalPackage {
+                            version,
+                            dir,
+                            fields,
+                        },
+                    );
+                }
+            }
+        }
+        LocalDb {
+            packages,
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LocalPackage> {
+        self.packages.get(name)
+    }
+
+    pub fn is_installed(&self, name: &str) -> bool {
+        self.packages.contains_key(name)
+    }
+
+    /// All installed packages, keyed by name.
+    pub fn packages(&self) -> impl Iterator<Item = (&str, &LocalPackage)> {
+        self.packages.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// The package's gzip-compressed `mtree` file in the local database, if installed.
+    pub fn mtree_path(&self, name: &str) -> Option<PathBuf> {
+        self.packages.get(name).map(|p| p.dir.join("mtree"))
+    }
+
+    /// Absolute paths (relative to the target root, e.g. `/usr/bin/ls`) owned by `name`.
+    /// Directory entries keep their trailing slash, like `pacman -Ql`.
+    pub fn files(&self, name: &str) -> Option<Arc<Vec<String>>> {
+        let pkg = self.packages.get(name)?;
+        let mut cache = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(f) = cache.get(name) {
+            return Some(f.clone());
+        }
+        let s = fs::read_to_string(pkg.dir.join("files")).unwrap_or_default();
+        let list: Vec<String> = parse_sections(&s)
+            .remove("FILES")
+            .unwrap_or_default()
+            .into_iter()
+            .map(|p| format!("/{}", p))
+            .collect();
+        let list = Arc::new(list);
+        cache.insert(name.to_string(), list.clone());
+        Some(list)
+    }
+}
+
+/// Time since the most recent refresh of the sync databases (`<root>/var/lib/pacman/sync/*.db`),
+/// or `None` when there are none.
+pub fn sync_db_age(root: &Path) -> Option<Duration> {
+    let newest = fs::read_dir(root.join(SYNC_DB_REL))
+        .ok()?
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|x| x == "db"))
+        .filter_map(|e| e.metadata().ok()?.modified().ok())
+        .max()?;
+    Some(SystemTime::now().duration_since(newest).unwrap_or_default())
+}
+
+fn cache() -> &'static Mutex<HashMap<PathBuf, Arc<LocalDb>>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, Arc<LocalDb>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Open (or reuse) the parsed local database for `root`.
+pub fn open(root: &Path) -> Arc<LocalDb> {
+    let mut c = cache().lock().unwrap_or_else(|e| e.into_inner());
+    c.entry(root.to_path_buf())
+        .or_insert_with(|| Arc::new(LocalDb::load(root)))
+        .clone()
+}
+
+/// Drop the cached database for `root`; call after pacman mutates it.
+pub fn invalidate(root: &Path) {
+    let mut c = cache().lock().unwrap_or_else(|e| e.into_inner());
+    c.remove(root);
+}
+
+/// Parse the `%SECTION%`-delimited format shared by `desc` and `files`.
+fn parse_sections(s: &str) -> HashMap<String, Vec<String>> {
+    let mut out: HashMap<String, Vec<String>> = HashMap::new();
+    let mut current: Option<String> = None;
+    for line in s.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            current = None;
+            continue;
+        }
+        if line.len() > 2 && line.starts_with('%') && line.ends_with('%') {
+            let key = line.trim_matches('%').to_string();
+            out.entry(key.clone()).or_default();
+            current = Some(key);
+            continue;
+        }
+        if let Some(k) = &current {
+            out.entry(k.clone()).or_default().push(line.to_string());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Write a minimal local-db entry under `root` (shared with other adapter tests).
+    pub(crate) fn write_pkg(
+        root: &Path,
+        name: &str,
+        version: &str,
+        desc_extra: &str,
+        files: &[&str],
+    ) {
+        let dir = root
+            .join(LOCAL_DB_REL)
+            .join(format!("{}-{}", name, version));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("desc"),
+            format!(
+                "%NAME%\n{}\n\n%VERSION%\n{}\n\n{}",
+                name, version, desc_extra
+            ),
+        )
+        .unwrap();
+        let mut f = String::from("%FILES%\n");
+        for p in files {
+            f.push_str(p);
+            f.push('\n');
+        }
+        fs::write(dir.join("files"), f).unwrap();
+    }
+
+    #[test]
+    fn test_reads_desc_and_files() {
+        let t = tempfile::tempdir().unwrap();
+        write_pkg(
+            t.path(),
+            "coreutils",
+            "9.5-1",
+            "%DEPENDS%\nacl\nglibc>=2.38\n\n%PROVIDES%\nfoo=1\n",
+            &["usr/", "usr/bin/", "usr/bin/ls", "usr/bin/cat"],
+        );
+        let db = open(t.path());
+        let p = db.get("coreutils").expect("package parsed");
+        assert_eq!(p.version, "9.5-1");
+        assert_eq!(p.field("DEPENDS"), ["acl", "glibc>=2.38"]);
+        assert_eq!(p.field("PROVIDES"), ["foo=1"]);
+        assert!(p.field("REPLACES").is_empty());
+        let files = db.files("coreutils").unwrap();
+        assert!(files.contains(&"/usr/bin/ls".to_string()));
+        assert!(files.contains(&"/usr/bin/".to_string()));
+        assert!(!db.is_installed("findutils"));
+    }
+
+    #[test]
+    fn test_invalidate_rereads() {
+        let t = tempfile::tempdir().unwrap();
+        assert!(!open(t.path()).is_installed("sudo"));
+        write_pkg(t.path(), "sudo", "1.9.16-1", "", &["usr/bin/sudo"]);
+        assert!(
+            !open(t.path()).is_installed("sudo"),
+            "cached until invalidated"
+        );
+        invalidate(t.path());
+        assert!(open(t.path()).is_installed("sudo"));
+    }
+}
@@ -0,0 +1,206 @@
+//! Reading package archives (`.pkg.tar.zst`, `.pkg.tar.gz` or uncompressed) in-process, and
+//! extracting selected entries from them. Nothing here runs `tar`, `zstd` or any other tool, so
+//! it still works when the binaries being restored are themselves broken.
+
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+
+use crate::util::paths::ensure_under_root;
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// The tar stream of the archive at `path`, decompressed by its magic bytes.
+pub fn open(path: &Path) -> Result<tar::Archive<Box<dyn Read>>, String> {
+    let mut f = fs::File::open(path).map_err(|e| format!("cannot open {}: {e}", path.display()))?;
+    let mut magic = [0u8; 6];
+    let n = f.read(&mut magic).map_err(|e| e.to_string())?;
+    let head = io::Cursor::new(magic[..n].to_vec()).chain(f);
+    let stream: Box<dyn Read> = if magic.starts_with(&ZSTD_MAGIC) {
+        Box::new(
+            zstd::Decoder::new(head)
+                .map_err(|e| format!("cannot decompress {}: {e}", path.display()))?,
+        )
+    } else if magic.starts_with(&GZIP_MAGIC) {
+        Box::new(GzDecoder::new(head))
+    } else if magic == XZ_MAGIC {
+        return Err(format!(
+            "{}: xz-compressed packages are not supported",
+            path.display()
+        ));
+    } else {
+        Box::new(head)
+    };
+    Ok(tar::Archive::new(stream))
+}
+
+/// Extract the entries of `archive` at `paths` (absolute, e.g. `/usr/bin/ls`) under `root`
+/// with their recorded modes (and owners, when running as root), replacing whatever is there.
+/// Each file is written next to its target and renamed over it. Symlinks already pointing
+/// where the archive says are left alone. Returns the paths written.
+pub fn extract(root: &Path, archive: &Path, paths: &[String]) -> Result<Vec<String>, String> {
+    let as_root = fs::metadata("/proc/self").is_ok_and(|m| m.uid() == 0);
+    let mut ar = open(archive)?;
+    let entries = ar
+        .entries()
+        .map_err(|e| format!("cannot read {}: {e}", archive.display()))?;
+    let mut written = Vec::new();
+    for entry in entries {
+        let mut e = entry.map_err(|e| format!("cannot read {}: {e}", archive.display()))?;
+        let member = e
+            .path()
+            .map_err(|e| e.to_string())?
+            .to_string_lossy()
+            .trim_start_matches("./")
+            .to_string();
+        let abs = format!("/{}", member);
+        if !paths.contains(&abs) {
+            continue;
+        }
+        let target = ensure_under_root(root, Path::new(&abs));
+        let header = e.header().clone();
+        let kind = header.entry_type();
+        let tmp = staging_path(&target);
+        let _ = fs::remove_file(&tmp);
+        if kind.is_symlink() {
+            let dest = e
+                .link_name()
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("{}: symlink without a target", abs))?
+                .into_owned();
+            if fs::read_link(&target).is_ok_and(|d| d == dest) {
+                continue;
+            }
+            symlink(&dest, &tmp).map_err(|e| format!("cannot create {}: {e}", tmp.display()))?;
+        } else if kind.is_file() || kind.is_hard_link() {
+            if kind.is_hard_link() {
+                // Hard links name an earlier member; copy what was extracted for it
+                let other = e
+                    .link_name()
+                    .map_err(|e| e.to_string())?
+                    .ok_or_else(|| format!("{}: hard link without a target", abs))?;
+                let src = root.join(other.to_string_lossy().trim_start_matches("./"));
+                fs::copy(&src, &tmp).map_err(|e| {
+                    format!("cannot copy {} to {}: {e}", src.display(), tmp.display())
+                })?;
+            } else {
+                let mut out = fs::File::create(&tmp)
+                    .map_err(|e| format!("cannot create {}: {e}", tmp.display()))?;
+                io::copy(&mut e, &mut out)
+                    .map_err(|e| format!("cannot write {}: {e}", tmp.display()))?;
+            }
+            // Owner first: chown clears setuid/setgid bits
+            if as_root {
+                // Package files are root's unless the archive says otherwise
+                let uid = header.uid().unwrap_or(0) as u32;
+                let gid = header.gid().unwrap_or(0) as u32;
+                std::os::unix::fs::chown(&tmp, Some(uid), Some(gid))
+                    .map_err(|e| format!("cannot chown {}: {e}", tmp.display()))?;
+            }
+            let mode = header.mode().map_err(|e| e.to_string())? & 0o7777;
+            fs::set_permissions(&tmp, fs::Permissions::from_mode(mode))
+                .map_err(|e| format!("cannot chmod {}: {e}", tmp.display()))?;
+        } else {
+            continue;
+        }
+        fs::rename(&tmp, &target).map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            format!("cannot replace {}: {e}", target.display())
+        })?;
+        written.push(abs);
+    }
+    Ok(written)
+}
+
+/// Temporary sibling of `target` to extract into.
+fn staging_path(target: &Path) -> PathBuf {
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    target.with_file_name(format!(".{}.oxidizr-extract", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_archive(path: &Path, members: &[(&str, &[u8], u32)], link: Option<(&str, &str)>) {
+        let enc = zstd::Encoder::new(fs::File::create(path).unwrap(), 0).unwrap();
+        let mut ar = tar::Builder::new(enc);
+        for (member, data, mode) in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(*mode);
+            header.set_cksum();
+            ar.append_data(&mut header, member, *data).unwrap();
+        }
+        if let Some((member, dest)) = link {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            ar.append_link(&mut header, member, dest).unwrap();
+        }
+        ar.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_selected_entries_with_modes() {
+        let t = tempfile::tempdir().unwrap();
+        let root = t.path();
+        let archive = root.join("coreutils-9.5-1-x86_64.pkg.tar.zst");
+        write_archive(
+            &archive,
+            &[
+                (".PKGINFO", b"pkgname = coreutils\n", 0o644),
+                ("usr/bin/ls", b"gnu-ls", 0o755),
+                ("usr/bin/cat", b"gnu-cat", 0o755),
+                ("usr/bin/su", b"gnu-su", 0o4755),
+            ],
+            Some(("usr/bin/dir-link", "ls")),
+        );
+        let bin = root.join("usr/bin");
+        fs::create_dir_all(&bin).unwrap();
+        symlink("/opt/uutils/uutils", bin.join("ls")).unwrap();
+        symlink("ls", bin.join("dir-link")).unwrap();
+
+        let wanted: Vec<String> = ["/usr/bin/ls", "/usr/bin/su", "/usr/bin/dir-link"]
+            .map(String::from)
+            .to_vec();
+        let written = extract(root, &archive, &wanted).unwrap();
+        assert_eq!(written, ["/usr/bin/ls", "/usr/bin/su"]);
+        assert_eq!(fs::read_to_string(bin.join("ls")).unwrap(), "gnu-ls");
+        let meta = fs::symlink_metadata(bin.join("ls")).unwrap();
+        assert!(meta.is_file());
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o755);
+        assert_eq!(
+            fs::metadata(bin.join("su")).unwrap().permissions().mode() & 0o7777,
+            0o4755
+        );
+        assert!(!bin.join("cat").exists());
+        assert!(!bin.join(".ls.oxidizr-extract").exists());
+
+        // Uncompressed archives read the same way; xz is refused
+        let plain = root.join("plain.pkg.tar");
+        let mut ar = tar::Builder::new(fs::File::create(&plain).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(7);
+        header.set_mode(0o755);
+        header.set_cksum();
+        ar.append_data(&mut header, "usr/bin/cat", &b"gnu-cat"[..])
+            .unwrap();
+        ar.finish().unwrap();
+        assert_eq!(
+            extract(root, &plain, &["/usr/bin/cat".to_string()]).unwrap(),
+            ["/usr/bin/cat"]
+        );
+        let xz = root.join("x.pkg.tar.xz");
+        fs::write(&xz, XZ_MAGIC).unwrap();
+        assert!(open(&xz).err().unwrap().contains("xz-compressed"));
+    }
+}
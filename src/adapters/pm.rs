@@ -0,0 +1,484 @@
+//! Package-manager backend.
+//!
+//! Every package operation goes through [`PackageManager`]. [`Pacman`] runs `pacman` against
+//! the live root, or inside another root in chroot mode (`--chroot`: `pacman --sysroot <root>`,
+//! or `--root`/`--dbpath`/`--cachedir` under it), and answers queries from the local database;
+//! the scripted backend
+//! ([`ScriptedPm`]) replays a JSON script against the database under `--root`, so commit
+//! paths can be exercised hermetically. Select it with `OXI_PM_SCRIPT=<file>` or the hidden
+//! `--pm-script <file>` flag.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::adapters::artifact_cache::OWN_CACHE_REL;
+pub use crate::adapters::pm_fake::ScriptedPm;
+use crate::adapters::{arch, pacman_db};
+use crate::cli::args::ChrootMode;
+
+/// Environment variable selecting the scripted backend.
+pub const SCRIPT_ENV: &str = "OXI_PM_SCRIPT";
+
+#[derive(Debug, Default, Clone)]
+pub struct InstallOptions {
+    /// Answer "remove conflicting package?" with yes (`--ask=4`)
+    pub replace_conflicts: bool,
+    /// `--overwrite` globs for unowned files that would otherwise conflict
+    pub overwrite: Vec<String>,
+    /// Refresh the sync databases and upgrade the system in the same transaction (`-Syu`)
+    pub sync_upgrade: bool,
+}
+
+/// An installed package the sync databases offer a newer version of (`pacman -Qu`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Upgrade {
+    pub name: String,
+    pub installed: String,
+    pub available: String,
+}
+
+/// A package as offered by the sync repositories.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncInfo {
+    pub repo: String,
+    pub version: String,
+}
+
+pub trait PackageManager: Send + Sync {
+    /// Tool name reported in `pm.*` events.
+    fn tool(&self) -> &'static str;
+
+    /// Whether package operations apply to `root` (pacman only manages the live root).
+    fn manages(&self, root: &Path) -> bool;
+
+    /// Whether AUR helpers may be tried when a sync install fails.
+    fn supports_aur(&self) -> bool {
+        false
+    }
+
+    /// Install `names` from the sync repositories (`pacman -S`).
+    fn install(&self, root: &Path, names: &[&str], opts: &InstallOptions) -> Result<(), String>;
+
+    /// Install local package archives (`pacman -U`); `labels` name them in events.
+    fn install_files(
+        &self,
+        root: &Path,
+        archives: &[PathBuf],
+        labels: &[&str],
+        opts: &InstallOptions,
+    ) -> Result<(), String>;
+
+    /// Remove installed packages (`pacman -R`).
+    fn remove(&self, root: &Path, names: &[&str]) -> Result<(), String>;
+
+    /// Repository and version the sync databases offer for `name`.
+    fn sync_info(&self, root: &Path, name: &str) -> Option<SyncInfo>;
+
+    /// Installed packages the sync databases offer newer versions of (ignored ones excluded).
+    fn outdated(&self, root: &Path) -> Vec<Upgrade>;
+
+    /// Time since the sync databases were last refreshed; `None` when there are none.
+    fn sync_db_age(&self, root: &Path) -> Option<Duration> {
+        pacman_db::sync_db_age(root)
+    }
+
+    fn is_installed(&self, root: &Path, name: &str) -> bool {
+        pacman_db::open(root).is_installed(name)
+    }
+
+    /// Installed version (`pkgver-pkgrel`).
+    fn version(&self, root: &Path, name: &str) -> Option<String> {
+        pacman_db::open(root).get(name).map(|p| p.version.clone())
+    }
+
+    /// Files owned by `name`, as absolute paths inside the root.
+    fn list_files(&self, root: &Path, name: &str) -> Option<Arc<Vec<String>>> {
+        pacman_db::open(root).files(name)
+    }
+}
+
+static BACKEND: OnceLock<Box<dyn PackageManager>> = OnceLock::new();
+
+/// Select the backend for this process: the scripted one when `script` is given (refused for
+/// the live root), else pacman
+/// (run inside non-live roots with `chroot`).
+pub fn init(
+    root: &Path,
+    script: Option<PathBuf>,
+    chroot: Option<ChrootMode>,
+) -> Result<(), String> {
+    let backend: Box<dyn PackageManager> = match script {
+        Some(p) => Box::new(ScriptedPm::load(&p, root)?),
+        None => Box::new(Pacman { chroot }),
+    };
+    let _ = BACKEND.set(backend);
+    Ok(())
+}
+
+/// The selected backend (pacman unless [`init`] chose otherwise).
+pub fn get() -> &'static dyn PackageManager {
+    BACKEND
+        .get_or_init(|| Box::new(Pacman { chroot: None }))
+        .as_ref()
+}
+
+/// pacman argument vector for `op` (`-S`, `-U`, `-R`) on `targets`.
+pub(crate) fn pacman_args<S: AsRef<str>>(
+    op: &str,
+    targets: &[S],
+    opts: &InstallOptions,
+) -> Vec<String> {
+    let op = if op == "-S" && opts.sync_upgrade {
+        "-Syu"
+    } else {
+        op
+    };
+    let mut args = vec![op.to_string(), "--noconfirm".to_string()];
+    if opts.replace_conflicts {
+        args.push("--ask=4".to_string());
+    }
+    for glob in &opts.overwrite {
+        args.push("--overwrite".to_string());
+        args.push(glob.clone());
+    }
+    args.extend(targets.iter().map(|t| t.as_ref().to_string()));
+    args
+}
+
+/// Emit a `pm.install` / `pm.remove` event and turn a non-zero exit code into an error.
+/// `sysroot` is the root the operation ran inside, `None` for the live system.
+pub(crate) fn report(
+    event: &str,
+    tool: &str,
+    args: &[String],
+    packages: &[&str],
+    sysroot: Option<&Path>,
+    code: i32,
+    stderr: &str,
+) -> Result<(), String> {
+    eprintln!(
+        "{}",
+        json!({
+            "event": event,
+            // `package` keeps the single-package schema; `packages` lists every target
+            "pm":{"tool": tool,"args": args, "package": packages.join(" "), "packages": packages, "sysroot": sysroot},
+            "exit_code": code,
+            "stderr_tail": stderr.chars().rev().take(400).collect::<String>().chars().rev().collect::<String>()
+        })
+    );
+    if code != 0 {
+        return Err(format!(
+            "{} {} failed with exit code {}",
+            tool,
+            args.join(" "),
+            code
+        ));
+    }
+    Ok(())
+}
+
+/// The system package manager.
+pub struct Pacman {
+    /// How to run pacman inside a root other than `/`; `None` leaves such roots alone
+    pub chroot: Option<ChrootMode>,
+}
+
+impl Pacman {
+    /// The root pacman runs inside, when not the live system.
+    fn sysroot<'a>(&self, root: &'a Path) -> Option<&'a Path> {
+        (self.chroot.is_some() && root != Path::new("/")).then_some(root)
+    }
+
+    /// Options pointing pacman at `root` in chroot mode.
+    fn chroot_args(&self, root: &Path) -> Vec<String> {
+        let Some(sysroot) = self.sysroot(root) else {
+            return Vec::new();
+        };
+        let under = |rel: &str| sysroot.join(rel).display().to_string();
+        match self.chroot {
+            Some(ChrootMode::Sysroot) => vec!["--sysroot".into(), sysroot.display().to_string()],
+            _ => vec![
+                "--root".into(),
+                sysroot.display().to_string(),
+                "--dbpath".into(),
+                under("var/lib/pacman"),
+                "--cachedir".into(),
+                under("var/cache/pacman/pkg"),
+            ],
+        }
+    }
+
+    /// Archive path as pacman sees it: `--sysroot` chroots first, so archives outside the root
+    /// are copied into it.
+    fn archive_arg(&self, root: &Path, archive: &Path) -> Result<String, String> {
+        if self.chroot != Some(ChrootMode::Sysroot) || self.sysroot(root).is_none() {
+            return Ok(archive.display().to_string());
+        }
+        let inside = match archive.strip_prefix(root) {
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) => {
+                let name = archive
+                    .file_name()
+                    .ok_or_else(|| format!("not a package archive: {}", archive.display()))?;
+                let rel = Path::new(OWN_CACHE_REL).join(name);
+                let dest = root.join(&rel);
+                if let Some(parent) = dest.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| format!("cannot create {}: {e}", parent.display()))?;
+                }
+                std::fs::copy(archive, &dest).map_err(|e| {
+                    format!(
+                        "cannot copy {} to {}: {e}",
+                        archive.display(),
+                        dest.display()
+                    )
+                })?;
+                rel
+            }
+        };
+        Ok(Path::new("/").join(inside).display().to_string())
+    }
+
+    /// A `pacman` query against `root`.
+    fn query(&self, root: &Path, args: &[&str]) -> Option<std::process::Output> {
+        Command::new("pacman")
+            .args(self.chroot_args(root))
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+    }
+
+    fn run(
+        &self,
+        root: &Path,
+        event: &str,
+        args: &[String],
+        packages: &[&str],
+    ) -> Result<(), String> {
+        let mut full = self.chroot_args(root);
+        full.extend(args.iter().cloned());
+        let out = arch::unlocked(root, || {
+            Command::new("pacman")
+                .args(&full)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+        })?
+        .map_err(|e| format!("failed to spawn pacman: {e}"))?;
+        pacman_db::invalidate(root);
+        let code = out.status.code().unwrap_or(1);
+        report(
+            event,
+            self.tool(),
+            &full,
+            packages,
+            self.sysroot(root),
+            code,
+            &String::from_utf8_lossy(&out.stderr),
+        )
+    }
+}
+
+impl PackageManager for Pacman {
+    fn tool(&self) -> &'static str {
+        "pacman"
+    }
+
+    fn manages(&self, root: &Path) -> bool {
+        root == Path::new("/") || self.chroot.is_some()
+    }
+
+    fn supports_aur(&self) -> bool {
+        true
+    }
+
+    fn install(&self, root: &Path, names: &[&str], opts: &InstallOptions) -> Result<(), String> {
+        self.run(root, "pm.install", &pacman_args("-S", names, opts), names)
+    }
+
+    fn install_files(
+        &self,
+        root: &Path,
+        archives: &[PathBuf],
+        labels: &[&str],
+        opts: &InstallOptions,
+    ) -> Result<(), String> {
+        let paths = archives
+            .iter()
+            .map(|a| self.archive_arg(root, a))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.run(root, "pm.install", &pacman_args("-U", &paths, opts), labels)
+    }
+
+    fn remove(&self, root: &Path, names: &[&str]) -> Result<(), String> {
+        let args = pacman_args("-R", names, &InstallOptions::default());
+        self.run(root, "pm.remove", &args, names)
+    }
+
+    fn sync_info(&self, root: &Path, name: &str) -> Option<SyncInfo> {
+        let out = self.query(root, &["-Si", name])?;
+        if !out.status.success() {
+            return None;
+        }
+        parse_sync_info(&String::from_utf8_lossy(&out.stdout))
+    }
+
+    fn outdated(&self, root: &Path) -> Vec<Upgrade> {
+        // Exit code 1 just means nothing to upgrade
+        self.query(root, &["-Qu"])
+            .map(|out| parse_upgrades(&String::from_utf8_lossy(&out.stdout)))
+            .unwrap_or_default()
+    }
+}
+
+/// Parse `pacman -Qu` lines (`name old -> new`), skipping `[ignored]` packages.
+fn parse_upgrades(s: &str) -> Vec<Upgrade> {
+    s.lines()
+        .filter(|l| !l.ends_with("[ignored]"))
+        .filter_map(|l| match l.split_whitespace().collect::<Vec<_>>()[..] {
+            [name, installed, "->", available] => Some(Upgrade {
+                name: name.to_string(),
+                installed: installed.to_string(),
+                available: available.to_string(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Parse `Repository` and `Version` from `pacman -Si` output.
+fn parse_sync_info(s: &str) -> Option<SyncInfo> {
+    let field = |key: &str| {
+        s.lines().find_map(|l| {
+            let (k, v) = l.split_once(':')?;
+            (k.trim() == key).then(|| v.trim().to_string())
+        })
+    };
+    Some(SyncInfo {
+        repo: field("Repository")?,
+        version: field("Version")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacman_args_and_sync_info() {
+        let opts = InstallOptions {
+            replace_conflicts: true,
+            overwrite: vec!["/usr/bin/*".to_string()],
+            ..InstallOptions::default()
+        };
+        assert_eq!(
+            pacman_args("-S", &["coreutils"], &opts),
+            [
+                "-S",
+                "--noconfirm",
+                "--ask=4",
+                "--overwrite",
+                "/usr/bin/*",
+                "coreutils"
+            ]
+        );
+        let info = parse_sync_info(
+            "Repository      : core\nName            : coreutils\nVersion         : 9.5-1\n",
+        );
+        assert_eq!(
+            info,
+            Some(SyncInfo {
+                repo: "core".to_string(),
+                version: "9.5-1".to_string()
+            })
+        );
+
+        let upgrade = InstallOptions {
+            sync_upgrade: true,
+            ..InstallOptions::default()
+        };
+        assert_eq!(
+            pacman_args("-S", &["sudo-rs"], &upgrade),
+            ["-Syu", "--noconfirm", "sudo-rs"]
+        );
+        assert_eq!(
+            parse_upgrades(
+                "glibc 2.39-1 -> 2.40-1
+uutils-coreutils 0.2.2-1 -> 0.2.3-1 [ignored]
+"
+            ),
+            [Upgrade {
+                name: "glibc".to_string(),
+                installed: "2.39-1".to_string(),
+                available: "2.40-1".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_chroot_args_and_sysroot_archives() {
+        let t = tempfile::tempdir().unwrap();
+        let root = t.path();
+        let r = root.display();
+
+        let live = Pacman { chroot: None };
+        assert!(live.chroot_args(root).is_empty());
+        assert!(!live.manages(root));
+
+        let sysroot = Pacman {
+            chroot: Some(ChrootMode::Sysroot),
+        };
+        assert!(sysroot.manages(root));
+        assert!(sysroot.chroot_args(Path::new("/")).is_empty());
+        assert_eq!(
+            sysroot.chroot_args(root),
+            ["--sysroot".to_string(), r.to_string()]
+        );
+
+        let dirs = Pacman {
+            chroot: Some(ChrootMode::Root),
+        };
+        assert_eq!(
+            dirs.chroot_args(root),
+            [
+                "--root".to_string(),
+                r.to_string(),
+                "--dbpath".to_string(),
+                format!("{}/var/lib/pacman", r),
+                "--cachedir".to_string(),
+                format!("{}/var/cache/pacman/pkg", r),
+            ]
+        );
+
+        // Archives inside the root are addressed from within it; others are copied in first
+        let inside = root.join("var/cache/oxidizr-arch/shim-1-1-any.pkg.tar");
+        assert_eq!(
+            sysroot.archive_arg(root, &inside).unwrap(),
+            "/var/cache/oxidizr-arch/shim-1-1-any.pkg.tar"
+        );
+        let outside = tempfile::tempdir().unwrap();
+        let file = outside
+            .path()
+            .join("uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst");
+        std::fs::write(&file, b"pkg").unwrap();
+        assert_eq!(
+            sysroot.archive_arg(root, &file).unwrap(),
+            "/var/cache/oxidizr-arch/uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst"
+        );
+        assert!(root
+            .join("var/cache/oxidizr-arch/uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst")
+            .is_file());
+        assert_eq!(
+            dirs.archive_arg(root, &file).unwrap(),
+            file.display().to_string()
+        );
+    }
+}
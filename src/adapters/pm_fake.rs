@@ -0,0 +1,622 @@
+//! Scripted package-manager backend for hermetic tests (`OXI_PM_SCRIPT=<file>`).
+//!
+//! The script is a JSON file:
+//!
+//! ```json
+//! {
+//!   "sync": {
+//!     "uutils-coreutils": { "version": "0.2.2-1", "repo": "extra",
+//!                           "files": ["usr/bin/coreutils"], "provides": [], "conflicts": [],
+//!                           "contents": { "usr/bin/coreutils": "#!/bin/sh\necho ls cat\n" } }
+//!   },
+//!   "fail": [ { "op": "install", "package": "sudo-rs", "exit_code": 1, "stderr": "..." } ],
+//!   "aur": false
+//! }
+//! ```
+//!
+//! Operations change the pacman local database under the target root the way pacman would:
+//! installs write `desc`/`files`/`mtree` and create the listed files as executables (stubs
+//! unless `contents` has them; refusing unowned files unless an `--overwrite` glob matches),
+//! upgrades delete the files the new version no longer ships, conflicting packages are removed
+//! only with `--ask=4`, removals delete the entry and every file it owns. `-Syu` also upgrades
+//! every installed package the script offers a newer version of and marks the sync databases
+//! under the root as refreshed. Each operation is appended to `"log"` in the script, so
+//! scenarios can assert on what ran.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::adapters::pacman_cache::archive_name_version;
+use crate::adapters::pm::{pacman_args, report, InstallOptions, PackageManager, SyncInfo, Upgrade};
+use crate::adapters::{arch, pacman_db, pkg_archive};
+use crate::util::paths::ensure_under_root;
+use crate::util::vercmp::vercmp;
+
+const LOCAL_DB_REL: &str = "var/lib/pacman/local";
+const SYNC_DB_REL: &str = "var/lib/pacman/sync";
+const LOCK_REL: &str = "var/lib/pacman/db.lck";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SyncPackage {
+    version: String,
+    #[serde(default = "default_repo")]
+    repo: String,
+    #[serde(default)]
+    files: Vec<String>,
+    #[serde(default)]
+    provides: Vec<String>,
+    #[serde(default)]
+    conflicts: Vec<String>,
+    #[serde(default)]
+    depends: Vec<String>,
+    /// File contents by path; files without an entry are installed as stubs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    contents: BTreeMap<String, String>,
+}
+
+fn default_repo() -> String {
+    "extra".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FailRule {
+    /// `install` or `remove`
+    op: String,
+    package: String,
+    #[serde(default = "default_exit_code")]
+    exit_code: i32,
+    #[serde(default)]
+    stderr: String,
+}
+
+fn default_exit_code() -> i32 {
+    1
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Script {
+    #[serde(default)]
+    sync: BTreeMap<String, SyncPackage>,
+    #[serde(default)]
+    fail: Vec<FailRule>,
+    /// Let AUR fallbacks run after a failed sync install, as with pacman
+    #[serde(default)]
+    aur: bool,
+    #[serde(default)]
+    log: Vec<Value>,
+}
+
+/// A package about to be installed, from the sync script or an archive's `.PKGINFO`.
+#[derive(Debug)]
+struct Incoming {
+    name: String,
+    pkg: SyncPackage,
+    /// File contents from an archive; files without an entry are written as stubs
+    contents: BTreeMap<String, Vec<u8>>,
+}
+
+impl Incoming {
+    fn from_sync(name: &str, pkg: &SyncPackage) -> Self {
+        Incoming {
+            name: name.to_string(),
+            pkg: pkg.clone(),
+            contents: pkg
+                .contents
+                .iter()
+                .map(|(f, c)| {
+                    (
+                        f.trim_start_matches('/').to_string(),
+                        c.clone().into_bytes(),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+pub struct ScriptedPm {
+    path: PathBuf,
+}
+
+impl ScriptedPm {
+    /// Load the script for operations under `root`. The live root is refused, so a stray
+    /// `OXI_PM_SCRIPT` can never fake package operations on a real system.
+    pub fn load(path: &Path, root: &Path) -> Result<Self, String> {
+        if root == Path::new("/") {
+            return Err(format!(
+                "the scripted package manager ({}) only runs under a staging --root, not /",
+                path.display()
+            ));
+        }
+        let pm = ScriptedPm {
+            path: path.to_path_buf(),
+        };
+        pm.read()?;
+        Ok(pm)
+    }
+
+    fn read(&self) -> Result<Script, String> {
+        let s = fs::read_to_string(&self.path)
+            .map_err(|e| format!("cannot read pm script {}: {e}", self.path.display()))?;
+        serde_json::from_str(&s)
+            .map_err(|e| format!("invalid pm script {}: {e}", self.path.display()))
+    }
+
+    /// Run one scripted operation: honour `fail` rules, apply `f`, log and report it.
+    fn run<F>(
+        &self,
+        root: &Path,
+        op: &str,
+        event: &str,
+        args: Vec<String>,
+        packages: &[&str],
+        f: F,
+    ) -> Result<(), String>
+    where
+        F: FnOnce(&Script) -> Result<(), String>,
+    {
+        let mut script = self.read()?;
+        let scripted = script
+            .fail
+            .iter()
+            .find(|r| r.op == op && packages.contains(&r.package.as_str()))
+            .map(|r| (r.exit_code, r.stderr.clone()));
+        let (code, stderr) = match scripted {
+            Some(failure) => failure,
+            // Like pacman, refuse to run while someone holds the database lock
+            None => match arch::unlocked(root, || {
+                if root.join(LOCK_REL).exists() {
+                    return Err("failed to init transaction (unable to lock database)".into());
+                }
+                f(&script)
+            })? {
+                Ok(()) => (0, String::new()),
+                Err(e) => (1, format!("error: {e}")),
+            },
+        };
+        pacman_db::invalidate(root);
+        script
+            .log
+            .push(json!({"op": op, "args": args, "packages": packages, "exit_code": code}));
+        let out = serde_json::to_string_pretty(&script).map_err(|e| e.to_string())?;
+        fs::write(&self.path, out)
+            .map_err(|e| format!("cannot write pm script {}: {e}", self.path.display()))?;
+        let sysroot = (root != Path::new("/")).then_some(root);
+        report(event, self.tool(), &args, packages, sysroot, code, &stderr)
+    }
+}
+
+impl PackageManager for ScriptedPm {
+    fn tool(&self) -> &'static str {
+        "scripted"
+    }
+
+    fn manages(&self, root: &Path) -> bool {
+        root != Path::new("/")
+    }
+
+    fn supports_aur(&self) -> bool {
+        self.read().is_ok_and(|s| s.aur)
+    }
+
+    fn install(&self, root: &Path, names: &[&str], opts: &InstallOptions) -> Result<(), String> {
+        let args = pacman_args("-S", names, opts);
+        self.run(root, "install", "pm.install", args, names, |script| {
+            let mut incoming = Vec::new();
+            if opts.sync_upgrade {
+                refresh_sync_dbs(root, script)?;
+                for u in outdated(root, script) {
+                    incoming.push(Incoming::from_sync(&u.name, &script.sync[&u.name]));
+                }
+            }
+            for name in names {
+                let pkg = script
+                    .sync
+                    .get(*name)
+                    .ok_or_else(|| format!("target not found: {}", name))?;
+                incoming.push(Incoming::from_sync(name, pkg));
+            }
+            incoming.iter().try_for_each(|p| install_one(root, p, opts))
+        })
+    }
+
+    fn install_files(
+        &self,
+        root: &Path,
+        archives: &[PathBuf],
+        labels: &[&str],
+        opts: &InstallOptions,
+    ) -> Result<(), String> {
+        let paths: Vec<String> = archives.iter().map(|p| p.display().to_string()).collect();
+        let args = pacman_args("-U", &paths, opts);
+        self.run(root, "install", "pm.install", args, labels, |script| {
+            let mut incoming = Vec::new();
+            for a in archives {
+                incoming.push(read_archive(a, script)?);
+            }
+            incoming.iter().try_for_each(|p| install_one(root, p, opts))
+        })
+    }
+
+    fn remove(&self, root: &Path, names: &[&str]) -> Result<(), String> {
+        let args = pacman_args("-R", names, &InstallOptions::default());
+        self.run(root, "remove", "pm.remove", args, names, |_| {
+            for name in names {
+                if !pacman_db::open(root).is_installed(name) {
+                    return Err(format!("target not found: {}", name));
+                }
+            }
+            names.iter().try_for_each(|n| remove_one(root, n))
+        })
+    }
+
+    fn sync_info(&self, _root: &Path, name: &str) -> Option<SyncInfo> {
+        let script = self.read().ok()?;
+        script.sync.get(name).map(|p| SyncInfo {
+            repo: p.repo.clone(),
+            version: p.version.clone(),
+        })
+    }
+
+    fn outdated(&self, root: &Path) -> Vec<Upgrade> {
+        self.read()
+            .map(|script| outdated(root, &script))
+            .unwrap_or_default()
+    }
+}
+
+/// Installed packages the script offers newer versions of.
+fn outdated(root: &Path, script: &Script) -> Vec<Upgrade> {
+    let db = pacman_db::open(root);
+    db.packages()
+        .filter_map(|(name, local)| {
+            let offered = script.sync.get(name)?;
+            (vercmp(&offered.version, &local.version) == Ordering::Greater).then(|| Upgrade {
+                name: name.to_string(),
+                installed: local.version.clone(),
+                available: offered.version.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Touch `<repo>.db` under the root for every repository in the script, as `pacman -Sy` would.
+fn refresh_sync_dbs(root: &Path, script: &Script) -> Result<(), String> {
+    let dir = root.join(SYNC_DB_REL);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let repos: BTreeSet<&str> = script.sync.values().map(|p| p.repo.as_str()).collect();
+    for repo in repos {
+        let db = dir.join(format!("{}.db", repo));
+        fs::File::create(&db)
+            .and_then(|f| f.set_modified(SystemTime::now()))
+            .map_err(|e| format!("cannot refresh {}: {e}", db.display()))?;
+    }
+    Ok(())
+}
+
+/// Package metadata from an archive: `.PKGINFO` and the members of plain tars (our shims,
+/// test builds), otherwise the `<name>-<pkgver>-<pkgrel>-<arch>.pkg.tar.*` file name plus the
+/// sync entry for its files.
+fn read_archive(path: &Path, script: &Script) -> Result<Incoming, String> {
+    if let Ok(mut ar) = pkg_archive::open(path) {
+        let mut info = None;
+        let mut files = Vec::new();
+        let mut contents = BTreeMap::new();
+        if let Ok(entries) = ar.entries() {
+            for mut e in entries.flatten() {
+                let Ok(member) = e.path().map(|p| p.to_string_lossy().into_owned()) else {
+                    continue;
+                };
+                let member = member.trim_start_matches("./").to_string();
+                if member == ".PKGINFO" {
+                    let mut s = String::new();
+                    e.read_to_string(&mut s).map_err(|e| e.to_string())?;
+                    info = Some(s);
+                } else if member.starts_with('.') || member.is_empty() {
+                    continue;
+                } else if e.header().entry_type().is_dir() {
+                    files.push(format!("{}/", member.trim_end_matches('/')));
+                } else {
+                    let mut data = Vec::new();
+                    e.read_to_end(&mut data).map_err(|e| e.to_string())?;
+                    files.push(member.clone());
+                    contents.insert(member, data);
+                }
+            }
+        }
+        if let Some(info) = info {
+            let mut p = parse_pkginfo(&info);
+            p.pkg.files = files;
+            p.contents = contents;
+            return Ok(p);
+        }
+    }
+    let (name, version) = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(archive_name_version)
+        .ok_or_else(|| format!("not a package archive: {}", path.display()))?;
+    let mut pkg = script.sync.get(&name).cloned().unwrap_or_default();
+    pkg.version = version;
+    Ok(Incoming {
+        name,
+        pkg,
+        contents: BTreeMap::new(),
+    })
+}
+
+fn parse_pkginfo(info: &str) -> Incoming {
+    let mut name = String::new();
+    let mut pkg = SyncPackage::default();
+    for line in info.lines() {
+        let Some((k, v)) = line.split_once(" = ") else {
+            continue;
+        };
+        let v = v.to_string();
+        match k {
+            "pkgname" => name = v,
+            "pkgver" => pkg.version = v,
+            "provides" => pkg.provides.push(v),
+            "conflict" | "conflicts" => pkg.conflicts.push(v),
+            "depend" => pkg.depends.push(v),
+            _ => {}
+        }
+    }
+    Incoming {
+        name,
+        pkg,
+        contents: BTreeMap::new(),
+    }
+}
+
+/// Package name without a version constraint (`coreutils>=9` -> `coreutils`).
+fn bare(dep: &str) -> &str {
+    dep.split(['<', '>', '=']).next().unwrap_or(dep)
+}
+
+/// Minimal `--overwrite` glob: a trailing `*` matches any suffix.
+fn glob_matches(glob: &str, path: &str) -> bool {
+    match glob.strip_suffix('*') {
+        Some(prefix) => path.starts_with(prefix),
+        None => glob == path,
+    }
+}
+
+fn install_one(root: &Path, p: &Incoming, opts: &InstallOptions) -> Result<(), String> {
+    let db = pacman_db::open(root);
+    // Installed packages conflicting either way
+    let conflicting: Vec<String> = db
+        .packages()
+        .filter(|(n, inst)| {
+            *n != p.name
+                && (p.pkg.conflicts.iter().any(|c| bare(c) == *n)
+                    || inst.field("CONFLICTS").iter().any(|c| bare(c) == p.name))
+        })
+        .map(|(n, _)| n.to_string())
+        .collect();
+    if !conflicting.is_empty() {
+        if !opts.replace_conflicts {
+            return Err(format!(
+                "unresolvable package conflicts detected: {} and {}",
+                p.name,
+                conflicting.join(", ")
+            ));
+        }
+        for c in &conflicting {
+            remove_one(root, c)?;
+        }
+    }
+
+    // Files present on disk but owned by no other package conflict unless overwritten
+    let db = pacman_db::open(root);
+    let owned_elsewhere = |f: &str| {
+        db.packages()
+            .any(|(n, _)| n != p.name && db.files(n).is_some_and(|fs| fs.iter().any(|x| x == f)))
+    };
+    let owned_by_self = db
+        .files(&p.name)
+        .map(|f| f.as_ref().clone())
+        .unwrap_or_default();
+    for f in &p.pkg.files {
+        let abs = format!("/{}", f.trim_start_matches('/'));
+        if abs.ends_with('/') {
+            continue;
+        }
+        let host = ensure_under_root(root, Path::new(&abs));
+        if fs::symlink_metadata(&host).is_err() || owned_by_self.contains(&abs) {
+            continue;
+        }
+        if owned_elsewhere(&abs) {
+            return Err(format!(
+                "{}: {} exists in filesystem (owned by another package)",
+                p.name, abs
+            ));
+        }
+        if !opts.overwrite.iter().any(|g| glob_matches(g, &abs)) {
+            return Err(format!("{}: {} exists in filesystem", p.name, abs));
+        }
+    }
+    if db.is_installed(&p.name) {
+        // An upgrade deletes the files the new version no longer ships
+        for f in owned_by_self.iter().filter(|f| !f.ends_with('/')) {
+            if !p
+                .pkg
+                .files
+                .iter()
+                .any(|n| format!("/{}", n.trim_start_matches('/')) == *f)
+            {
+                let _ = fs::remove_file(ensure_under_root(root, Path::new(f)));
+            }
+        }
+        remove_entry(root, &p.name)?;
+    }
+
+    let mut mtree = String::from("#mtree\n/set type=file uid=0 gid=0 mode=644\n");
+    for f in &p.pkg.files {
+        let abs = format!("/{}", f.trim_start_matches('/'));
+        let host = ensure_under_root(root, Path::new(&abs));
+        if abs.ends_with('/') {
+            fs::create_dir_all(&host).map_err(|e| e.to_string())?;
+            mtree.push_str(&format!(
+                ".{} type=dir mode=755\n",
+                abs.trim_end_matches('/')
+            ));
+            continue;
+        }
+        if let Some(parent) = host.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let _ = fs::remove_file(&host);
+        let data = match p.contents.get(f.trim_start_matches('/')) {
+            Some(data) => data.clone(),
+            None => format!("#!/bin/sh\n# {} {}\nexit 0\n", p.name, abs).into_bytes(),
+        };
+        mtree.push_str(&format!(
+            ".{} mode=755 size={} sha256digest={}\n",
+            abs,
+            data.len(),
+            Sha256::digest(&data)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        ));
+        fs::write(&host, data).map_err(|e| e.to_string())?;
+        fs::set_permissions(&host, fs::Permissions::from_mode(0o755)).map_err(|e| e.to_string())?;
+    }
+
+    let dir = root
+        .join(LOCAL_DB_REL)
+        .join(format!("{}-{}", p.name, p.pkg.version));
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let mut desc = format!("%NAME%\n{}\n\n%VERSION%\n{}\n\n", p.name, p.pkg.version);
+    for (key, vals) in [
+        ("PROVIDES", &p.pkg.provides),
+        ("CONFLICTS", &p.pkg.conflicts),
+        ("DEPENDS", &p.pkg.depends),
+    ] {
+        if !vals.is_empty() {
+            desc.push_str(&format!("%{}%\n{}\n\n", key, vals.join("\n")));
+        }
+    }
+    fs::write(dir.join("desc"), desc).map_err(|e| e.to_string())?;
+    let mut files = String::from("%FILES%\n");
+    for f in &p.pkg.files {
+        files.push_str(f.trim_start_matches('/'));
+        files.push('\n');
+    }
+    fs::write(dir.join("files"), files).map_err(|e| e.to_string())?;
+    let mut gz = GzEncoder::new(
+        fs::File::create(dir.join("mtree")).map_err(|e| e.to_string())?,
+        Compression::default(),
+    );
+    gz.write_all(mtree.as_bytes())
+        .and_then(|_| gz.finish().map(|_| ()))
+        .map_err(|e| e.to_string())?;
+    pacman_db::invalidate(root);
+    Ok(())
+}
+
+/// Remove `name` and every non-directory file it owns (whatever is at the path now).
+fn remove_one(root: &Path, name: &str) -> Result<(), String> {
+    let files = pacman_db::open(root).files(name).unwrap_or_default();
+    for f in files.iter().filter(|f| !f.ends_with('/')) {
+        let _ = fs::remove_file(ensure_under_root(root, Path::new(f)));
+    }
+    remove_entry(root, name)
+}
+
+fn remove_entry(root: &Path, name: &str) -> Result<(), String> {
+    let db = pacman_db::open(root);
+    if let Some(p) = db.get(name) {
+        let dir = root
+            .join(LOCAL_DB_REL)
+            .join(format!("{}-{}", name, p.version));
+        fs::remove_dir_all(&dir).map_err(|e| format!("cannot remove {}: {e}", dir.display()))?;
+    }
+    pacman_db::invalidate(root);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::pacman_db::tests::write_pkg;
+
+    fn script(dir: &Path, body: &str) -> ScriptedPm {
+        let p = dir.join("pm.json");
+        fs::write(&p, body).unwrap();
+        ScriptedPm::load(&p, dir).unwrap()
+    }
+
+    #[test]
+    fn test_scripted_install_conflicts_and_failures() {
+        let t = tempfile::tempdir().unwrap();
+        let root = t.path().join("root");
+        write_pkg(&root, "coreutils", "9.5-1", "", &["usr/bin/ls"]);
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::write(root.join("usr/bin/ls"), b"gnu").unwrap();
+        let pm = script(
+            t.path(),
+            r#"{"sync":{
+                "uutils-coreutils":{"version":"0.2.2-1","files":["usr/bin/coreutils"]},
+                "gnu-rival":{"version":"1-1","files":["usr/bin/ls"],"conflicts":["coreutils"]}},
+              "fail":[{"op":"remove","package":"coreutils","exit_code":3}]}"#,
+        );
+
+        // Never the live root
+        assert!(ScriptedPm::load(&t.path().join("pm.json"), Path::new("/"))
+            .err()
+            .unwrap()
+            .contains("staging --root"));
+        assert!(!pm.manages(Path::new("/")));
+
+        pm.install(&root, &["uutils-coreutils"], &InstallOptions::default())
+            .unwrap();
+        assert_eq!(
+            pm.version(&root, "uutils-coreutils").as_deref(),
+            Some("0.2.2-1")
+        );
+        assert!(
+            fs::metadata(root.join("usr/bin/coreutils"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o111
+                != 0
+        );
+        assert!(pm
+            .install(&root, &["nope"], &InstallOptions::default())
+            .is_err());
+
+        // Conflicts need --ask=4; the conflicting package's files go with it
+        assert!(pm
+            .install(&root, &["gnu-rival"], &InstallOptions::default())
+            .is_err());
+        let ask = InstallOptions {
+            replace_conflicts: true,
+            ..InstallOptions::default()
+        };
+        pm.install(&root, &["gnu-rival"], &ask).unwrap();
+        assert!(!pm.is_installed(&root, "coreutils"));
+
+        // Scripted failure exit code
+        write_pkg(&root, "coreutils", "9.5-1", "", &[]);
+        pacman_db::invalidate(&root);
+        let err = pm.remove(&root, &["coreutils"]).unwrap_err();
+        assert!(err.ends_with("failed with exit code 3"), "{err}");
+        let log = pm.read().unwrap().log;
+        assert_eq!(log.len(), 5);
+        assert_eq!(log[4]["op"], "remove");
+    }
+}
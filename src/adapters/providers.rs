@@ -0,0 +1,209 @@
+//! Provider accounting for the availability invariant (REQ-PM-7/REQ-PM-8): there must always be
+//! at least one functional provider per package, before and after every package-manager step.
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::adapters::pacman_db;
+use crate::cli::args::Package;
+use crate::util::paths::ensure_under_root;
+
+/// Known provider package names for each `Package` (distro package first).
+pub fn provider_candidates(pkg: Package) -> &'static [&'static str] {
+    match pkg {
+        Package::Coreutils => &["coreutils", "uutils-coreutils"],
+        Package::Findutils => &["findutils", "uutils-findutils", "uutils-findutils-bin"],
+        Package::Sudo => &["sudo", "sudo-rs"],
+    }
+}
+
+/// Map a pacman package name back to the `Package` it provides, if any.
+pub fn package_for(name: &str) -> Option<Package> {
+    [Package::Coreutils, Package::Findutils, Package::Sudo]
+        .into_iter()
+        .find(|p| provider_candidates(*p).contains(&name))
+}
+
+#[derive(Debug, Default)]
+pub struct ProviderCount {
+    /// Installed packages that are, or declare `provides=`, the package
+    pub installed: Vec<String>,
+    /// Installed providers with at least one executable regular file under /usr/bin
+    pub functional: Vec<String>,
+}
+
+pub fn count_providers(root: &Path, pkg: Package) -> ProviderCount {
+    let db = pacman_db::open(root);
+    let distro = provider_candidates(pkg)[0];
+    let mut installed: Vec<String> = provider_candidates(pkg)
+        .iter()
+        .filter(|n| db.is_installed(n))
+        .map(|n| n.to_string())
+        .collect();
+    // Packages declaring provides=<distro> (e.g. a provides shim)
+    for (name, p) in db.packages() {
+        let declares = p
+            .field("PROVIDES")
+            .iter()
+            .any(|v| v.split(['=', '<', '>']).next() == Some(distro));
+        if declares && !installed.iter().any(|n| n == name) {
+            installed.push(name.to_string());
+        }
+    }
+    let functional = installed
+        .iter()
+        .filter(|n| has_executable(root, &db, n))
+        .cloned()
+        .collect();
+    ProviderCount {
+        installed,
+        functional,
+    }
+}
+
+fn has_executable(root: &Path, db: &pacman_db::LocalDb, name: &str) -> bool {
+    let Some(files) = db.files(name) else {
+        return false;
+    };
+    files
+        .iter()
+        .filter(|f| f.starts_with("/usr/bin/") && !f.ends_with('/'))
+        .any(|f| {
+            // Regular executable file: links we created over distro files do not count
+            std::fs::symlink_metadata(ensure_under_root(root, Path::new(f)))
+                .is_ok_and(|m| m.file_type().is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+}
+
+/// Pre-check: refuse removing `removing` when it would leave `pkg` without a functional
+/// provider. Emits a `pm.invariant` event with the decision.
+pub fn guard_removal(
+    root: &Path,
+    pkg: Package,
+    removing: &[&str],
+    dry_run: bool,
+) -> Result<(), String> {
+    let count = count_providers(root, pkg);
+    let remaining: Vec<String> = count
+        .functional
+        .iter()
+        .filter(|n| !removing.contains(&n.as_str()))
+        .cloned()
+        .collect();
+    let allow = !remaining.is_empty();
+    emit(
+        "pre",
+        pkg,
+        &format!("pacman -R {}", removing.join(" ")),
+        &count,
+        &remaining,
+        allow,
+        dry_run,
+    );
+    if allow {
+        return Ok(());
+    }
+    let msg = format!(
+        "pm invariant: removing {} would leave no functional {:?} provider (installed=[{}], functional=[{}])",
+        removing.join(" "),
+        pkg,
+        count.installed.join(", "),
+        count.functional.join(", ")
+    );
+    if dry_run {
+        eprintln!("[dry-run] {}", msg);
+        return Ok(());
+    }
+    Err(msg)
+}
+
+/// Post-check after a package-manager step: at least one functional provider must remain.
+pub fn check_after(root: &Path, pkg: Package, operation: &str) -> Result<(), String> {
+    pacman_db::invalidate(root);
+    let count = count_providers(root, pkg);
+    let ok = !count.functional.is_empty();
+    emit("post", pkg, operation, &count, &count.functional, ok, false);
+    if ok {
+        Ok(())
+    } else {
+        Err(format!(
+            "pm invariant violated after '{}': no functional {:?} provider remains (installed=[{}])",
+            operation,
+            pkg,
+            count.installed.join(", ")
+        ))
+    }
+}
+
+fn emit(
+    phase: &str,
+    pkg: Package,
+    operation: &str,
+    count: &ProviderCount,
+    remaining: &[String],
+    ok: bool,
+    dry_run: bool,
+) {
+    eprintln!(
+        "{}",
+        json!({
+            "event": "pm.invariant",
+            "phase": phase,
+            "package": format!("{:?}", pkg),
+            "operation": operation,
+            "installed": count.installed,
+            "functional": count.functional,
+            "remaining_functional": remaining,
+            "decision": if ok { "allow" } else { "refuse" },
+            "dry_run": dry_run,
+        })
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::pacman_db::tests::write_pkg;
+
+    fn exec_file(root: &Path, rel: &str) {
+        let p = root.join(rel);
+        std::fs::create_dir_all(p.parent().unwrap()).unwrap();
+        std::fs::write(&p, b"bin").unwrap();
+        std::fs::set_permissions(&p, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_refuses_removing_last_functional_provider() {
+        let t = tempfile::tempdir().unwrap();
+        write_pkg(t.path(), "coreutils", "9.5-1", "", &["usr/bin/ls"]);
+        exec_file(t.path(), "usr/bin/ls");
+        let count = count_providers(t.path(), Package::Coreutils);
+        assert_eq!(count.functional, vec!["coreutils"]);
+        assert!(guard_removal(t.path(), Package::Coreutils, &["coreutils"], false).is_err());
+        // Dry-run reports the refusal without failing
+        assert!(guard_removal(t.path(), Package::Coreutils, &["coreutils"], true).is_ok());
+    }
+
+    #[test]
+    fn test_allows_removal_when_replacement_is_functional() {
+        let t = tempfile::tempdir().unwrap();
+        write_pkg(t.path(), "coreutils", "9.5-1", "", &["usr/bin/ls"]);
+        write_pkg(
+            t.path(),
+            "uutils-coreutils",
+            "0.2.2-1",
+            "",
+            &["usr/bin/uu-ls"],
+        );
+        exec_file(t.path(), "usr/bin/uu-ls");
+        // GNU ls replaced by our link: coreutils is installed but not functional
+        std::os::unix::fs::symlink("uu-ls", t.path().join("usr/bin/ls")).unwrap();
+        let count = count_providers(t.path(), Package::Coreutils);
+        assert_eq!(count.installed, vec!["coreutils", "uutils-coreutils"]);
+        assert_eq!(count.functional, vec!["uutils-coreutils"]);
+        assert!(guard_removal(t.path(), Package::Coreutils, &["coreutils"], false).is_ok());
+        assert!(check_after(t.path(), Package::Coreutils, "pacman -R coreutils").is_ok());
+    }
+}
@@ -0,0 +1,252 @@
+//! Reverse-dependency impact of removing distro packages, read from the local database.
+//!
+//! For each package being removed, finds the installed packages whose `%DEPENDS%` or
+//! `%OPTDEPENDS%` name it (directly or through one of its `%PROVIDES%`), and which installed
+//! package or provides shim still satisfies each dependency afterwards. `%REPLACES%` is
+//! reported for context only: pacman honours it during sync upgrades, not for dependencies.
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::adapters::pacman_db;
+use crate::adapters::shim::ShimPackage;
+use crate::util::vercmp::vercmp;
+
+/// An installed package depending on a package being removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Dependent {
+    pub package: String,
+    /// The dependency as declared, e.g. `coreutils>=9`
+    pub depends: String,
+    pub optional: bool,
+    /// Packages (installed, or shims about to be installed) satisfying it after the removal
+    pub satisfied_by: Vec<String>,
+}
+
+impl Dependent {
+    /// A hard dependency nothing will satisfy.
+    pub fn blocks(&self) -> bool {
+        !self.optional && self.satisfied_by.is_empty()
+    }
+
+    /// One-line description for the command output.
+    pub fn describe(&self) -> String {
+        let kind = if self.optional {
+            "optionally depends on"
+        } else {
+            "depends on"
+        };
+        let by = if self.satisfied_by.is_empty() {
+            "unsatisfied".to_string()
+        } else {
+            format!("satisfied by {}", self.satisfied_by.join(", "))
+        };
+        format!("{} {} {} ({})", self.package, kind, self.depends, by)
+    }
+}
+
+/// What removing one package does to the packages depending on it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Impact {
+    pub package: String,
+    pub dependents: Vec<Dependent>,
+    /// Installed packages declaring `replaces=<package>`
+    pub replaced_by: Vec<String>,
+}
+
+impl Impact {
+    pub fn blocking(&self) -> impl Iterator<Item = &Dependent> {
+        self.dependents.iter().filter(|d| d.blocks())
+    }
+}
+
+/// A dependency string (`name`, `name>=1.2`, optdepends' `name: reason`) split into the
+/// name and an optional version constraint.
+fn parse_dep(dep: &str) -> (&str, Option<(&str, &str)>) {
+    let dep = strip_reason(dep);
+    for op in [">=", "<=", "=", ">", "<"] {
+        if let Some((name, ver)) = dep.split_once(op) {
+            return (name, Some((op, ver)));
+        }
+    }
+    (dep, None)
+}
+
+/// Whether a package or provision `name[=version]` satisfies `dep`, like pacman: versioned
+/// dependencies need a versioned provision.
+fn satisfies(provision: &str, dep: &str) -> bool {
+    let (name, version) = match provision.split_once('=') {
+        Some((n, v)) => (n, Some(v)),
+        None => (provision, None),
+    };
+    let (dep_name, constraint) = parse_dep(dep);
+    if name != dep_name {
+        return false;
+    }
+    let Some((op, want)) = constraint else {
+        return true;
+    };
+    let Some(have) = version else {
+        return false;
+    };
+    let ord = vercmp(have, want);
+    match op {
+        ">=" => ord != Ordering::Less,
+        "<=" => ord != Ordering::Greater,
+        ">" => ord == Ordering::Greater,
+        "<" => ord == Ordering::Less,
+        _ => ord == Ordering::Equal,
+    }
+}
+
+/// Impact of removing `removing` in one transaction that installs `shims`. Packages not
+/// installed are skipped.
+pub fn analyze(root: &Path, removing: &[&str], shims: &[ShimPackage]) -> Vec<Impact> {
+    let db = pacman_db::open(root);
+    // Everything that remains after the transaction, with what it provides
+    let mut remaining: Vec<(String, Vec<String>)> = db
+        .packages()
+        .filter(|(name, _)| !removing.contains(name))
+        .map(|(name, p)| {
+            let mut provisions = vec![format!("{}={}", name, p.version)];
+            provisions.extend(p.field("PROVIDES").iter().cloned());
+            (name.to_string(), provisions)
+        })
+        .collect();
+    remaining.extend(
+        shims
+            .iter()
+            .map(|s| (s.name.clone(), vec![s.provides.clone()])),
+    );
+
+    let mut impacts = Vec::new();
+    for target in removing {
+        let Some(pkg) = db.get(target) else {
+            continue;
+        };
+        let mut names = vec![target.to_string()];
+        names.extend(
+            pkg.field("PROVIDES")
+                .iter()
+                .map(|p| parse_dep(p).0.to_string()),
+        );
+
+        let mut dependents = Vec::new();
+        let mut replaced_by = Vec::new();
+        for (name, p) in db.packages() {
+            if removing.contains(&name) {
+                continue;
+            }
+            if p.field("REPLACES")
+                .iter()
+                .any(|r| parse_dep(r).0 == *target)
+            {
+                replaced_by.push(name.to_string());
+            }
+            let deps = p.field("DEPENDS").iter().map(|d| (d, false));
+            let optdeps = p.field("OPTDEPENDS").iter().map(|d| (d, true));
+            for (dep, optional) in deps.chain(optdeps) {
+                if !names.iter().any(|n| n == parse_dep(dep).0) {
+                    continue;
+                }
+                let satisfied_by = remaining
+                    .iter()
+                    .filter(|(_, provisions)| provisions.iter().any(|pr| satisfies(pr, dep)))
+                    .map(|(n, _)| n.clone())
+                    .collect();
+                dependents.push(Dependent {
+                    package: name.to_string(),
+                    depends: strip_reason(dep).to_string(),
+                    optional,
+                    satisfied_by,
+                });
+            }
+        }
+        impacts.push(Impact {
+            package: target.to_string(),
+            dependents,
+            replaced_by,
+        });
+    }
+    impacts
+}
+
+/// The dependency without an optdepends reason.
+fn strip_reason(dep: &str) -> &str {
+    dep.split_once(": ").map_or(dep, |(d, _)| d).trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::pacman_db::tests::write_pkg;
+
+    #[test]
+    fn test_dependents_satisfied_by_shim_or_blocking() {
+        let t = tempfile::tempdir().unwrap();
+        write_pkg(t.path(), "coreutils", "9.5-1", "", &[]);
+        write_pkg(
+            t.path(),
+            "base",
+            "3-2",
+            "%DEPENDS%\ncoreutils\nfindutils\n",
+            &[],
+        );
+        write_pkg(
+            t.path(),
+            "old-tool",
+            "1-1",
+            "%DEPENDS%\ncoreutils>=10\n",
+            &[],
+        );
+        write_pkg(
+            t.path(),
+            "ranger",
+            "1.9-1",
+            "%OPTDEPENDS%\ncoreutils<9: legacy stat output\n",
+            &[],
+        );
+        write_pkg(
+            t.path(),
+            "uutils-coreutils",
+            "0.2.2-1",
+            "%REPLACES%\ncoreutils-rs\n",
+            &[],
+        );
+        let shim = ShimPackage {
+            name: "oxidizr-coreutils-provides".to_string(),
+            version: "9.5-1".to_string(),
+            provides: "coreutils=9.5-1".to_string(),
+            conflicts: "coreutils".to_string(),
+            depends: "uutils-coreutils".to_string(),
+        };
+
+        let impacts = analyze(t.path(), &["coreutils", "findutils"], &[shim]);
+        assert_eq!(impacts.len(), 1, "findutils is not installed");
+        let impact = &impacts[0];
+        assert!(impact.replaced_by.is_empty());
+        let by_name = |n: &str| impact.dependents.iter().find(|d| d.package == n).unwrap();
+        assert_eq!(by_name("base").satisfied_by, ["oxidizr-coreutils-provides"]);
+        assert!(by_name("old-tool").blocks());
+        let ranger = by_name("ranger");
+        assert_eq!(ranger.depends, "coreutils<9");
+        assert!(ranger.optional && !ranger.blocks());
+        assert_eq!(
+            impact
+                .blocking()
+                .map(|d| d.package.as_str())
+                .collect::<Vec<_>>(),
+            ["old-tool"]
+        );
+
+        // Without the shim nothing satisfies base either
+        let impacts = analyze(t.path(), &["coreutils"], &[]);
+        assert_eq!(impacts[0].blocking().count(), 2);
+
+        assert!(satisfies("coreutils=9.5-1", "coreutils>=9"));
+        assert!(!satisfies("coreutils", "coreutils>=9"));
+        assert!(satisfies("coreutils", "coreutils"));
+    }
+}
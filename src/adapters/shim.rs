@@ -0,0 +1,142 @@
+//! Local "provides" shim packages (`oxidizr-<pkg>-provides`).
+//!
+//! `base` depends on `coreutils` and `findutils`, so the GNU packages cannot simply be removed.
+//! The shim declares `provides=<pkg>` and `conflicts=<pkg>` and depends on the replacement;
+//! installing it with `pacman -U` removes the GNU package in the same transaction while keeping
+//! the dependency graph satisfied. The archive is written directly (no makepkg).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::adapters::artifact_cache::OWN_CACHE_REL;
+use crate::adapters::{pacman_db, providers};
+use crate::cli::args::Package;
+
+#[derive(Debug, Clone)]
+pub struct ShimPackage {
+    pub name: String,
+    pub version: String,
+    pub provides: String,
+    pub conflicts: String,
+    pub depends: String,
+}
+
+impl ShimPackage {
+    /// Shim for `pkg`, versioned after the installed distro package so versioned
+    /// dependencies (e.g. `coreutils>=8`) remain satisfied.
+    pub fn for_package(root: &Path, pkg: Package) -> Self {
+        let db = pacman_db::open(root);
+        let candidates = providers::provider_candidates(pkg);
+        let distro = candidates[0];
+        // Depend on whichever replacement is actually installed
+        let depends = candidates[1..]
+            .iter()
+            .find(|n| db.is_installed(n))
+            .unwrap_or(&candidates[1]);
+        let version = db.get(distro).map(|p| p.version.clone());
+        ShimPackage {
+            name: Self::name_for(pkg),
+            version: version.clone().unwrap_or_else(|| "0-1".to_string()),
+            provides: match version {
+                Some(v) => format!("{}={}", distro, v),
+                None => distro.to_string(),
+            },
+            conflicts: distro.to_string(),
+            depends: depends.to_string(),
+        }
+    }
+
+    /// Shim package name for `pkg`, e.g. `oxidizr-coreutils-provides`.
+    pub fn name_for(pkg: Package) -> String {
+        format!(
+            "oxidizr-{}-provides",
+            providers::provider_candidates(pkg)[0]
+        )
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("{}-{}-any.pkg.tar", self.name, self.version)
+    }
+
+    /// Archive location under `<root>/var/cache/oxidizr-arch/`.
+    pub fn archive_path(&self, root: &Path) -> PathBuf {
+        root.join(OWN_CACHE_REL).join(self.file_name())
+    }
+
+    /// Contents of the `.PKGINFO` member.
+    pub fn pkginfo(&self, builddate: u64) -> String {
+        let mut s = String::new();
+        s.push_str("# Generated by oxidizr-arch\n");
+        s.push_str(&format!("pkgname = {}\n", self.name));
+        s.push_str(&format!("pkgbase = {}\n", self.name));
+        s.push_str(&format!("pkgver = {}\n", self.version));
+        s.push_str(&format!(
+            "pkgdesc = oxidizr-arch shim: {} provides {}\n",
+            self.depends, self.conflicts
+        ));
+        s.push_str("url = https://github.com/veighnsche/oxidizr-arch\n");
+        s.push_str(&format!("builddate = {}\n", builddate));
+        s.push_str("packager = oxidizr-arch\n");
+        s.push_str("size = 0\n");
+        s.push_str("arch = any\n");
+        s.push_str("license = Apache-2.0 OR MIT\n");
+        s.push_str(&format!("provides = {}\n", self.provides));
+        s.push_str(&format!("conflicts = {}\n", self.conflicts));
+        s.push_str(&format!("depend = {}\n", self.depends));
+        s
+    }
+
+    /// Write the package archive to [`Self::archive_path`] and return its path.
+    pub fn write_archive(&self, root: &Path) -> Result<PathBuf, String> {
+        let dir = root.join(OWN_CACHE_REL);
+        fs::create_dir_all(&dir).map_err(|e| format!("cannot create {}: {e}", dir.display()))?;
+        let path = self.archive_path(root);
+        let builddate = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let info = self.pkginfo(builddate);
+        let file =
+            fs::File::create(&path).map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+        let mut builder = tar::Builder::new(file);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(info.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(builddate);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, ".PKGINFO", info.as_bytes())
+            .and_then(|_| builder.finish())
+            .map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::pacman_db::tests::write_pkg;
+    use std::io::Read;
+
+    #[test]
+    fn test_shim_archive_has_pkginfo() {
+        let t = tempfile::tempdir().unwrap();
+        write_pkg(t.path(), "coreutils", "9.5-1", "", &["usr/bin/ls"]);
+        write_pkg(t.path(), "uutils-coreutils", "0.2.2-1", "", &[]);
+        let shim = ShimPackage::for_package(t.path(), Package::Coreutils);
+        assert_eq!(shim.name, "oxidizr-coreutils-provides");
+        assert_eq!(shim.provides, "coreutils=9.5-1");
+
+        let path = shim.write_archive(t.path()).unwrap();
+        assert!(path.ends_with("oxidizr-coreutils-provides-9.5-1-any.pkg.tar"));
+        let mut ar = tar::Archive::new(fs::File::open(&path).unwrap());
+        let mut entry = ar.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.path().unwrap().to_str(), Some(".PKGINFO"));
+        let mut info = String::new();
+        entry.read_to_string(&mut info).unwrap();
+        assert!(info.contains("provides = coreutils=9.5-1\n"));
+        assert!(info.contains("conflicts = coreutils\n"));
+        assert!(info.contains("depend = uutils-coreutils\n"));
+    }
+}
@@ -0,0 +1,261 @@
+//! Post-apply smoke suite (REQ-H1), run by Switchyard after a committed apply.
+//!
+//! On a live root each package gets a few real invocations through the linked names
+//! (`ls --version` via argv0 dispatch, `sort`/`tr` pipelines, `find -maxdepth 0`,
+//! `sudo --version` on sudo-rs). Staging roots are never executed; links are checked
+//! structurally instead. Any failure makes Switchyard roll back the plan when the policy
+//! asks for `auto_rollback`.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde_json::json;
+use switchyard::adapters::{SmokeFailure, SmokeTestRunner};
+use switchyard::types::{Action, Plan};
+
+#[derive(Debug)]
+pub struct ArchSmokeRunner {
+    root: PathBuf,
+}
+
+impl ArchSmokeRunner {
+    pub fn new(root: &Path) -> Self {
+        ArchSmokeRunner {
+            root: root.to_path_buf(),
+        }
+    }
+}
+
+/// A single smoke invocation: run `name` with `args`, feed `stdin`, compare trimmed stdout.
+struct SmokeCheck {
+    label: &'static str,
+    name: &'static str,
+    args: Vec<String>,
+    stdin: Option<&'static str>,
+    /// Expected trimmed stdout; `None` only requires non-empty output
+    expect: Option<String>,
+}
+
+/// Per-package suite, limited to the names present in the applied plan.
+fn suite(names: &BTreeMap<String, PathBuf>) -> Vec<SmokeCheck> {
+    let all = vec![
+        // coreutils
+        SmokeCheck {
+            label: "ls --version",
+            name: "ls",
+            args: vec!["--version".into()],
+            stdin: None,
+            expect: None,
+        },
+        SmokeCheck {
+            label: "printf 'b\\na\\n' | sort",
+            name: "sort",
+            args: vec![],
+            stdin: Some("b\na\n"),
+            expect: Some("a\nb".into()),
+        },
+        SmokeCheck {
+            label: "echo oxidizr | tr a-z A-Z",
+            name: "tr",
+            args: vec!["a-z".into(), "A-Z".into()],
+            stdin: Some("oxidizr\n"),
+            expect: Some("OXIDIZR".into()),
+        },
+        // findutils
+        SmokeCheck {
+            label: "find / -maxdepth 0",
+            name: "find",
+            args: vec!["/".into(), "-maxdepth".into(), "0".into()],
+            stdin: None,
+            expect: Some("/".into()),
+        },
+        // sudo
+        SmokeCheck {
+            label: "sudo-rs --version",
+            name: "sudo",
+            args: vec!["--version".into()],
+            stdin: None,
+            expect: None,
+        },
+    ];
+    all.into_iter()
+        .filter(|c| names.contains_key(c.name))
+        .collect()
+}
+
+fn run_check(bin: &Path, check: &SmokeCheck) -> Result<(), String> {
+    let mut cmd = Command::new(bin);
+    cmd.args(&check.args);
+    cmd.stdin(if check.stdin.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    });
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("cannot execute {}: {e}", bin.display()))?;
+    if let (Some(input), Some(mut sin)) = (check.stdin, child.stdin.take()) {
+        sin.write_all(input.as_bytes())
+            .map_err(|e| format!("cannot write stdin: {e}"))?;
+    }
+    let out = child
+        .wait_with_output()
+        .map_err(|e| format!("wait failed: {e}"))?;
+    if !out.status.success() {
+        return Err(format!("exit code {}", out.status.code().unwrap_or(-1)));
+    }
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let got = stdout.trim();
+    match &check.expect {
+        Some(want) if got != want => Err(format!("expected {:?}, got {:?}", want, got)),
+        None if got.is_empty() => Err("no output".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Execute the suite for the linked names; returns the failed check labels.
+fn run_suite(names: &BTreeMap<String, PathBuf>) -> Vec<String> {
+    let mut failed = Vec::new();
+    for check in suite(names) {
+        let bin = &names[check.name];
+        let res = run_check(bin, &check);
+        eprintln!(
+            "{}",
+            json!({
+                "event":"smoke.check",
+                "check": check.label,
+                "path": bin.display().to_string(),
+                "ok": res.is_ok(),
+                "error": res.as_ref().err(),
+            })
+        );
+        if res.is_err() {
+            failed.push(check.label.to_string());
+        }
+    }
+    failed
+}
+
+/// Staging roots: links resolve to their executable source; restored targets exist.
+fn structural_check(plan: &Plan) -> Vec<String> {
+    let mut failed = Vec::new();
+    for act in &plan.actions {
+        let (target, ok) = match act {
+            Action::EnsureSymlink { source, target } => {
+                let t = target.as_path();
+                let want = std::fs::canonicalize(source.as_path()).ok();
+                let got = std::fs::canonicalize(&t).ok();
+                let exec = std::fs::metadata(&t).is_ok_and(|m| m.permissions().mode() & 0o111 != 0);
+                let is_link =
+                    std::fs::symlink_metadata(&t).is_ok_and(|m| m.file_type().is_symlink());
+                (t, is_link && exec && want.is_some() && want == got)
+            }
+            Action::RestoreFromBackup { target } => {
+                let t = target.as_path();
+                let ok = std::fs::symlink_metadata(&t).is_ok();
+                (t, ok)
+            }
+        };
+        if !ok {
+            failed.push(target.display().to_string());
+        }
+    }
+    failed
+}
+
+/// Names (file names of targets) touched by the plan, mapped to their on-disk path.
+fn plan_names(plan: &Plan) -> BTreeMap<String, PathBuf> {
+    let mut names = BTreeMap::new();
+    for act in &plan.actions {
+        let target = match act {
+            Action::EnsureSymlink { target, .. } | Action::RestoreFromBackup { target } => {
+                target.as_path()
+            }
+        };
+        if let Some(n) = target.file_name().and_then(|n| n.to_str()) {
+            names.insert(n.to_string(), target.clone());
+        }
+    }
+    names
+}
+
+impl SmokeTestRunner for ArchSmokeRunner {
+    fn run(&self, plan: &Plan) -> Result<(), SmokeFailure> {
+        let live = self.root == Path::new("/");
+        let failed = if live {
+            run_suite(&plan_names(plan))
+        } else {
+            structural_check(plan)
+        };
+        eprintln!(
+            "{}",
+            json!({
+                "event":"smoke.result",
+                "mode": if live { "exec" } else { "structural" },
+                "ok": failed.is_empty(),
+                "failed": failed,
+            })
+        );
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(SmokeFailure)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use switchyard::types::safepath::SafePath;
+
+    #[test]
+    fn test_suite_runs_through_linked_names() {
+        let t = tempfile::tempdir().unwrap();
+        let mut names = BTreeMap::new();
+        for n in ["ls", "sort", "tr", "find"] {
+            let sys = Path::new("/usr/bin").join(n);
+            if !sys.exists() {
+                return;
+            }
+            let link = t.path().join(n);
+            std::os::unix::fs::symlink(&sys, &link).unwrap();
+            names.insert(n.to_string(), link);
+        }
+        assert!(run_suite(&names).is_empty());
+
+        // A name linked to the wrong tool fails its check
+        let bad = t.path().join("tr-bad");
+        std::os::unix::fs::symlink("/usr/bin/sort", &bad).unwrap();
+        names.insert("tr".to_string(), bad);
+        assert_eq!(run_suite(&names), vec!["echo oxidizr | tr a-z A-Z"]);
+    }
+
+    #[test]
+    fn test_structural_check_for_staging_roots() {
+        let t = tempfile::tempdir().unwrap();
+        let root = t.path();
+        std::fs::create_dir_all(root.join("usr/bin")).unwrap();
+        let src = root.join("usr/bin/uu-ls");
+        std::fs::write(&src, b"#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&src, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let dst = root.join("usr/bin/ls");
+        std::os::unix::fs::symlink(&src, &dst).unwrap();
+        let plan = Plan {
+            actions: vec![Action::EnsureSymlink {
+                source: SafePath::from_rooted(root, &src).unwrap(),
+                target: SafePath::from_rooted(root, &dst).unwrap(),
+            }],
+        };
+        let runner = ArchSmokeRunner::new(root);
+        assert!(runner.run(&plan).is_ok());
+
+        std::fs::remove_file(&src).unwrap();
+        assert!(runner.run(&plan).is_err());
+    }
+}
@@ -1,7 +1,9 @@
 use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Package {
     Coreutils,
     Findutils,
@@ -35,6 +37,44 @@ pub struct Cli {
     #[arg(long, global = true)]
     pub allow_missing: Option<String>,
 
+    /// Install replacements from the sync repositories even when the system is behind them or
+    /// the databases are stale (partial upgrade)
+    #[arg(
+        long,
+        global = true,
+        default_value_t = false,
+        conflicts_with = "sync_upgrade"
+    )]
+    pub allow_partial: bool,
+
+    /// Install replacements from the sync repositories with `pacman -Syu <pkg>`, upgrading the
+    /// system in the same transaction
+    #[arg(long, global = true, default_value_t = false)]
+    pub sync_upgrade: bool,
+
+    /// Run package operations inside a --root other than "/" (e.g. a pacstrapped image tree)
+    /// instead of skipping them: `--chroot` or `--chroot=sysroot` runs `pacman --sysroot <root>`, `--chroot=root` runs
+    /// `pacman --root <root> --dbpath <root>/var/lib/pacman --cachedir <root>/var/cache/pacman/pkg`
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "sysroot"
+    )]
+    pub chroot: Option<ChrootMode>,
+
+    /// Policy profile (production, container, ci, or one defined in policy.toml) or a path
+    /// to a policy file; auto-detected from the environment when omitted
+    #[arg(long, global = true, value_name = "NAME|PATH")]
+    pub policy: Option<String>,
+
+    /// Run package operations against a scripted fake backend (tests; also OXI_PM_SCRIPT)
+    #[arg(long, global = true, hide = true, value_name = "PATH")]
+    pub pm_script: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -52,6 +92,23 @@ pub enum Commands {
         /// Local artifact path when --offline (still validated)
         #[arg(long, value_name = "PATH")]
         use_local: Option<PathBuf>,
+        /// Install the replacement from this package archive (pacman -U)
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["offline", "use_local", "local_repo"])]
+        from_pkg: Option<PathBuf>,
+        /// Install the newest replacement archive from this local package directory (pacman -U)
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["offline", "use_local"])]
+        local_repo: Option<PathBuf>,
+        /// Install and pin this replacement version (pkgver or pkgver-pkgrel) from the package
+        /// cache or --local-repo; pinned packages are added to pacman's IgnorePkg
+        #[arg(
+            long = "version",
+            value_name = "VERSION",
+            conflicts_with_all = ["offline", "use_local", "from_pkg", "unpin"]
+        )]
+        pin_version: Option<String>,
+        /// Drop the version pin and install from the usual sources again
+        #[arg(long, default_value_t = false)]
+        unpin: bool,
     },
     /// Restore GNU/stock tools for a package (or all)
     Restore {
@@ -64,6 +121,9 @@ pub enum Commands {
         /// Keep RS packages installed but de-preferred
         #[arg(long, default_value_t = false)]
         keep_replacements: bool,
+        /// Keep the removed replacements' cached package files and AUR build directories
+        #[arg(long, default_value_t = false)]
+        keep_cache: bool,
     },
     /// Replace distro packages with Rust replacements (ensure install + safe swap + remove GNU)
     Replace {
@@ -74,6 +134,15 @@ pub enum Commands {
         #[arg(long, conflicts_with = "package")]
         all: bool,
     },
+    /// Upgrade active replacements and link applets added (or restore those dropped) since
+    Update {
+        /// Package to update
+        #[arg(value_enum)]
+        package: Option<Package>,
+        /// Update every active package
+        #[arg(long, conflicts_with = "package")]
+        all: bool,
+    },
     /// Report current active state
     Status {
         /// Output machine-readable JSON
@@ -86,6 +155,23 @@ pub enum Commands {
         #[arg(long, default_value_t = false)]
         json: bool,
     },
+    /// Check that managed links are intact (exit 0 = ok, 1 = error, 2 = drift)
+    Verify {
+        /// Package to verify; when omitted, verifies all managed packages
+        #[arg(value_enum)]
+        package: Option<Package>,
+        /// Re-link only the entries that drifted (dry-run unless --commit)
+        #[arg(long, default_value_t = false)]
+        repair: bool,
+        /// Output machine-readable JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Manage the pacman hook that keeps replacements active across upgrades
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
     /// Generate shell completions
     Completions {
         /// Shell to generate completions for
@@ -94,6 +180,15 @@ pub enum Commands {
     },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum HookAction {
+    /// Install the ALPM hook under <root>/etc/pacman.d/hooks/
+    Install,
+    /// Hook entry point: re-apply links for active packages after a pacman transaction
+    #[command(hide = true)]
+    PostTransaction,
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
 pub enum Shell {
     Bash,
@@ -101,6 +196,16 @@ pub enum Shell {
     Fish,
 }
 
+/// How pacman is pointed at a non-live root (`--chroot`).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+pub enum ChrootMode {
+    /// `pacman --sysroot <root>`: chroots first, using the root's pacman.conf, databases,
+    /// cache and hooks
+    Sysroot,
+    /// `pacman --root --dbpath --cachedir` under the root, with the host's pacman.conf
+    Root,
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
 pub enum ParityLevel {
     /// Critical Set only; SELinux Set required iff SELinux enabled
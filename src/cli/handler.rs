@@ -1,25 +1,42 @@
-use oxidizr_cli_core::api::build_api;
+use std::path::PathBuf;
+
 use oxidizr_cli_core::prompts::should_proceed;
+use serde_json::json;
+use switchyard::adapters::{FileLockManager, FsOwnershipOracle};
 use switchyard::logging::JsonlSink;
-use switchyard::policy::types::SmokePolicy;
 use switchyard::policy::Policy;
 use switchyard::types::ApplyMode;
 use switchyard::Switchyard;
 
-use crate::cli::args::{Cli, Commands};
+use crate::adapters::pm;
+use crate::adapters::smoke::ArchSmokeRunner;
+use crate::cli::args::{Cli, Commands, HookAction};
+use crate::commands::sync_guard::SyncPolicy;
+use crate::commands::use_install::PackageSource;
+use crate::policy::Profile;
 
 pub fn dispatch(cli: Cli) -> Result<(), String> {
-    // Default policy: conservative, disallow degraded EXDEV for built-ins
+    pm::init(
+        &cli.root,
+        cli.pm_script
+            .clone()
+            .or_else(|| std::env::var_os(pm::SCRIPT_ENV).map(PathBuf::from)),
+        cli.chroot,
+    )?;
+
+    // Start from the conservative coreutils preset; the profile decides which gates to relax
+    let profile = Profile::resolve(&cli.root, cli.policy.as_deref())?;
+    eprintln!(
+        "{}",
+        json!({
+            "event":"policy.profile",
+            "profile": profile.name,
+            "source": profile.source,
+            "knobs": profile.knobs,
+        })
+    );
     let mut policy = Policy::coreutils_switch_preset();
-    // Developer/container ergonomics: in our Arch container flows, we want to exercise
-    // link swaps without strict preflight STOP gates. Relax a few knobs safely:
-    // - Allow commit without a dedicated external lock manager (we still attach one below)
-    // - Skip preflight STOP gates to let apply proceed in the ephemeral container
-    // - Disable rescue requirement which may not be met in minimal images
-    policy.governance.allow_unlocked_commit = true;
-    policy.apply.override_preflight = true;
-    policy.rescue.require = false;
-    policy.governance.smoke = SmokePolicy::Off;
+    profile.apply(&mut policy);
 
     // Narrow scope to requested root and explicitly to its /usr/bin subtree
     policy.scope.allow_roots.push(cli.root.clone());
@@ -33,30 +50,58 @@ pub fn dispatch(cli: Cli) -> Result<(), String> {
         let _ = std::fs::create_dir_all(parent);
     }
 
-    let api: Switchyard<JsonlSink, JsonlSink> = build_api(policy, lock_path);
+    // Attach the smoke suite only when the profile requires it: Switchyard runs an attached
+    // runner even under SmokePolicy::Off, and reports a missing one under Require (REQ-H1/H3)
+    let mut builder = Switchyard::builder(JsonlSink, JsonlSink, policy)
+        .with_lock_manager(Box::new(FileLockManager::new(lock_path)))
+        .with_ownership_oracle(Box::new(FsOwnershipOracle));
+    if profile.knobs.smoke {
+        builder = builder.with_smoke_runner(Box::new(ArchSmokeRunner::new(&cli.root)));
+    }
+    let api: Switchyard<JsonlSink, JsonlSink> = builder.build();
 
     let apply_mode = if cli.commit {
         ApplyMode::Commit
     } else {
         ApplyMode::DryRun
     };
+    let sync = SyncPolicy {
+        allow_partial: cli.allow_partial,
+        sync_upgrade: cli.sync_upgrade,
+    };
 
     match cli.command {
         Commands::Use {
             package,
             offline,
             use_local,
+            from_pkg,
+            local_repo,
+            pin_version,
+            unpin,
         } => {
             if matches!(apply_mode, ApplyMode::Commit) && !should_proceed(cli.assume_yes, &cli.root)
             {
                 return Err("aborted by user".to_string());
             }
+            let source = match (from_pkg, local_repo, pin_version) {
+                (Some(file), _, _) => PackageSource::Archive(file),
+                (None, local_repo, Some(version)) => PackageSource::Pinned {
+                    version,
+                    local_repo,
+                },
+                (None, Some(dir), None) => PackageSource::LocalRepo(dir),
+                (None, None, None) => PackageSource::Repos,
+            };
             crate::commands::r#use::exec(
                 &api,
                 &cli.root,
                 package,
                 offline,
                 use_local,
+                source,
+                unpin,
+                sync,
                 apply_mode,
                 cli.require_parity,
                 cli.allow_missing.clone(),
@@ -66,6 +111,7 @@ pub fn dispatch(cli: Cli) -> Result<(), String> {
             package,
             all,
             keep_replacements,
+            keep_cache,
         } => {
             if matches!(apply_mode, ApplyMode::Commit) && !should_proceed(cli.assume_yes, &cli.root)
             {
@@ -77,12 +123,29 @@ pub fn dispatch(cli: Cli) -> Result<(), String> {
                 package,
                 all,
                 keep_replacements,
+                keep_cache,
                 apply_mode,
                 cli.assume_yes,
             )
         }
+        Commands::Update { package, all } => {
+            if matches!(apply_mode, ApplyMode::Commit) && !should_proceed(cli.assume_yes, &cli.root)
+            {
+                return Err("aborted by user".to_string());
+            }
+            crate::commands::update::exec(
+                &api,
+                &cli.root,
+                package,
+                all,
+                apply_mode,
+                cli.require_parity,
+                cli.allow_missing.clone(),
+                sync,
+            )
+        }
         Commands::Status { json } => crate::commands::status::exec(&cli.root, json),
-        Commands::Doctor { json } => crate::commands::doctor::exec(&cli.root, json),
+        Commands::Doctor { json } => crate::commands::doctor::exec(&cli.root, &profile, json),
         Commands::Replace { package, all } => crate::commands::replace::exec(
             &api,
             &cli.root,
@@ -91,7 +154,42 @@ pub fn dispatch(cli: Cli) -> Result<(), String> {
             apply_mode,
             cli.assume_yes,
             cli.require_parity,
+            sync,
         ),
+        Commands::Verify {
+            package,
+            repair,
+            json,
+        } => {
+            if repair
+                && matches!(apply_mode, ApplyMode::Commit)
+                && !should_proceed(cli.assume_yes, &cli.root)
+            {
+                return Err("aborted by user".to_string());
+            }
+            let clean =
+                crate::commands::verify::exec(&api, &cli.root, package, repair, apply_mode, json)?;
+            if !clean {
+                std::process::exit(crate::commands::verify::EXIT_DRIFT);
+            }
+            Ok(())
+        }
+        Commands::Hook { action } => match action {
+            HookAction::Install => {
+                if matches!(apply_mode, ApplyMode::Commit)
+                    && !should_proceed(cli.assume_yes, &cli.root)
+                {
+                    return Err("aborted by user".to_string());
+                }
+                crate::commands::hook::install(&cli.root, apply_mode)
+            }
+            HookAction::PostTransaction => crate::commands::hook::post_transaction(
+                &api,
+                &cli.root,
+                apply_mode,
+                cli.require_parity,
+            ),
+        },
         Commands::Completions { shell } => crate::cli::completions::emit(shell),
     }
 }
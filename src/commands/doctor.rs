@@ -2,9 +2,11 @@ use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 
+use crate::adapters::pm;
+use crate::commands::versions::{self, ReplacementVersions};
+use crate::policy::Profile;
 use crate::util::selinux::selinux_enabled;
 use serde::Serialize;
-use std::process::Command;
 
 #[derive(Serialize)]
 pub struct DoctorReport {
@@ -16,6 +18,9 @@ pub struct DoctorReport {
     tips: Vec<String>,
     selinux_enabled: bool,
     uutils_selinux_applets_present: Option<bool>,
+    policy_profile: String,
+    policy_source: String,
+    replacements: Vec<ReplacementVersions>,
 }
 
 fn detect_distro(root: &Path) -> (String, Option<String>) {
@@ -39,24 +44,14 @@ fn detect_distro(root: &Path) -> (String, Option<String>) {
 }
 
 fn check_locks(root: &Path) -> (bool, Vec<String>) {
-    use fs2::FileExt;
-    use std::fs::OpenOptions;
-    // pacman DB lock
+    // pacman DB lock: pacman creates db.lck for the length of a transaction, so its presence
+    // means a package manager (or a committing oxidizr-arch) is running
     let locks = ["/var/lib/pacman/db.lck"];
-    let mut held = vec![];
-    for l in locks {
-        let p = root.join(l.trim_start_matches('/'));
-        if !p.exists() {
-            continue;
-        }
-        if let Ok(f) = OpenOptions::new().read(true).write(true).open(&p) {
-            if f.try_lock_exclusive().is_err() {
-                held.push(l.to_string());
-            } else {
-                let _ = f.unlock();
-            }
-        }
-    }
+    let held: Vec<String> = locks
+        .iter()
+        .filter(|l| root.join(l.trim_start_matches('/')).exists())
+        .map(|l| l.to_string())
+        .collect();
     (!held.is_empty(), held)
 }
 
@@ -65,31 +60,21 @@ fn check_paths(root: &Path) -> bool {
 }
 
 fn uutils_selinux_applets_present(root: &Path) -> Option<bool> {
-    // Only meaningful on live root where pacman reflects current runtime
-    if root != Path::new("/") {
-        return None;
-    }
-    // Try pacman -Ql uutils-coreutils and look for uu-chcon/runcon
-    let out = Command::new("pacman")
-        .args(["-Ql", "uutils-coreutils"])
-        .output()
-        .ok()?;
-    if !out.status.success() {
-        return None;
-    }
-    let s = String::from_utf8_lossy(&out.stdout);
-    let has_chcon = s.contains("/usr/bin/uu-chcon");
-    let has_runcon = s.contains("/usr/bin/uu-runcon");
+    // Read the uutils-coreutils file list from the local pacman DB (live or --root tree)
+    let files = pm::get().list_files(root, "uutils-coreutils")?;
+    let has_chcon = files.iter().any(|f| f == "/usr/bin/uu-chcon");
+    let has_runcon = files.iter().any(|f| f == "/usr/bin/uu-runcon");
     Some(has_chcon && has_runcon)
 }
 
-pub fn exec(root: &Path, json: bool) -> Result<(), String> {
+pub fn exec(root: &Path, profile: &Profile, json: bool) -> Result<(), String> {
     let (distro_id, distro_version) = detect_distro(root);
     let (locks_present, locks) = check_locks(root);
     let paths_ok = check_paths(root);
     let mut tips = vec![];
     let selinux_on = selinux_enabled(root);
     let uutils_se = uutils_selinux_applets_present(root);
+    let (packages, replacements): (Vec<_>, Vec<_>) = versions::check(root).into_iter().unzip();
     if locks_present {
         tips.push(
             "Package manager busy (pacman lock detected); retry after current operation finishes."
@@ -106,10 +91,17 @@ pub fn exec(root: &Path, json: bool) -> Result<(), String> {
         match uutils_se {
             Some(false) => tips.push("SELinux enabled but uu-chcon/uu-runcon are not present in uutils; replace will be blocked. Consider a SELinux-enabled uutils build.".to_string()),
             Some(true) => {/* ok */}
-            None => tips.push("SELinux enabled; could not determine if uu-chcon/uu-runcon are present (non-live root or pacman unavailable).".to_string()),
+            None => tips.push("SELinux enabled; could not determine if uu-chcon/uu-runcon are present (uutils-coreutils not in the local pacman DB).".to_string()),
         }
     }
 
+    tips.extend(
+        packages
+            .iter()
+            .zip(&replacements)
+            .filter_map(|(p, r)| r.hint(*p)),
+    );
+
     if json {
         let rep = DoctorReport {
             distro_id,
@@ -120,6 +112,9 @@ pub fn exec(root: &Path, json: bool) -> Result<(), String> {
             tips,
             selinux_enabled: selinux_on,
             uutils_selinux_applets_present: uutils_se,
+            policy_profile: profile.name.clone(),
+            policy_source: profile.source.clone(),
+            replacements,
         };
         println!(
             "{}",
@@ -143,6 +138,24 @@ pub fn exec(root: &Path, json: bool) -> Result<(), String> {
             "Paths ok (usr/bin): {}",
             if paths_ok { "yes" } else { "no" }
         );
+        println!("Policy profile: {} ({})", profile.name, profile.source);
+        for r in &replacements {
+            let mut line = format!(
+                "Replacement {}: {}",
+                r.package,
+                r.installed.as_deref().unwrap_or("not installed")
+            );
+            if let Some(v) = &r.pinned {
+                line.push_str(&format!(" (pinned: {})", v));
+            }
+            if let Some(a) = &r.available {
+                line.push_str(&format!(" (available: {}/{})", a.repo, a.version));
+            }
+            if r.outdated {
+                line.push_str(" (outdated)");
+            }
+            println!("{}", line);
+        }
         if !tips.is_empty() {
             println!("Tips:");
             for t in &tips {
@@ -0,0 +1,138 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+use switchyard::logging::JsonlSink;
+use switchyard::types::ApplyMode;
+use switchyard::Switchyard;
+
+use crate::cli::args::{Package, ParityLevel};
+use crate::state::State;
+
+/// Hook file location relative to the target root.
+const HOOK_REL: &str = "etc/pacman.d/hooks/oxidizr-arch.hook";
+
+pub fn hook_path(root: &Path) -> PathBuf {
+    root.join(HOOK_REL)
+}
+
+/// ALPM hook that re-applies the selection whenever pacman reinstalls or upgrades
+/// a distro package whose files we replaced with links.
+pub fn hook_contents() -> String {
+    let mut s = String::new();
+    s.push_str(
+        "# Managed by oxidizr-arch; regenerate with 'oxidizr-arch --commit hook install'.\n",
+    );
+    s.push_str("[Trigger]\n");
+    s.push_str("Operation = Install\n");
+    s.push_str("Operation = Upgrade\n");
+    s.push_str("Type = Package\n");
+    for p in [Package::Coreutils, Package::Findutils, Package::Sudo] {
+        s.push_str(&format!("Target = {}\n", distro_pkg_name(p)));
+    }
+    s.push('\n');
+    s.push_str("[Action]\n");
+    s.push_str("Description = Re-applying oxidizr-arch replacement links...\n");
+    s.push_str("When = PostTransaction\n");
+    s.push_str("Exec = /usr/bin/oxidizr-arch --commit --assume-yes hook post-transaction\n");
+    s.push_str("NeedsTargets\n");
+    s
+}
+
+/// Write the ALPM hook under `<root>/etc/pacman.d/hooks/` (dry-run prints it).
+pub fn install(root: &Path, mode: ApplyMode) -> Result<(), String> {
+    let path = hook_path(root);
+    let contents = hook_contents();
+    if matches!(mode, ApplyMode::DryRun) {
+        eprintln!("[dry-run] would write {}:", path.display());
+        eprint!("{}", contents);
+        println!("[DRY-RUN] hook: would install {}", path.display());
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("cannot create {}: {e}", parent.display()))?;
+    }
+    std::fs::write(&path, contents).map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+    eprintln!(
+        "{}",
+        json!({
+            "event":"hook.install",
+            "path": path.display().to_string(),
+        })
+    );
+    println!("[OK] hook: installed {}", path.display());
+    Ok(())
+}
+
+/// PostTransaction entry point: re-run the `use` link plan for every active package whose
+/// distro package was part of the transaction. pacman holds its DB lock while hooks run,
+/// so this never invokes the package manager.
+pub fn post_transaction(
+    api: &Switchyard<JsonlSink, JsonlSink>,
+    root: &Path,
+    mode: ApplyMode,
+    parity: ParityLevel,
+) -> Result<(), String> {
+    let state = State::load(root)?;
+    let targets = read_targets();
+    let mut failures = Vec::new();
+    for (package, sel) in &state.active {
+        let name = distro_pkg_name(*package);
+        // Without NeedsTargets input (manual invocation), re-apply everything active
+        if !targets.is_empty() && !targets.iter().any(|t| t == name) {
+            continue;
+        }
+        let res = crate::commands::r#use::link(
+            api,
+            root,
+            *package,
+            sel.use_local.is_some(),
+            sel.use_local.clone(),
+            mode,
+            parity,
+            None,
+        );
+        eprintln!(
+            "{}",
+            json!({
+                "event":"hook.post_transaction",
+                "package": format!("{:?}", package),
+                "trigger": name,
+                "ok": res.is_ok(),
+                "error": res.as_ref().err(),
+            })
+        );
+        if let Err(e) = res {
+            failures.push(format!("{}: {}", name, e));
+        }
+    }
+    if !failures.is_empty() {
+        return Err(format!(
+            "hook: failed to re-apply replacements: {}",
+            failures.join("; ")
+        ));
+    }
+    Ok(())
+}
+
+/// Targets passed by pacman on stdin (`NeedsTargets`), one package name per line.
+fn read_targets() -> Vec<String> {
+    if atty::is(atty::Stream::Stdin) {
+        return Vec::new();
+    }
+    let mut buf = String::new();
+    let _ = std::io::stdin().read_to_string(&mut buf);
+    buf.lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+fn distro_pkg_name(pkg: Package) -> &'static str {
+    match pkg {
+        Package::Coreutils => "coreutils",
+        Package::Findutils => "findutils",
+        Package::Sudo => "sudo",
+    }
+}
@@ -1,14 +1,20 @@
 pub mod doctor;
+pub mod hook;
 pub mod replace;
 pub mod replace_parity;
 pub mod replace_utils;
 pub mod restore;
+pub mod restore_archive;
 pub mod status;
+pub mod sync_guard;
+pub mod transaction;
+pub mod update;
 pub mod use_cmd;
 pub mod use_install;
 pub mod use_link_planner;
 pub mod use_parity;
-pub mod use_post;
 pub mod use_utils;
+pub mod verify;
+pub mod versions;
 
 pub use use_cmd as r#use;
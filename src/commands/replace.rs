@@ -1,25 +1,39 @@
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::path::PathBuf;
 
+use serde_json::json;
 use switchyard::logging::JsonlSink;
-use switchyard::types::ApplyMode;
+use switchyard::types::{ApplyMode, PlanInput};
 use switchyard::Switchyard;
 
-use crate::adapters::arch::pm_lock_message;
+use crate::adapters::arch::DbLock;
 use crate::adapters::arch_adapter::ArchAdapter;
+use crate::adapters::pm;
+use crate::adapters::providers::provider_candidates;
+use crate::adapters::revdeps;
+use crate::adapters::shim::ShimPackage;
 use crate::cli::args::{Package, ParityLevel};
 use crate::commands::replace_parity::{enforce_replace_parity, filter_postverify_names};
 use crate::commands::replace_utils::{
-    guess_artifact_path, remove_distro_packages, resolve_source_bin, verify_link_points_to,
+    guess_artifact_path, remove_distro_packages, report_reverse_deps, verify_link_points_to,
 };
+use crate::commands::sync_guard::SyncPolicy;
+use crate::commands::transaction::{Step, Transaction};
+use crate::commands::use_cmd::{prepare, save_selection};
+use crate::commands::use_install::{describe_install_from, install_from, PackageSource};
+use crate::commands::use_utils::replacement_pkg_name;
+use crate::state::{drop_tombstone_backup, State};
 use crate::util::paths::ensure_under_root;
 use crate::util::selinux::selinux_enabled;
 use oxidizr_cli_core::dest_dir_path;
 use oxidizr_cli_core::DistroAdapter;
 use oxidizr_cli_core::PackageKind;
-use switchyard::types::safepath::SafePath;
 
+/// Replace the targets as one transaction: either every package is swapped or, on failure,
+/// everything done so far is rolled back.
 #[allow(unused_variables)]
+#[allow(clippy::too_many_arguments)]
 pub fn exec(
     api: &Switchyard<JsonlSink, JsonlSink>,
     root: &Path,
@@ -28,9 +42,8 @@ pub fn exec(
     mode: ApplyMode,
     assume_yes: bool,
     parity: ParityLevel,
+    sync: SyncPolicy,
 ) -> Result<(), String> {
-    let adapter = ArchAdapter;
-    let live_root = root == Path::new("/");
     let targets: Vec<Package> = if all {
         vec![Package::Coreutils, Package::Findutils, Package::Sudo]
     } else if let Some(p) = package {
@@ -39,49 +52,156 @@ pub fn exec(
         return Err("specify a package or use --all".to_string());
     };
 
-    if matches!(mode, ApplyMode::Commit) && live_root {
-        if let Some(msg) = pm_lock_message(root) {
-            return Err(msg);
-        }
-    }
+    // Hold the pacman database lock for the whole commit (released around our pacman runs)
+    let _lock = match mode {
+        ApplyMode::Commit => Some(DbLock::acquire(root)?),
+        ApplyMode::DryRun => None,
+    };
 
-    // For each target, first activate replacement via `use`, then enforce parity gates
-    for p in &targets {
-        let (offline, use_local) = if root != Path::new("/") {
-            if let Some(path) = guess_artifact_path(root, *p) {
-                (true, Some(path))
-            } else {
-                (false, None)
+    let mut tx = Transaction::new("replace", api, root);
+    run(&mut tx, api, root, &targets, mode, parity, sync).map_err(|e| tx.rollback(e))
+}
+
+/// The replace transaction: replacements installed, parity preflight for every target,
+/// one combined link plan, one pacman batch swapping the distro packages for their shims,
+/// then post-verify. Committed steps are recorded in `tx` so a failure undoes all of them.
+fn run(
+    tx: &mut Transaction,
+    api: &Switchyard<JsonlSink, JsonlSink>,
+    root: &Path,
+    targets: &[Package],
+    mode: ApplyMode,
+    parity: ParityLevel,
+    sync: SyncPolicy,
+) -> Result<(), String> {
+    let adapter = ArchAdapter;
+    let pm = pm::get();
+    let managed = pm.manages(root);
+    let commit = matches!(mode, ApplyMode::Commit);
+
+    // Replacement sources: staged artifacts under roots the package manager does not manage,
+    // installed packages otherwise
+    let sources: Vec<(Package, bool, Option<PathBuf>)> = targets
+        .iter()
+        .map(
+            |p| match guess_artifact_path(root, *p).filter(|_| !managed) {
+                Some(path) => (*p, true, Some(path)),
+                None => (*p, false, None),
+            },
+        )
+        .collect();
+
+    // Ensure every replacement is installed before anything is linked
+    for (p, offline, _) in &sources {
+        if *offline {
+            continue;
+        }
+        let rs_pkg = replacement_pkg_name(*p);
+        // Pinned replacements are installed at their pinned version
+        let source = PackageSource::Repos.with_pin(root, *p)?;
+        if commit {
+            let present = pm.is_installed(root, rs_pkg);
+            install_from(root, rs_pkg, &source, sync)?;
+            if managed && !present {
+                tx.record(Step::Installed(vec![rs_pkg.to_string()]));
             }
         } else {
-            (false, None)
-        };
+            eprintln!("{}", describe_install_from(root, rs_pkg, &source, sync)?);
+        }
+    }
+
+    // Packages depending on the distro packages must stay satisfied by what remains installed
+    // or by the provides shims
+    let distro_names: Vec<&str> = targets.iter().map(|p| provider_candidates(*p)[0]).collect();
+    let shims: Vec<ShimPackage> = targets
+        .iter()
+        .map(|p| ShimPackage::for_package(root, *p))
+        .collect();
+    let impacts = revdeps::analyze(root, &distro_names, &shims);
+    report_reverse_deps(&impacts, mode, managed)?;
 
-        // Ensure RS is installed & active using `use` semantics (no parity enforcement here)
-        crate::commands::r#use::exec(
-            api,
+    // Parity preflight for every target against its planned links, before any change
+    let effective_parity = if commit { parity } else { ParityLevel::Strict };
+    let mut prepared = Vec::new();
+    for ((p, offline, use_local), distro) in sources.iter().zip(&distro_names) {
+        let prep = prepare(root, *p, *offline, use_local.clone(), mode)?;
+        enforce_replace_parity(
+            &adapter,
             root,
             *p,
-            offline,
-            use_local.clone(),
-            mode,
-            parity,
-            None,
-        )?;
-
-        // Enforce parity gates (Replace semantics) and emit summaries
-        let effective_parity = if matches!(mode, ApplyMode::DryRun) {
-            ParityLevel::Strict
-        } else {
-            parity
-        };
-        let _ready =
-            enforce_replace_parity(&adapter, root, *p, effective_parity, offline, &use_local)?;
+            effective_parity,
+            *offline,
+            use_local,
+            &prep.linked_names(),
+        )?
+        .report(impacts.iter().find(|i| i.package == *distro));
+        prepared.push(prep);
     }
 
-    // Snapshot distro-provided names for post-verify (only for coreutils/findutils)
-    let mut verify_sets: Vec<(Package, Vec<String>, PathBuf)> = Vec::new();
-    for p in &targets {
+    // One combined Switchyard plan for all targets
+    let plan = api.plan(PlanInput {
+        link: prepared.iter().flat_map(|p| p.links.clone()).collect(),
+        restore: vec![],
+    });
+    let _pre = api
+        .preflight(&plan)
+        .map_err(|e| format!("preflight failed: {e:?}"))?;
+    match api.apply(&plan, mode) {
+        Ok(rep) if commit => {
+            if !rep.errors.is_empty() {
+                // Switchyard already rolled this plan back; earlier steps are undone by `tx`
+                eprintln!(
+                    "{}",
+                    json!({
+                        "event":"replace.exec.apply_failed",
+                        "errors": rep.errors,
+                        "rolled_back": rep.rolled_back,
+                        "rollback_errors": rep.rollback_errors,
+                    })
+                );
+                return Err(format!("apply failed: {}", rep.errors.join("; ")));
+            }
+            eprintln!(
+                "{}",
+                json!({
+                    "event":"replace.exec.apply_ok",
+                    "executed_actions": rep.executed.len()
+                })
+            );
+            tx.record(Step::Applied(rep));
+        }
+        Ok(_) => {
+            for prep in &prepared {
+                println!(
+                    "[DRY-RUN] replace {}: would link {} applet(s)",
+                    format!("{:?}", prep.package).to_lowercase(),
+                    prep.links.len()
+                );
+            }
+        }
+        Err(e) => return Err(format!("apply failed: {e:?}")),
+    }
+
+    if commit {
+        // Selections are saved before pacman runs so the hook re-links on reinstalls
+        tx.record(Step::State(State::load(root)?));
+        for ((p, offline, use_local), prep) in sources.iter().zip(&prepared) {
+            save_selection(root, *p, *offline, use_local.clone(), &prep.pairs())?;
+        }
+        if managed {
+            let mut state = State::load(root)?;
+            for p in targets {
+                state.active.entry(*p).or_default().replaced = true;
+            }
+            state.save(root)?;
+        }
+    }
+
+    // Snapshot distro-provided names for post-verify (only for coreutils/findutils), with the
+    // source each one was planned to link to
+    let mut verify_sets: Vec<(Package, Vec<String>, BTreeMap<String, PathBuf>)> = Vec::new();
+    for prep in &prepared {
+        let p = prep.package;
         if !matches!(p, Package::Sudo) {
             let kind = if matches!(p, Package::Coreutils) {
                 PackageKind::Coreutils
@@ -89,49 +209,70 @@ pub fn exec(
                 PackageKind::Findutils
             };
             let names_raw = adapter.enumerate_package_commands(root, kind);
-            let source_bin = if root != Path::new("/") {
-                guess_artifact_path(root, *p).unwrap_or_else(|| resolve_source_bin(*p))
-            } else {
-                resolve_source_bin(*p)
-            };
             // Filter names by parity policy for post-verify
-            let names = filter_postverify_names(names_raw, root, *p, parity);
-            verify_sets.push((*p, names, source_bin));
+            let names = filter_postverify_names(names_raw, root, p, parity);
+            let sources = prep
+                .pairs()
+                .into_iter()
+                .filter_map(|(src, dst)| {
+                    let name = dst.file_name()?.to_string_lossy().into_owned();
+                    Some((name, src))
+                })
+                .collect();
+            verify_sets.push((p, names, sources));
         }
     }
 
-    // Remove distro packages (commit) or preview (dry-run)
-    let (distro_names, _rs_names): (Vec<&str>, Vec<&str>) = targets
-        .iter()
-        .map(|p| match p {
-            Package::Coreutils => ("coreutils", "uutils-coreutils"),
-            Package::Findutils => ("findutils", "uutils-findutils"),
-            Package::Sudo => ("sudo", "sudo-rs"),
-        })
-        .unzip();
-
-    remove_distro_packages(root, live_root, mode, &distro_names)?;
+    // Remove distro packages in one pacman transaction (commit) or preview (dry-run)
+    remove_distro_packages(tx, root, mode, &distro_names)?;
+    if commit && managed {
+        // Removing the distro packages deleted the files they owned, our links included; link
+        // again and drop the "was absent" backups this takes, so restore and rollback still
+        // find the original files backed up by the first apply
+        let rep = api
+            .apply(&plan, mode)
+            .map_err(|e| format!("re-link after package removal failed: {e:?}"))?;
+        if !rep.errors.is_empty() {
+            return Err(format!(
+                "re-link after package removal failed: {}",
+                rep.errors.join("; ")
+            ));
+        }
+        let relinked: Vec<PathBuf> = prepared
+            .iter()
+            .flat_map(|p| p.pairs())
+            .map(|(_, target)| target)
+            .collect();
+        tx.record(Step::Relinked(relinked.clone()));
+        for target in &relinked {
+            drop_tombstone_backup(target)?;
+        }
+    }
 
     if matches!(mode, ApplyMode::Commit) {
-        // Post-verify: for each captured name, ensure it exists and resolves to replacement
+        // Post-verify: for each captured name, ensure it exists and resolves to replacement.
+        // A failure here rolls everything back, reinstalling the removed distro packages.
         let dest_dir = dest_dir_path();
-        for (pkg, names, src) in verify_sets {
+        for (pkg, names, sources) in verify_sets {
             if names.is_empty() {
                 continue;
             }
-            let src_sp = SafePath::from_rooted(root, &src)
-                .map_err(|e| format!("invalid source_bin: {e:?}"))?;
-            let src_path = src_sp.as_path().to_path_buf();
-            for app in names {
-                let dst = ensure_under_root(root, &dest_dir).join(&app);
-                let ok = verify_link_points_to(&dst, &src_path);
-                if !ok {
-                    return Err(format!(
-                        "post-verify failed for {:?}: {} does not point to replacement",
-                        pkg,
-                        dst.display()
-                    ));
-                }
+            let broken: Vec<String> = names
+                .into_iter()
+                .filter(|app| {
+                    let dst = ensure_under_root(root, &dest_dir).join(app);
+                    !sources
+                        .get(app)
+                        .is_some_and(|src| verify_link_points_to(&dst, src))
+                })
+                .collect();
+            if !broken.is_empty() {
+                return Err(format!(
+                    "post-verify failed for {:?}: {} link(s) do not point to the replacement: {}",
+                    pkg,
+                    broken.len(),
+                    broken.join(", ")
+                ));
             }
             println!("[OK] replace {:?}: post-verify links look good", pkg);
         }
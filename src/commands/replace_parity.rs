@@ -1,20 +1,52 @@
+use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
 
 use serde_json::json;
 
+use crate::adapters::revdeps::Impact;
 use crate::cli::args::{Package, ParityLevel};
-use crate::commands::replace_utils::{link_points_to_exec, resolve_source_bin};
+use crate::commands::replace_utils::resolve_source_bin;
 use crate::util::selinux::selinux_enabled;
 use oxidizr_cli_core::coverage2::coverage_preflight;
 use oxidizr_cli_core::packages::static_fallback_applets;
 use oxidizr_cli_core::{DistroAdapter, PackageKind};
 
-#[allow(dead_code)]
 pub struct ReplaceReady {
+    pub package: Package,
+    pub parity: ParityLevel,
     pub provider: &'static str,
     pub selinux_on: bool,
 }
 
+impl ReplaceReady {
+    /// Emit the `replace.exec.summary` event, with `revdeps` (the reverse-dependency report of
+    /// removing the distro package), and the `[READY]` line.
+    pub fn report(&self, revdeps: Option<&Impact>) {
+        eprintln!(
+            "{}",
+            json!({
+                "event":"replace.exec.summary",
+                "package": format!("{:?}", self.package),
+                "provider": self.provider,
+                "selinux_enabled": self.selinux_on,
+                "parity_threshold": format!("{:?}", self.parity),
+                "parity_ok": true,
+                "critical_missing": [],
+                "selinux_missing": [],
+                "reverse_dependencies": revdeps,
+            })
+        );
+        println!(
+            "[READY] replace {:?}: provider={} parity=OK ({})",
+            self.package,
+            self.provider,
+            format!("{:?}", self.parity).to_lowercase()
+        );
+    }
+}
+
+/// Parity gate for `replace`, evaluated before anything is applied: `linked` holds the applet
+/// names the planned links will point at an executable replacement.
 pub fn enforce_replace_parity<A: DistroAdapter>(
     adapter: &A,
     root: &Path,
@@ -22,6 +54,7 @@ pub fn enforce_replace_parity<A: DistroAdapter>(
     parity: ParityLevel,
     offline: bool,
     use_local: &Option<PathBuf>,
+    linked: &BTreeSet<String>,
 ) -> Result<ReplaceReady, String> {
     let provider = match pkg {
         Package::Sudo => "sudo-rs",
@@ -49,27 +82,9 @@ pub fn enforce_replace_parity<A: DistroAdapter>(
                 ));
             }
         }
-        // Summary
-        eprintln!(
-            "{}",
-            json!({
-                "event":"replace.exec.summary",
-                "package": format!("{:?}", pkg),
-                "provider": provider,
-                "selinux_enabled": selinux_on,
-                "parity_threshold": format!("{:?}", parity),
-                "parity_ok": true,
-                "critical_missing": [],
-                "selinux_missing": [],
-            })
-        );
-        println!(
-            "[READY] replace {:?}: provider={} parity=OK ({})",
-            pkg,
-            provider,
-            format!("{:?}", parity).to_lowercase()
-        );
         return Ok(ReplaceReady {
+            package: pkg,
+            parity,
             provider,
             selinux_on,
         });
@@ -87,7 +102,7 @@ pub fn enforce_replace_parity<A: DistroAdapter>(
 
     let mut crit_missing: Vec<String> = critical_set
         .iter()
-        .filter(|n| !link_points_to_exec(root, n))
+        .filter(|n| !linked.contains(*n))
         .cloned()
         .collect();
     crit_missing.sort();
@@ -95,7 +110,7 @@ pub fn enforce_replace_parity<A: DistroAdapter>(
 
     let mut se_missing: Vec<String> = selinux_set
         .iter()
-        .filter(|n| !link_points_to_exec(root, n))
+        .filter(|n| !linked.contains(*n))
         .cloned()
         .collect();
     se_missing.sort();
@@ -121,27 +136,9 @@ pub fn enforce_replace_parity<A: DistroAdapter>(
         ));
     }
 
-    eprintln!(
-        "{}",
-        json!({
-            "event":"replace.exec.summary",
-            "package": format!("{:?}", pkg),
-            "provider": provider,
-            "selinux_enabled": selinux_on,
-            "parity_threshold": format!("{:?}", parity),
-            "parity_ok": true,
-            "critical_missing": [],
-            "selinux_missing": [],
-        })
-    );
-    println!(
-        "[READY] replace {:?}: provider={} parity=OK ({})",
-        pkg,
-        provider,
-        format!("{:?}", parity).to_lowercase()
-    );
-
     Ok(ReplaceReady {
+        package: pkg,
+        parity,
         provider,
         selinux_on,
     })
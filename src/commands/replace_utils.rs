@@ -1,89 +1,179 @@
-use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 
+use crate::adapters::pm::{self, InstallOptions};
+use crate::adapters::providers;
+use crate::adapters::revdeps::Impact;
+use crate::adapters::shim::ShimPackage;
 use crate::cli::args::Package;
+use crate::commands::transaction::{RemovedPackage, Step, Transaction};
 use serde_json::json;
-use std::process::{Command, Stdio};
 use switchyard::types::ApplyMode;
 
-pub fn link_points_to_exec(root: &Path, name: &str) -> bool {
-    let link_path = root.join("usr/bin").join(name);
-    let md = match std::fs::symlink_metadata(&link_path) {
-        Ok(m) => m,
-        Err(_) => return false,
-    };
-    if !md.file_type().is_symlink() {
-        return false;
-    }
-    let tgt = match std::fs::read_link(&link_path) {
-        Ok(t) => t,
-        Err(_) => return false,
-    };
-    let abs = if tgt.is_absolute() {
-        tgt
-    } else {
-        link_path.parent().unwrap_or(Path::new("/")).join(tgt)
-    };
-    match std::fs::metadata(&abs) {
-        Ok(m) => m.permissions().mode() & 0o111 != 0,
-        Err(_) => false,
-    }
-}
-
+/// Remove the distro packages by installing a local `oxidizr-<pkg>-provides` shim that
+/// conflicts with each of them, so pacman swaps them all out in one transaction and packages
+/// depending on them (e.g. `base`) stay satisfied.
 pub fn remove_distro_packages(
+    tx: &mut Transaction,
     root: &Path,
-    live_root: bool,
     mode: ApplyMode,
     distro_names: &[&str],
 ) -> Result<(), String> {
     if matches!(mode, ApplyMode::Commit) {
-        if !live_root {
+        if !pm::get().manages(root) {
             eprintln!(
-                "[info] skipping pacman removals under non-live root: {}",
+                "[info] skipping pacman removals under non-live root: {} (pass --chroot to run pacman inside it)",
                 root.display()
             );
         } else {
+            let mut batch = Vec::new();
             for name in distro_names {
-                let mut cmd = Command::new("pacman");
-                let args = vec![
-                    "-R".to_string(),
-                    "--noconfirm".to_string(),
-                    (*name).to_string(),
-                ];
-                let args_view = args.clone();
-                cmd.args(&args);
-                cmd.stdin(Stdio::null());
-                cmd.stdout(Stdio::piped());
-                cmd.stderr(Stdio::piped());
-                let out = cmd
-                    .output()
-                    .map_err(|e| format!("failed to spawn pacman: {e}"))?;
-                let code = out.status.code().unwrap_or(1);
-                let stderr_tail = String::from_utf8_lossy(&out.stderr);
-                eprintln!(
-                    "{}",
-                    json!({
-                        "event":"pm.remove","pm":{"tool":"pacman","args": args_view, "package": name},
-                        "exit_code": code,
-                        "stderr_tail": stderr_tail.chars().rev().take(400).collect::<String>().chars().rev().collect::<String>()
-                    })
-                );
-                if code != 0 {
-                    return Err(format!("pacman -R {} failed with exit code {}", name, code));
-                } else {
-                    println!("[OK] replace: removed package {}", name);
+                let Some(pkg) = providers::package_for(name) else {
+                    continue;
+                };
+                providers::guard_removal(root, pkg, &[name], false)?;
+                batch.push((*name, pkg));
+            }
+            if batch.is_empty() {
+                return Ok(());
+            }
+            let mut archives = Vec::new();
+            let mut shims = Vec::new();
+            for (_, pkg) in &batch {
+                let shim = ShimPackage::for_package(root, *pkg);
+                let shim_path = shim.write_archive(root)?;
+                emit_shim_event(&shim, &shim_path, false);
+                archives.push(shim_path);
+                shims.push(shim);
+            }
+            let names: Vec<&str> = batch.iter().map(|(n, _)| *n).collect();
+            let removed: Vec<RemovedPackage> = names
+                .iter()
+                .map(|n| RemovedPackage::capture(root, n))
+                .collect();
+            let labels: Vec<&str> = shims.iter().map(|s| s.name.as_str()).collect();
+            // Answer "remove conflicting package?" with yes under --noconfirm (--ask=4)
+            let opts = InstallOptions {
+                replace_conflicts: true,
+                ..InstallOptions::default()
+            };
+            pm::get().install_files(root, &archives, &labels, &opts)?;
+            tx.record(Step::Shimmed(removed));
+            for ((name, pkg), shim) in batch.iter().zip(&shims) {
+                if pm::get().is_installed(root, name) {
+                    return Err(format!(
+                        "pacman -U {} did not remove {}",
+                        shim.file_name(),
+                        name
+                    ));
                 }
+                providers::check_after(
+                    root,
+                    *pkg,
+                    &format!("pacman -U {} (replacing {})", shim.file_name(), name),
+                )?;
+                println!(
+                    "[OK] replace: removed package {} (provided by {})",
+                    name, shim.name
+                );
             }
         }
     } else {
         for name in distro_names {
-            eprintln!("[dry-run] would run: pacman -R --noconfirm {}", name);
-            println!("[DRY-RUN] replace: would remove package {}", name);
+            let Some(pkg) = providers::package_for(name) else {
+                continue;
+            };
+            providers::guard_removal(root, pkg, &[name], true)?;
+            let shim = ShimPackage::for_package(root, pkg);
+            let shim_path = shim.archive_path(root);
+            emit_shim_event(&shim, &shim_path, true);
+            eprintln!(
+                "[dry-run] would write {} with .PKGINFO:",
+                shim_path.display()
+            );
+            eprint!("{}", shim.pkginfo(0));
+            eprintln!(
+                "[dry-run] would run: pacman -U --noconfirm --ask=4 {}",
+                shim_path.display()
+            );
+            println!(
+                "[DRY-RUN] replace: would remove package {} (provided by {})",
+                name, shim.name
+            );
         }
     }
     Ok(())
 }
 
+/// Report every package depending on the distro packages about to be removed. A hard
+/// dependency left unsatisfied refuses the removal when `enforce` (the package manager
+/// manages the root); dry-run reports the refusal instead.
+pub fn report_reverse_deps(
+    impacts: &[Impact],
+    mode: ApplyMode,
+    enforce: bool,
+) -> Result<(), String> {
+    let lines = impacts.iter().flat_map(|impact| {
+        impact
+            .dependents
+            .iter()
+            .map(|d| d.describe())
+            .chain(
+                impact
+                    .replaced_by
+                    .iter()
+                    .map(|r| format!("replaced by {}", r)),
+            )
+            .map(move |line| format!("replace {}: {}", impact.package, line))
+    });
+    for line in lines {
+        match mode {
+            ApplyMode::Commit => eprintln!("[info] {}", line),
+            ApplyMode::DryRun => println!("[DRY-RUN] {}", line),
+        }
+    }
+    let blocking: Vec<String> = impacts
+        .iter()
+        .flat_map(|i| i.blocking())
+        .map(|d| format!("{} depends on {}", d.package, d.depends))
+        .collect();
+    if blocking.is_empty() || !enforce {
+        return Ok(());
+    }
+    let msg = format!(
+        "removing {} would leave dependencies unsatisfied: {} (no installed package or provides shim satisfies them)",
+        impacts
+            .iter()
+            .filter(|i| i.blocking().next().is_some())
+            .map(|i| i.package.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        blocking.join("; ")
+    );
+    match mode {
+        ApplyMode::Commit => Err(msg),
+        ApplyMode::DryRun => {
+            eprintln!("[dry-run] would refuse: {}", msg);
+            Ok(())
+        }
+    }
+}
+
+fn emit_shim_event(shim: &ShimPackage, path: &Path, dry_run: bool) {
+    eprintln!(
+        "{}",
+        json!({
+            "event":"pm.shim",
+            "name": shim.name,
+            "version": shim.version,
+            "provides": shim.provides,
+            "conflicts": shim.conflicts,
+            "depends": shim.depends,
+            "path": path.display().to_string(),
+            "dry_run": dry_run,
+        })
+    );
+}
+
 pub fn verify_link_points_to(dst: &Path, src: &Path) -> bool {
     use std::fs;
     if let Ok(md) = fs::symlink_metadata(dst) {
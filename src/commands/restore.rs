@@ -1,36 +1,46 @@
 use std::path::Path;
-use std::process::{Command, Stdio};
 
+use serde_json::json;
 use switchyard::logging::JsonlSink;
 use switchyard::types::safepath::SafePath;
 use switchyard::types::{ApplyMode, PlanInput, RestoreRequest};
 use switchyard::Switchyard;
 
-use crate::adapters::arch::pm_lock_message;
+use crate::adapters::arch::DbLock;
 use crate::adapters::arch_adapter::ArchAdapter;
+use crate::adapters::artifact_cache;
+use crate::adapters::mtree::{self, Mismatch};
+use crate::adapters::pm::{self, pacman_args, InstallOptions};
+use crate::adapters::providers;
+use crate::adapters::shim::ShimPackage;
 use crate::cli::args::Package;
-use crate::util::paths::ensure_under_root;
+use crate::commands::restore_archive;
+use crate::commands::transaction::{RemovedPackage, Step, Transaction};
+use crate::commands::use_utils::replacement_pkg_name;
+use crate::state::State;
+use crate::util::paths::{ensure_under_root, strip_root};
 use oxidizr_cli_core::dest_dir_path;
+use oxidizr_cli_core::packages::coreutils_critical_set;
 use oxidizr_cli_core::{static_fallback_applets, DistroAdapter, PackageKind};
-use serde_json::json;
 
-#[allow(unused_variables)]
+/// Restore the packages as one transaction: either every package is back on its distro
+/// build or, on failure, everything done so far is rolled back.
+#[allow(unused_variables, clippy::too_many_arguments)]
 pub fn exec(
     api: &Switchyard<JsonlSink, JsonlSink>,
     root: &Path,
     package: Option<Package>,
     all: bool,
     keep_replacements: bool,
+    keep_cache: bool,
     mode: ApplyMode,
     assume_yes: bool,
 ) -> Result<(), String> {
-    let adapter = ArchAdapter;
-    let live_root = root == Path::new("/");
-    if matches!(mode, ApplyMode::Commit) && live_root {
-        if let Some(msg) = pm_lock_message(root) {
-            return Err(msg);
-        }
-    }
+    // Hold the pacman database lock for the whole commit (released around our pacman runs)
+    let _lock = match mode {
+        ApplyMode::Commit => Some(DbLock::acquire(root)?),
+        ApplyMode::DryRun => None,
+    };
 
     let packages: Vec<Package> = if all {
         vec![Package::Coreutils, Package::Findutils, Package::Sudo]
@@ -40,57 +50,161 @@ pub fn exec(
         vec![Package::Coreutils, Package::Findutils, Package::Sudo]
     };
 
-    // Pre: ensure distro packages are installed when committing
-    if matches!(mode, ApplyMode::Commit) {
-        if !live_root {
-            eprintln!(
-                "[info] skipping pacman installs under non-live root: {}",
-                root.display()
+    let mut tx = Transaction::new("restore", api, root);
+    run(&mut tx, api, root, &packages, keep_replacements, mode).map_err(|e| tx.rollback(e))?;
+    // Only after the transaction: removing the cache cannot be undone
+    if !keep_replacements {
+        clean_cache(root, &packages, keep_cache, mode)?;
+    }
+    Ok(())
+}
+
+/// Remove the cached package files and AUR build directories of the replacements restore
+/// uninstalled (all of them in dry-run); a replacement still installed keeps its cache.
+fn clean_cache(
+    root: &Path,
+    packages: &[Package],
+    keep_cache: bool,
+    mode: ApplyMode,
+) -> Result<(), String> {
+    let pm = pm::get();
+    for p in packages {
+        let rs_name = replacement_pkg_name(*p);
+        if matches!(mode, ApplyMode::Commit) && pm.is_installed(root, rs_name) {
+            continue;
+        }
+        let artifacts = artifact_cache::find(root, rs_name);
+        if artifacts.is_empty() {
+            continue;
+        }
+        let total = artifact_cache::human_size(artifacts.iter().map(|a| a.bytes).sum());
+        if keep_cache {
+            println!(
+                "[info] restore: keeping {} cached artifact(s) of {} ({}) (--keep-cache)",
+                artifacts.len(),
+                rs_name,
+                total
             );
-        } else {
-            for p in &packages {
-                let name = distro_pkg_name(*p);
-                let mut cmd = Command::new("pacman");
-                let args = vec![
-                    "-S".to_string(),
-                    "--noconfirm".to_string(),
-                    name.to_string(),
-                ];
-                let args_view = args.clone();
-                cmd.args(&args);
-                cmd.stdin(Stdio::null());
-                cmd.stdout(Stdio::piped());
-                cmd.stderr(Stdio::piped());
-                let out = cmd
-                    .output()
-                    .map_err(|e| format!("failed to spawn pacman: {e}"))?;
-                let code = out.status.code().unwrap_or(1);
-                let stderr_tail = String::from_utf8_lossy(&out.stderr);
+            continue;
+        }
+        match mode {
+            ApplyMode::DryRun => {
+                for a in &artifacts {
+                    println!(
+                        "[DRY-RUN] restore: would remove {} {} ({})",
+                        a.kind,
+                        strip_root(root, &a.path).display(),
+                        artifact_cache::human_size(a.bytes)
+                    );
+                }
+                println!(
+                    "[DRY-RUN] restore: would free {} from cached {} artifacts",
+                    total, rs_name
+                );
+            }
+            ApplyMode::Commit => {
+                artifact_cache::remove(&artifacts)?;
                 eprintln!(
                     "{}",
                     json!({
-                        "event":"pm.install","pm":{"tool":"pacman","args": args_view, "package": name},
-                        "exit_code": code,
-                        "stderr_tail": stderr_tail.chars().rev().take(400).collect::<String>().chars().rev().collect::<String>()
+                        "event":"restore.cache_cleanup",
+                        "package": rs_name,
+                        "artifacts": artifacts,
+                        "bytes": artifacts.iter().map(|a| a.bytes).sum::<u64>(),
                     })
                 );
-                if code != 0 {
-                    return Err(format!("pacman -S {} failed with exit code {}", name, code));
-                }
+                println!(
+                    "[OK] restore: removed {} cached artifact(s) of {} ({} freed)",
+                    artifacts.len(),
+                    rs_name,
+                    total
+                );
             }
         }
-    } else {
-        for p in &packages {
+    }
+    Ok(())
+}
+
+/// The restore transaction: selections dropped, one `pacman -S` for the distro packages, one
+/// combined restore plan, one `pacman -R` for the replacements. Committed steps are recorded
+/// in `tx` so a failure undoes all of them.
+fn run(
+    tx: &mut Transaction,
+    api: &Switchyard<JsonlSink, JsonlSink>,
+    root: &Path,
+    packages: &[Package],
+    keep_replacements: bool,
+    mode: ApplyMode,
+) -> Result<(), String> {
+    let adapter = ArchAdapter;
+    let pm = pm::get();
+    let commit = matches!(mode, ApplyMode::Commit);
+
+    // Drop the selection first so the pacman hook does not re-link during `pacman -S`
+    if commit {
+        let mut state = State::load(root)?;
+        let before = state.clone();
+        state.active.retain(|p, _| !packages.contains(p));
+        state.pins.retain(|p, _| !packages.contains(p));
+        if state.active.len() != before.active.len() || state.pins.len() != before.pins.len() {
+            state.save(root)?;
+            tx.record(Step::State(before.clone()));
+            if state.pins.len() != before.pins.len() {
+                state.sync_ignore_pkg(root)?;
+            }
+        }
+    }
+
+    // Pre: ensure distro packages are installed, in one pacman transaction
+    let names: Vec<&str> = packages.iter().map(|p| distro_pkg_name(*p)).collect();
+    let shimmed: Vec<Package> = packages
+        .iter()
+        .copied()
+        .filter(|p| pm.is_installed(root, &ShimPackage::name_for(*p)))
+        .collect();
+    let mut opts = InstallOptions::default();
+    if !shimmed.is_empty() {
+        // --ask=4 lets pacman remove the conflicting provides shims left by `replace`; the
+        // links left in /usr/bin are owned by no package and would conflict otherwise
+        opts.replace_conflicts = true;
+        opts.overwrite.push("/usr/bin/*".to_string());
+    }
+    if commit {
+        if !pm.manages(root) {
             eprintln!(
-                "[dry-run] would run: pacman -S --noconfirm {}",
-                distro_pkg_name(*p)
+                "[info] skipping pacman installs under non-live root: {} (pass --chroot to run pacman inside it)",
+                root.display()
             );
+        } else {
+            let missing: Vec<String> = packages
+                .iter()
+                .filter(|p| !shimmed.contains(p))
+                .map(|p| distro_pkg_name(*p))
+                .filter(|n| !pm.is_installed(root, n))
+                .map(str::to_string)
+                .collect();
+            pm.install(root, &names, &opts)?;
+            if !shimmed.is_empty() {
+                tx.record(Step::Unshimmed(shimmed));
+            }
+            if !missing.is_empty() {
+                tx.record(Step::Installed(missing));
+            }
+            for p in packages {
+                let name = distro_pkg_name(*p);
+                providers::check_after(root, *p, &format!("pacman -S {}", name))?;
+            }
         }
+    } else {
+        eprintln!(
+            "[dry-run] would run: pacman {}",
+            pacman_args("-S", &names, &opts).join(" ")
+        );
     }
 
-    // Compute full set of applets to restore
-    let mut applets = Vec::new();
-    for p in &packages {
+    // Compute full set of applets to restore, per package
+    let mut restored: Vec<(Package, Vec<String>)> = Vec::new();
+    for p in packages {
         let kind = match p {
             Package::Coreutils => PackageKind::Coreutils,
             Package::Findutils => PackageKind::Findutils,
@@ -100,19 +214,25 @@ pub fn exec(
         if set.is_empty() {
             set = static_fallback_applets(kind);
         }
-        applets.extend(set);
+        restored.push((*p, set));
     }
 
-    // Build restore plan
+    // Build one restore plan for all packages
     let dest_dir = dest_dir_path();
     let mut restores = Vec::new();
-    for app in &applets {
+    for app in restored.iter().flat_map(|(_, set)| set) {
         let dest_base = ensure_under_root(root, &dest_dir);
         let dst = dest_base.join(app);
         let sp = SafePath::from_rooted(root, &dst).map_err(|e| format!("invalid target: {e:?}"))?;
         restores.push(RestoreRequest { target: sp });
     }
 
+    // Paths without a backup come from the distro package archive instead
+    let fallbacks = restore_archive::plan(root, &restored);
+    if commit {
+        restore_archive::check_sources(root, &fallbacks)?;
+    }
+
     let plan = api.plan(PlanInput {
         link: vec![],
         restore: restores,
@@ -120,83 +240,142 @@ pub fn exec(
     let _pre = api
         .preflight(&plan)
         .map_err(|e| format!("preflight failed: {e:?}"))?;
-    let _rep = api
+    let rep = api
         .apply(&plan, mode)
         .map_err(|e| format!("apply failed: {e:?}"))?;
+    if commit {
+        if !rep.errors.is_empty() {
+            return Err(format!(
+                "restore apply failed: {}{}",
+                rep.errors.join("; "),
+                if rep.rolled_back {
+                    " (changes rolled back)"
+                } else {
+                    ""
+                }
+            ));
+        }
+        tx.record(Step::Applied(rep));
+        restore_archive::apply(tx, root, &fallbacks)?;
+        // Before the replacements go: a failure here rolls back to them
+        verify_restored(root, &restored)?;
+    } else {
+        restore_archive::describe(root, &fallbacks);
+    }
 
-    // Post: optionally purge RS packages unless --keep-replacements
-    if matches!(mode, ApplyMode::Commit) {
-        if !keep_replacements && live_root {
-            for p in &packages {
+    // Post: optionally purge RS packages unless --keep-replacements, in one pacman transaction
+    if keep_replacements {
+        return Ok(());
+    }
+    if commit {
+        if pm.manages(root) {
+            let mut removing = Vec::new();
+            for p in packages {
                 let rs_name = replacement_pkg_name(*p);
-                if pacman_installed(rs_name) {
-                    let mut cmd = Command::new("pacman");
-                    let args = vec![
-                        "-R".to_string(),
-                        "--noconfirm".to_string(),
-                        rs_name.to_string(),
-                    ];
-                    let args_view = args.clone();
-                    cmd.args(&args);
-                    cmd.stdin(Stdio::null());
-                    cmd.stdout(Stdio::piped());
-                    cmd.stderr(Stdio::piped());
-                    let out = cmd
-                        .output()
-                        .map_err(|e| format!("failed to spawn pacman: {e}"))?;
-                    let code = out.status.code().unwrap_or(1);
-                    let stderr_tail = String::from_utf8_lossy(&out.stderr);
-                    eprintln!(
-                        "{}",
-                        json!({
-                            "event":"pm.remove","pm":{"tool":"pacman","args": args_view, "package": rs_name},
-                            "exit_code": code,
-                            "stderr_tail": stderr_tail.chars().rev().take(400).collect::<String>().chars().rev().collect::<String>()
-                        })
-                    );
-                    if code != 0 {
-                        return Err(format!(
-                            "pacman -R {} failed with exit code {}",
-                            rs_name, code
-                        ));
-                    }
+                if pm.is_installed(root, rs_name) {
+                    providers::guard_removal(root, *p, &[rs_name], false)?;
+                    removing.push((*p, rs_name));
+                }
+            }
+            if !removing.is_empty() {
+                let rs_names: Vec<&str> = removing.iter().map(|(_, n)| *n).collect();
+                let removed: Vec<RemovedPackage> = rs_names
+                    .iter()
+                    .map(|n| RemovedPackage::capture(root, n))
+                    .collect();
+                pm.remove(root, &rs_names)?;
+                tx.record(Step::Removed(removed));
+                for (p, rs_name) in &removing {
+                    providers::check_after(root, *p, &format!("pacman -R {}", rs_name))?;
                 }
             }
         }
-    } else if !keep_replacements {
-        for p in &packages {
-            eprintln!(
-                "[dry-run] would run: pacman -R --noconfirm {}",
-                replacement_pkg_name(*p)
-            );
-        }
+    } else {
+        let rs_names: Vec<&str> = packages.iter().map(|p| replacement_pkg_name(*p)).collect();
+        eprintln!(
+            "[dry-run] would run: pacman -R --noconfirm {}",
+            rs_names.join(" ")
+        );
     }
 
     Ok(())
 }
 
-fn distro_pkg_name(pkg: Package) -> &'static str {
+/// Restored applets that must match the distro package: the parity-critical set.
+fn core_binaries(pkg: Package) -> Vec<String> {
     match pkg {
-        Package::Coreutils => "coreutils",
-        Package::Findutils => "findutils",
-        Package::Sudo => "sudo",
+        Package::Coreutils => coreutils_critical_set(),
+        Package::Findutils => static_fallback_applets(PackageKind::Findutils),
+        Package::Sudo => vec!["sudo".to_string()],
     }
 }
 
-fn replacement_pkg_name(pkg: Package) -> &'static str {
-    match pkg {
-        Package::Coreutils => "uutils-coreutils",
-        Package::Findutils => "uutils-findutils",
-        Package::Sudo => "sudo-rs",
+/// Check the restored paths against the distro package's `mtree`, like `pacman -Qkk` scoped
+/// to them. Every mismatch is reported; a mismatching core binary fails the restore.
+fn verify_restored(root: &Path, restored: &[(Package, Vec<String>)]) -> Result<(), String> {
+    let dest_dir = dest_dir_path();
+    for (p, applets) in restored {
+        let name = distro_pkg_name(*p);
+        let paths: Vec<String> = applets
+            .iter()
+            .map(|a| dest_dir.join(a).display().to_string())
+            .collect();
+        let Some(mismatches) = mtree::verify(root, name, &paths)? else {
+            eprintln!(
+                "[info] restore {}: no mtree for {} in the local database; skipping the integrity check",
+                name, name
+            );
+            continue;
+        };
+        let core = core_binaries(*p);
+        let core_mismatches: Vec<&Mismatch> = mismatches
+            .iter()
+            .filter(|m| core.iter().any(|c| dest_dir.join(c) == Path::new(&m.path)))
+            .collect();
+        eprintln!(
+            "{}",
+            json!({
+                "event":"restore.verify",
+                "package": name,
+                "paths": paths.len(),
+                "mismatches": mismatches,
+                "core_mismatches": core_mismatches.len(),
+            })
+        );
+        for m in &mismatches {
+            eprintln!(
+                "[warn] restore {}: {}: {}",
+                name,
+                m.path,
+                m.problems.join(", ")
+            );
+        }
+        if !core_mismatches.is_empty() {
+            return Err(format!(
+                "restored {} binaries do not match the package: {}; reinstall it with `pacman -S {}`",
+                name,
+                core_mismatches
+                    .iter()
+                    .map(|m| format!("{} ({})", m.path, m.problems.join(", ")))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+                name
+            ));
+        }
+        if mismatches.is_empty() {
+            println!(
+                "[OK] restore {}: restored files match the package mtree",
+                name
+            );
+        }
     }
+    Ok(())
 }
 
-fn pacman_installed(name: &str) -> bool {
-    let st = Command::new("pacman")
-        .args(["-Qi", name])
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status();
-    matches!(st, Ok(s) if s.success())
+pub fn distro_pkg_name(pkg: Package) -> &'static str {
+    match pkg {
+        Package::Coreutils => "coreutils",
+        Package::Findutils => "findutils",
+        Package::Sudo => "sudo",
+    }
 }
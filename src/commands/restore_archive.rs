@@ -0,0 +1,210 @@
+//! Fallback restore source for paths Switchyard has no backup of (backups wiped, or links made
+//! without one): the distro package's own files, extracted in-process from its archive in the
+//! pacman cache. Only paths that are still a foreign symlink or missing are taken; the archive
+//! must match the installed version when the local database knows it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+
+use crate::adapters::pacman_cache::{cache_dirs, matching_archive, newest_archive};
+use crate::adapters::pkg_archive;
+use crate::adapters::pm;
+use crate::cli::args::Package;
+use crate::commands::restore::distro_pkg_name;
+use crate::commands::transaction::{Step, Transaction};
+use crate::state::latest_backup;
+use crate::util::paths::{ensure_under_root, strip_root};
+use oxidizr_cli_core::dest_dir_path;
+
+/// Paths of one package that need the archive, and the archive found for them.
+#[derive(Debug)]
+pub struct Fallback {
+    pub package: Package,
+    pub paths: Vec<String>,
+    pub archive: Option<PathBuf>,
+}
+
+impl Fallback {
+    fn applets(&self) -> String {
+        self.paths
+            .iter()
+            .map(|p| p.rsplit('/').next().unwrap_or(p))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Why there is no archive, naming the version and the directories searched.
+    fn missing_archive(&self, root: &Path) -> String {
+        let name = distro_pkg_name(self.package);
+        let version = pm::get().version(root, name);
+        format!(
+            "no backup of {} and no {}{} package archive in {}; download it (e.g. from the Arch Linux Archive) into the package cache",
+            self.applets(),
+            name,
+            version.map(|v| format!(" {}", v)).unwrap_or_default(),
+            search_dirs(root)
+                .iter()
+                .map(|d| d.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+/// Package caches to search: those of `root`, then the host's for another root.
+fn search_dirs(root: &Path) -> Vec<PathBuf> {
+    let mut dirs = cache_dirs(root);
+    if root != Path::new("/") {
+        for d in cache_dirs(Path::new("/")) {
+            if !dirs.contains(&d) {
+                dirs.push(d);
+            }
+        }
+    }
+    dirs
+}
+
+/// Archive of the distro package: the installed version when the local database knows it,
+/// else the newest cached one.
+fn find_archive(root: &Path, name: &str) -> Option<PathBuf> {
+    let version = pm::get().version(root, name);
+    search_dirs(root).iter().find_map(|d| {
+        match &version {
+            Some(v) => matching_archive(d, name, v),
+            None => newest_archive(d, name),
+        }
+        .map(|(p, _)| p)
+    })
+}
+
+/// A restored path the archive has to provide: no backup, and either missing or a symlink that
+/// does not point into the package's own files (the package's links, like `sudoedit -> sudo`,
+/// stay).
+fn needs_archive(root: &Path, path: &str, shipped: &[String]) -> bool {
+    let target = ensure_under_root(root, Path::new(path));
+    if latest_backup(&target).is_some() {
+        return false;
+    }
+    match fs::read_link(&target) {
+        Ok(dest) => {
+            let dest = target.parent().unwrap_or(root).join(dest);
+            !shipped.contains(&strip_root(root, &dest).display().to_string())
+        }
+        Err(_) => fs::symlink_metadata(&target).is_err(),
+    }
+}
+
+/// Paths without a backup, per package, with the archive to take them from. Call before the
+/// restore plan runs.
+pub fn plan(root: &Path, restored: &[(Package, Vec<String>)]) -> Vec<Fallback> {
+    let dest_dir = dest_dir_path();
+    restored
+        .iter()
+        .filter_map(|(p, applets)| {
+            let name = distro_pkg_name(*p);
+            let files = pm::get().list_files(root, name);
+            let shipped: &[String] = files.as_deref().map_or(&[], |f| f.as_slice());
+            let paths: Vec<String> = applets
+                .iter()
+                .map(|a| dest_dir.join(a).display().to_string())
+                // Without a database entry, the archive decides what the package ships
+                .filter(|path| files.is_none() || shipped.contains(path))
+                .filter(|path| needs_archive(root, path, shipped))
+                .collect();
+            (!paths.is_empty()).then(|| Fallback {
+                package: *p,
+                paths,
+                archive: find_archive(root, name),
+            })
+        })
+        .collect()
+}
+
+/// Refuse before anything changes when a fallback has no archive to take its paths from.
+pub fn check_sources(root: &Path, fallbacks: &[Fallback]) -> Result<(), String> {
+    match fallbacks.iter().find(|f| f.archive.is_none()) {
+        Some(f) => Err(f.missing_archive(root)),
+        None => Ok(()),
+    }
+}
+
+/// Dry-run description of the fallbacks.
+pub fn describe(root: &Path, fallbacks: &[Fallback]) {
+    for f in fallbacks {
+        let name = distro_pkg_name(f.package);
+        match &f.archive {
+            Some(archive) => eprintln!(
+                "[dry-run] restore {}: no backup of {}; would extract them from {}",
+                name,
+                f.applets(),
+                strip_root(root, archive).display()
+            ),
+            None => eprintln!("[dry-run] would refuse: {}", f.missing_archive(root)),
+        }
+    }
+}
+
+/// Extract the paths of every fallback from its archive, after the restore plan. Each path's
+/// previous symlink is recorded in `tx` so a later failure links it again.
+pub fn apply(tx: &mut Transaction, root: &Path, fallbacks: &[Fallback]) -> Result<(), String> {
+    for f in fallbacks {
+        let name = distro_pkg_name(f.package);
+        let archive = f.archive.as_ref().ok_or_else(|| f.missing_archive(root))?;
+        // Switchyard may have put some back after all
+        let todo: Vec<String> = f
+            .paths
+            .iter()
+            .filter(|p| {
+                let t = ensure_under_root(root, Path::new(p));
+                fs::symlink_metadata(&t).map_or(true, |m| m.file_type().is_symlink())
+            })
+            .cloned()
+            .collect();
+        let previous: Vec<(PathBuf, Option<PathBuf>)> = todo
+            .iter()
+            .map(|p| {
+                let t = ensure_under_root(root, Path::new(p));
+                let dest = fs::read_link(&t).ok();
+                (t, dest)
+            })
+            .collect();
+        let written = pkg_archive::extract(root, archive, &todo)?;
+        tx.record(Step::Extracted(
+            previous
+                .into_iter()
+                .filter(|(t, _)| written.contains(&strip_root(root, t).display().to_string()))
+                .collect(),
+        ));
+        let absent: Vec<&String> = todo.iter().filter(|p| !written.contains(p)).collect();
+        eprintln!(
+            "{}",
+            json!({
+                "event":"restore.extract",
+                "package": name,
+                "archive": strip_root(root, archive),
+                "paths": written,
+                "not_in_archive": absent,
+            })
+        );
+        if !absent.is_empty() {
+            return Err(format!(
+                "{} does not contain {}",
+                archive.display(),
+                absent
+                    .iter()
+                    .map(|p| p.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        println!(
+            "[OK] restore {}: extracted {} path(s) without a backup from {}",
+            name,
+            written.len(),
+            strip_root(root, archive).display()
+        );
+    }
+    Ok(())
+}
@@ -1,50 +1,57 @@
-use std::os::unix::fs::PermissionsExt;
-use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
 
 use serde::Serialize;
 
+use crate::cli::args::Package;
+use crate::commands::versions::{self, ReplacementVersions};
+use crate::state::State;
+use crate::util::paths::strip_root;
+
 #[derive(Serialize)]
 struct StatusJson<'a> {
     coreutils: &'a str,
     findutils: &'a str,
     sudo: &'a str,
+    /// Installed, pinned and candidate versions of every replacement package
+    replacements: BTreeMap<String, ReplacementVersions>,
 }
 
 pub fn exec(root: &Path, json: bool) -> Result<(), String> {
-    fn symlink_points_to_executable(root: &Path, name: &str) -> bool {
-        let link_path = root.join("usr/bin").join(name);
-        let md = match link_path.symlink_metadata() {
-            Ok(m) => m,
-            Err(_) => return false,
-        };
-        if !md.file_type().is_symlink() {
-            return false;
-        }
-        let tgt = match std::fs::read_link(&link_path) {
-            Ok(t) => t,
-            Err(_) => return false,
-        };
-        let abs: PathBuf = if tgt.is_absolute() {
-            tgt
-        } else {
-            link_path.parent().unwrap_or(Path::new("/")).join(tgt)
-        };
-        match std::fs::metadata(&abs) {
-            Ok(m) => m.permissions().mode() & 0o111 != 0,
-            Err(_) => false,
-        }
-    }
-    let check = |name: &str| -> bool { symlink_points_to_executable(root, name) };
-    // Consider package active if ANY representative applet symlink exists
-    let coreutils_active = ["ls", "cat", "echo", "mv"].iter().any(|n| check(n));
-    let findutils_active = ["find", "xargs"].iter().any(|n| check(n));
-    let sudo_active = check("sudo");
+    // Active means a committed `use`/`replace` recorded the package in the state file
+    // and its recorded links are still in place.
+    let state = State::load(root)?;
+    let is_active = |pkg: Package| -> bool {
+        state.active.get(&pkg).is_some_and(|sel| {
+            sel.links.is_empty()
+                || sel.links.values().any(|l| {
+                    let t = l.target_under(root);
+                    fs::read_link(&t).is_ok_and(|dest| {
+                        let abs = if dest.is_absolute() {
+                            dest
+                        } else {
+                            t.parent().unwrap_or(root).join(dest)
+                        };
+                        strip_root(root, &abs) == l.source
+                    })
+                })
+        })
+    };
+    let coreutils_active = is_active(Package::Coreutils);
+    let findutils_active = is_active(Package::Findutils);
+    let sudo_active = is_active(Package::Sudo);
+    let replacements = versions::check(root);
 
     if json {
         let payload = StatusJson {
             coreutils: if coreutils_active { "active" } else { "unset" },
             findutils: if findutils_active { "active" } else { "unset" },
             sudo: if sudo_active { "active" } else { "unset" },
+            replacements: replacements
+                .into_iter()
+                .map(|(p, v)| (format!("{:?}", p).to_lowercase(), v))
+                .collect(),
         };
         println!(
             "{}",
@@ -60,6 +67,48 @@ pub fn exec(root: &Path, json: bool) -> Result<(), String> {
             if findutils_active { "active" } else { "unset" }
         );
         println!("sudo: {}", if sudo_active { "active" } else { "unset" });
+        for (pkg, sel) in &state.active {
+            if let Some(l) = sel.links.values().next() {
+                println!(
+                    "  {}: {} managed link(s) from {} {}",
+                    format!("{:?}", pkg).to_lowercase(),
+                    sel.links.len(),
+                    l.provider,
+                    l.provider_version.as_deref().unwrap_or("")
+                );
+            }
+        }
+        for (pkg, v) in &replacements {
+            let Some(pinned) = &v.pinned else {
+                continue;
+            };
+            let available = v
+                .available
+                .as_ref()
+                .map(|a| format!(", available {}/{}", a.repo, a.version))
+                .unwrap_or_default();
+            println!(
+                "  {}: {} pinned to {} (installed {}{})",
+                format!("{:?}", pkg).to_lowercase(),
+                v.package,
+                pinned,
+                v.installed.as_deref().unwrap_or("none"),
+                available
+            );
+        }
+        for (pkg, v) in &replacements {
+            let Some(hint) = v.hint(*pkg) else {
+                continue;
+            };
+            println!(
+                "  {}: {} {} is outdated (candidate {})",
+                format!("{:?}", pkg).to_lowercase(),
+                v.package,
+                v.installed.as_deref().unwrap_or_default(),
+                v.candidate().unwrap_or_default()
+            );
+            eprintln!("Tip: {}", hint);
+        }
         if coreutils_active {
             eprintln!("Tip: restore with 'oxidizr-arch restore coreutils --commit'.");
             eprintln!("Next: after validating workloads, you may fully switch by removing GNU packages with 'oxidizr-arch --commit replace coreutils'.");
@@ -0,0 +1,121 @@
+//! Pre-install check against partial upgrades.
+//!
+//! Before `pacman -S <replacement>`, look at the age of the sync databases, whether they offer
+//! the package (`pacman -Si`) and whether the installed system is behind them (`pacman -Qu`).
+//! Installing from databases newer than the system is Arch's classic partial upgrade, and
+//! stale databases point at versions the mirrors no longer carry, so both refuse the install
+//! unless `--allow-partial` is given; `--sync-upgrade` runs `pacman -Syu <pkg>` instead. Every
+//! decision is logged as a `pm.sync_check` event.
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::adapters::pm::{self, InstallOptions};
+
+/// Sync databases older than this are considered stale.
+pub const STALE_AFTER: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How many outdated packages to name in messages and events.
+const SAMPLE: usize = 5;
+
+/// What the operator allowed (`--allow-partial`, `--sync-upgrade`).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncPolicy {
+    pub allow_partial: bool,
+    pub sync_upgrade: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Databases current and the system up to date: plain `pacman -S`
+    Install,
+    /// `pacman -Syu <pkg>`, as requested
+    SyncUpgrade,
+    /// Behind or stale, installed anyway with `--allow-partial`
+    AllowPartial,
+    /// Not in the current sync databases: the AUR fallback is expected
+    NotInSync,
+    Refuse,
+}
+
+/// Decide how to install `rs_pkg` from the sync repositories and return the pacman options
+/// for it; refusals are errors naming both ways out. Emits `pm.sync_check`.
+pub fn check(root: &Path, rs_pkg: &str, policy: SyncPolicy) -> Result<InstallOptions, String> {
+    let pm = pm::get();
+    let age = pm.sync_db_age(root);
+    let available = pm.sync_info(root, rs_pkg);
+    // Upgrading the package itself is not a partial upgrade
+    let outdated: Vec<_> = pm
+        .outdated(root)
+        .into_iter()
+        .filter(|u| u.name != rs_pkg)
+        .collect();
+
+    let mut reasons = Vec::new();
+    if let Some(age) = age.filter(|a| *a > STALE_AFTER) {
+        reasons.push(format!(
+            "the sync databases are {} days old",
+            age.as_secs() / 86_400
+        ));
+    }
+    if !outdated.is_empty() {
+        let sample: Vec<String> = outdated
+            .iter()
+            .take(SAMPLE)
+            .map(|u| format!("{} {} -> {}", u.name, u.installed, u.available))
+            .collect();
+        reasons.push(format!(
+            "{} installed package(s) are older than the sync databases ({}{})",
+            outdated.len(),
+            sample.join(", "),
+            if outdated.len() > SAMPLE { ", ..." } else { "" }
+        ));
+    }
+    let decision = if policy.sync_upgrade {
+        Decision::SyncUpgrade
+    } else if !reasons.is_empty() {
+        if policy.allow_partial {
+            Decision::AllowPartial
+        } else {
+            Decision::Refuse
+        }
+    } else if available.is_none() {
+        Decision::NotInSync
+    } else {
+        Decision::Install
+    };
+
+    eprintln!(
+        "{}",
+        json!({
+            "event":"pm.sync_check",
+            "package": rs_pkg,
+            "sync_db_age_secs": age.map(|a| a.as_secs()),
+            "available": available,
+            "outdated_count": outdated.len(),
+            "outdated": outdated.iter().take(SAMPLE).collect::<Vec<_>>(),
+            "reasons": reasons,
+            "allow_partial": policy.allow_partial,
+            "sync_upgrade": policy.sync_upgrade,
+            "decision": decision,
+        })
+    );
+
+    match decision {
+        Decision::Refuse => Err(format!(
+            "refusing to install {}: {}; installing it now would be a partial upgrade. Re-run with --sync-upgrade to run pacman -Syu {} (upgrades the whole system), or with --allow-partial to install against the current databases",
+            rs_pkg,
+            reasons.join("; "),
+            rs_pkg
+        )),
+        Decision::SyncUpgrade => Ok(InstallOptions {
+            sync_upgrade: true,
+            ..InstallOptions::default()
+        }),
+        _ => Ok(InstallOptions::default()),
+    }
+}
@@ -0,0 +1,404 @@
+//! All-or-nothing execution for `replace`, `restore` and `update` across several packages.
+//!
+//! Every committed step is recorded as it completes. When a later step fails (including the
+//! post-verify after packages were removed) the recorded steps are undone in reverse order:
+//! removed packages are reinstalled, preferring the exact version from the pacman cache,
+//! then the backed-up topology is restored. Each undone step is printed as `[UNDO]` and the
+//! whole rollback is reported in a single `transaction.rollback` event.
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use switchyard::logging::JsonlSink;
+use switchyard::types::{Action, ApplyMode, ApplyReport};
+use switchyard::Switchyard;
+
+use crate::adapters::pacman_cache::cached_archive;
+use crate::adapters::pm::{self, InstallOptions};
+use crate::adapters::shim::ShimPackage;
+use crate::cli::args::Package;
+use crate::state::{state_path, State};
+use crate::util::paths::strip_root;
+
+/// A package this operation removed, with the version it had.
+#[derive(Debug, Clone)]
+pub struct RemovedPackage {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl RemovedPackage {
+    /// Capture `name` and its installed version from the local database.
+    pub fn capture(root: &Path, name: &str) -> Self {
+        RemovedPackage {
+            name: name.to_string(),
+            version: pm::get().version(root, name),
+        }
+    }
+
+    fn label(&self) -> String {
+        match &self.version {
+            Some(v) => format!("{} {}", self.name, v),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// A completed step and what undoing it takes.
+#[derive(Debug)]
+pub enum Step {
+    /// Packages installed with `pacman -S` (undo: `pacman -R`)
+    Installed(Vec<String>),
+    /// Packages removed with `pacman -R` (undo: reinstall them)
+    Removed(Vec<RemovedPackage>),
+    /// Packages upgraded with `pacman -S`, at the version they had (undo: downgrade them from
+    /// the pacman cache)
+    Upgraded(Vec<RemovedPackage>),
+    /// Distro packages swapped out for their provides shim (undo: reinstall them over our links)
+    Shimmed(Vec<RemovedPackage>),
+    /// Distro packages reinstalled over their provides shim (undo: install the shim again)
+    Unshimmed(Vec<Package>),
+    /// A committed Switchyard apply (undo: apply its rollback plan)
+    Applied(ApplyReport),
+    /// Links made again after pacman deleted them (undo: remove them; their backups are those
+    /// of the first apply, restored by undoing its [`Step::Applied`])
+    Relinked(Vec<PathBuf>),
+    /// The state file before this operation changed it (undo: write it back)
+    State(State),
+    /// Links removed without a backup, with their destination (undo: link them again)
+    Unlinked(Vec<(PathBuf, PathBuf)>),
+    /// Paths extracted from a package archive, with the symlink each replaced (undo: link
+    /// them again)
+    Extracted(Vec<(PathBuf, Option<PathBuf>)>),
+}
+
+impl Step {
+    fn describe(&self) -> String {
+        match self {
+            Step::Installed(names) => format!("removed {}", names.join(" ")),
+            Step::Removed(pkgs) | Step::Shimmed(pkgs) => format!(
+                "reinstalled {}",
+                pkgs.iter()
+                    .map(RemovedPackage::label)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Step::Upgraded(pkgs) => format!(
+                "downgraded to {}",
+                pkgs.iter()
+                    .map(RemovedPackage::label)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Step::Unshimmed(pkgs) => format!(
+                "reinstalled {}",
+                pkgs.iter()
+                    .map(|p| ShimPackage::name_for(*p))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            Step::Applied(rep) => format!("restored {} backed-up path(s)", rep.executed.len()),
+            Step::Relinked(paths) => format!("unlinked {} re-linked path(s)", paths.len()),
+            Step::Unlinked(links) => format!("re-created {} removed link(s)", links.len()),
+            Step::State(_) => "restored state file".to_string(),
+            Step::Extracted(paths) => format!("re-linked {} extracted path(s)", paths.len()),
+        }
+    }
+}
+
+pub struct Transaction<'a> {
+    operation: &'static str,
+    api: &'a Switchyard<JsonlSink, JsonlSink>,
+    root: &'a Path,
+    steps: Vec<Step>,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(
+        operation: &'static str,
+        api: &'a Switchyard<JsonlSink, JsonlSink>,
+        root: &'a Path,
+    ) -> Self {
+        Transaction {
+            operation,
+            api,
+            root,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Record a completed (committed) step.
+    pub fn record(&mut self, step: Step) {
+        self.steps.push(step);
+    }
+
+    /// Undo every recorded step after `cause` made the operation fail. Returns the error to
+    /// surface, annotated with the rollback outcome. Nothing is emitted when no step was
+    /// committed (dry-run, or a failure before the first change).
+    pub fn rollback(mut self, cause: String) -> String {
+        if self.steps.is_empty() {
+            return cause;
+        }
+        // The state file goes back first so the pacman hook does not re-link while the
+        // distro packages are reinstalled below
+        if let Some(i) = self.steps.iter().position(|s| matches!(s, Step::State(_))) {
+            let step = self.steps.remove(i);
+            self.steps.push(step);
+        }
+        let mut undone = Vec::new();
+        let mut failed = Vec::new();
+        let mut summary = Vec::new();
+        while let Some(step) = self.steps.pop() {
+            let what = step.describe();
+            match self.undo(step) {
+                Ok(detail) => {
+                    println!("[UNDO] {}: {}", self.operation, what);
+                    undone.push(detail);
+                    summary.push(what);
+                }
+                Err(e) => failed.push(format!("{}: {}", what, e)),
+            }
+        }
+        eprintln!(
+            "{}",
+            json!({
+                "event":"transaction.rollback",
+                "operation": self.operation,
+                "cause": cause,
+                "undone": undone,
+                "failed": failed,
+                "ok": failed.is_empty(),
+            })
+        );
+        if failed.is_empty() {
+            format!("{} (rolled back: {})", cause, summary.join("; "))
+        } else {
+            format!(
+                "{} (rollback incomplete: {}; undone: {})",
+                cause,
+                failed.join("; "),
+                summary.join("; ")
+            )
+        }
+    }
+
+    /// Undo one step; returns what was undone for the rollback event.
+    fn undo(&self, step: Step) -> Result<Value, String> {
+        let root = self.root;
+        match step {
+            Step::Installed(names) => {
+                let refs: Vec<&str> = names.iter().map(String::as_str).collect();
+                pm::get().remove(root, &refs)?;
+                Ok(json!({"step":"remove", "packages": names}))
+            }
+            Step::Removed(pkgs) => reinstall(root, &pkgs, false),
+            Step::Shimmed(pkgs) => reinstall(root, &pkgs, true),
+            Step::Upgraded(pkgs) => downgrade(root, &pkgs),
+            Step::Unshimmed(pkgs) => {
+                let mut archives = Vec::new();
+                let mut shims = Vec::new();
+                for p in pkgs {
+                    let shim = ShimPackage::for_package(root, p);
+                    archives.push(shim.write_archive(root)?);
+                    shims.push(shim.name);
+                }
+                let refs: Vec<&str> = shims.iter().map(String::as_str).collect();
+                let opts = InstallOptions {
+                    replace_conflicts: true,
+                    ..InstallOptions::default()
+                };
+                pm::get().install_files(root, &archives, &refs, &opts)?;
+                Ok(json!({"step":"reinstall_shim", "packages": shims}))
+            }
+            Step::Applied(rep) => {
+                let plan = self.api.plan_rollback_of(&rep);
+                let back = self
+                    .api
+                    .apply(&plan, ApplyMode::Commit)
+                    .map_err(|e| format!("{e:?}"))?;
+                if !back.errors.is_empty() {
+                    return Err(back.errors.join("; "));
+                }
+                let targets: Vec<String> = plan
+                    .actions
+                    .iter()
+                    .map(|a| match a {
+                        Action::EnsureSymlink { target, .. }
+                        | Action::RestoreFromBackup { target } => {
+                            strip_root(root, &target.as_path()).display().to_string()
+                        }
+                    })
+                    .collect();
+                Ok(json!({"step":"restore_backups", "targets": targets}))
+            }
+            Step::Relinked(paths) => {
+                let mut targets = Vec::new();
+                for target in paths {
+                    if fs::symlink_metadata(&target).is_ok_and(|m| m.file_type().is_symlink()) {
+                        fs::remove_file(&target)
+                            .map_err(|e| format!("cannot remove {}: {e}", target.display()))?;
+                    }
+                    targets.push(strip_root(root, &target).display().to_string());
+                }
+                Ok(json!({"step":"unlink_relinked", "targets": targets}))
+            }
+            Step::Unlinked(links) => {
+                let mut targets = Vec::new();
+                for (target, dest) in links {
+                    if fs::symlink_metadata(&target).is_err() {
+                        symlink(&dest, &target)
+                            .map_err(|e| format!("cannot link {}: {e}", target.display()))?;
+                    }
+                    targets.push(strip_root(root, &target).display().to_string());
+                }
+                Ok(json!({"step":"relink_removed", "targets": targets}))
+            }
+            Step::Extracted(paths) => {
+                let mut targets = Vec::new();
+                for (target, link) in paths {
+                    let _ = fs::remove_file(&target);
+                    if let Some(dest) = link {
+                        symlink(&dest, &target)
+                            .map_err(|e| format!("cannot link {}: {e}", target.display()))?;
+                    }
+                    targets.push(strip_root(root, &target).display().to_string());
+                }
+                Ok(json!({"step":"relink_extracted", "targets": targets}))
+            }
+            Step::State(prev) => {
+                prev.save(root)?;
+                prev.sync_ignore_pkg(root)?;
+                Ok(json!({"step":"state", "path": strip_root(root, &state_path(root))}))
+            }
+        }
+    }
+}
+
+/// Reinstall removed packages at the version they had: archives still in the pacman cache go
+/// through `pacman -U` (works offline), anything else through `pacman -S`. With `over_links`
+/// the distro package replaces its shim and may overwrite the links we left under /usr/bin.
+fn reinstall(root: &Path, pkgs: &[RemovedPackage], over_links: bool) -> Result<Value, String> {
+    let mut opts = InstallOptions::default();
+    if over_links {
+        // Answer "remove conflicting package?" with yes (distro package <-> shim)
+        opts.replace_conflicts = true;
+        opts.overwrite.push("/usr/bin/*".to_string());
+    }
+    let mut cached = Vec::new();
+    let mut synced = Vec::new();
+    let mut detail = Vec::new();
+    for p in pkgs {
+        match p
+            .version
+            .as_deref()
+            .and_then(|v| cached_archive(root, &p.name, v))
+        {
+            Some(archive) => {
+                detail.push(json!({
+                    "package": p.name,
+                    "version": p.version,
+                    "from": strip_root(root, &archive),
+                }));
+                cached.push((p.name.as_str(), archive));
+            }
+            None => {
+                detail.push(json!({"package": p.name, "version": p.version, "from": "sync"}));
+                synced.push(p.name.as_str());
+            }
+        }
+    }
+    if !cached.is_empty() {
+        let archives: Vec<PathBuf> = cached.iter().map(|(_, a)| a.clone()).collect();
+        let names: Vec<&str> = cached.iter().map(|(n, _)| *n).collect();
+        pm::get().install_files(root, &archives, &names, &opts)?;
+    }
+    if !synced.is_empty() {
+        pm::get().install(root, &synced, &opts)?;
+    }
+    Ok(json!({"step":"reinstall", "packages": detail}))
+}
+
+/// Install the previous versions of upgraded packages from the pacman cache; the sync
+/// repositories only offer the new one.
+fn downgrade(root: &Path, pkgs: &[RemovedPackage]) -> Result<Value, String> {
+    let mut archives = Vec::new();
+    let mut detail = Vec::new();
+    for p in pkgs {
+        let archive = p
+            .version
+            .as_deref()
+            .and_then(|v| cached_archive(root, &p.name, v))
+            .ok_or_else(|| format!("no cached archive of {} to downgrade to", p.label()))?;
+        detail.push(json!({
+            "package": p.name,
+            "version": p.version,
+            "from": strip_root(root, &archive),
+        }));
+        archives.push(archive);
+    }
+    let names: Vec<&str> = pkgs.iter().map(|p| p.name.as_str()).collect();
+    pm::get().install_files(root, &archives, &names, &InstallOptions::default())?;
+    Ok(json!({"step":"downgrade", "packages": detail}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use switchyard::adapters::FileLockManager;
+    use switchyard::policy::Policy;
+    use switchyard::types::safepath::SafePath;
+    use switchyard::types::{LinkRequest, PlanInput};
+
+    #[test]
+    fn test_rollback_reverts_links_and_state() {
+        let t = tempfile::tempdir().unwrap();
+        let root = t.path();
+        let bin = root.join("usr/bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join("ls"), b"gnu ls").unwrap();
+        let src = bin.join("uu-ls");
+        fs::write(&src, b"#!/bin/sh\n").unwrap();
+        fs::set_permissions(&src, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut policy = Policy::coreutils_switch_preset();
+        policy.governance.allow_unlocked_commit = true;
+        policy.apply.override_preflight = true;
+        policy.rescue.require = false;
+        policy.governance.smoke = switchyard::policy::types::SmokePolicy::Off;
+        policy.scope.allow_roots.push(root.to_path_buf());
+        let api = Switchyard::builder(JsonlSink, JsonlSink, policy)
+            .with_lock_manager(Box::new(FileLockManager::new(root.join("oxidizr.lock"))))
+            .build();
+
+        let before = State::load(root).unwrap();
+        let mut tx = Transaction::new("replace", &api, root);
+        tx.record(Step::State(before.clone()));
+        let mut after = before;
+        after.active.entry(Package::Coreutils).or_default().replaced = true;
+        after.save(root).unwrap();
+
+        let plan = api.plan(PlanInput {
+            link: vec![LinkRequest {
+                source: SafePath::from_rooted(root, &src).unwrap(),
+                target: SafePath::from_rooted(root, &bin.join("ls")).unwrap(),
+            }],
+            restore: vec![],
+        });
+        let rep = api.apply(&plan, ApplyMode::Commit).unwrap();
+        assert!(rep.errors.is_empty(), "{:?}", rep.errors);
+        assert!(fs::symlink_metadata(bin.join("ls"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        tx.record(Step::Applied(rep));
+
+        let msg = tx.rollback("pacman -U failed".to_string());
+        assert!(msg.starts_with("pacman -U failed (rolled back: "), "{msg}");
+        assert_eq!(fs::read(bin.join("ls")).unwrap(), b"gnu ls");
+        assert!(State::load(root).unwrap().active.is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+//! `update`: upgrade active replacements and bring their links in line with the new build.
+//!
+//! `use` and `replace` keep an installed replacement as it is, so applets added by a newer
+//! release would never be linked. `update` upgrades the replacement from the sync repositories
+//! (guarded like any sync install, pins respected), resolves its applets again, compares them
+//! with the links recorded in the state file and applies a plan for just the difference: new
+//! and moved applets are linked, applets the package no longer ships are restored from their
+//! backups. Parity is checked again before anything is linked. All packages are updated as one
+//! transaction: a failure after an upgrade downgrades it again and undoes the links.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use serde_json::json;
+use switchyard::logging::JsonlSink;
+use switchyard::types::safepath::SafePath;
+use switchyard::types::{ApplyMode, LinkRequest, PlanInput, RestoreRequest};
+use switchyard::Switchyard;
+
+use crate::adapters::arch::DbLock;
+use crate::adapters::arch_adapter::ArchAdapter;
+use crate::adapters::pm::{self, pacman_args};
+use crate::cli::args::{Package, ParityLevel};
+use crate::commands::replace_parity::enforce_replace_parity;
+use crate::commands::sync_guard::{self, SyncPolicy};
+use crate::commands::transaction::{RemovedPackage, Step, Transaction};
+use crate::commands::use_cmd::{prepare, Prepared};
+use crate::commands::use_parity::emit_use_parity_summary;
+use crate::commands::use_utils::replacement_pkg_name;
+use crate::state::{record_links, ManagedLink, State};
+use crate::util::paths::ensure_under_root;
+use crate::util::vercmp::vercmp;
+
+/// Update `package`, or every active package with `all`.
+#[allow(clippy::too_many_arguments)]
+pub fn exec(
+    api: &Switchyard<JsonlSink, JsonlSink>,
+    root: &Path,
+    package: Option<Package>,
+    all: bool,
+    mode: ApplyMode,
+    parity: ParityLevel,
+    allow_missing: Option<String>,
+    sync: SyncPolicy,
+) -> Result<(), String> {
+    let state = State::load(root)?;
+    let targets: Vec<Package> = if all {
+        state.active.keys().copied().collect()
+    } else if let Some(p) = package {
+        if !state.active.contains_key(&p) {
+            return Err(format!(
+                "{0} is not active; run `oxidizr-arch use {0}` first",
+                format!("{:?}", p).to_lowercase()
+            ));
+        }
+        vec![p]
+    } else {
+        return Err("specify a package or use --all".to_string());
+    };
+    if targets.is_empty() {
+        println!("[info] update: no active replacements");
+        return Ok(());
+    }
+
+    // Hold the pacman database lock for the whole commit (released around our pacman runs)
+    let _lock = match mode {
+        ApplyMode::Commit => Some(DbLock::acquire(root)?),
+        ApplyMode::DryRun => None,
+    };
+
+    let mut tx = Transaction::new("update", api, root);
+    for p in targets {
+        let res = upgrade(&mut tx, root, p, mode, sync);
+        if let Err(e) =
+            res.and_then(|_| relink(&mut tx, api, root, p, mode, parity, allow_missing.clone()))
+        {
+            return Err(tx.rollback(e));
+        }
+    }
+    Ok(())
+}
+
+/// Upgrade the replacement of `package` when the sync repositories offer a newer version.
+fn upgrade(
+    tx: &mut Transaction,
+    root: &Path,
+    package: Package,
+    mode: ApplyMode,
+    sync: SyncPolicy,
+) -> Result<(), String> {
+    let pm = pm::get();
+    let rs_pkg = replacement_pkg_name(package);
+    let state = State::load(root)?;
+    if state
+        .active
+        .get(&package)
+        .is_some_and(|s| s.use_local.is_some())
+    {
+        println!(
+            "[info] update {}: linked from a local artifact; not upgrading",
+            rs_pkg
+        );
+        return Ok(());
+    }
+    if let Some(pin) = state.pins.get(&package) {
+        println!(
+            "[info] update {}: pinned to {}; not upgrading (drop the pin with `use --unpin`)",
+            rs_pkg, pin.version
+        );
+        return Ok(());
+    }
+    if !pm.manages(root) {
+        eprintln!(
+            "[info] skipping pacman upgrade under non-live root: {} (pass --chroot to run pacman inside it)",
+            root.display()
+        );
+        return Ok(());
+    }
+    let Some(installed) = pm.version(root, rs_pkg) else {
+        return Err(format!(
+            "{} is not installed; run `oxidizr-arch use {}` first",
+            rs_pkg,
+            format!("{:?}", package).to_lowercase()
+        ));
+    };
+    let Some(available) = pm.sync_info(root, rs_pkg) else {
+        println!(
+            "[info] update {}: not in the sync repositories; upgrade it with your AUR helper",
+            rs_pkg
+        );
+        return Ok(());
+    };
+    if vercmp(&available.version, &installed) != Ordering::Greater {
+        println!("[info] update {}: {} is up to date", rs_pkg, installed);
+        return Ok(());
+    }
+
+    match mode {
+        ApplyMode::Commit => {
+            let opts = sync_guard::check(root, rs_pkg, sync)?;
+            pm.install(root, &[rs_pkg], &opts)
+                .map_err(|e| format!("failed to upgrade {}: {}", rs_pkg, e))?;
+            tx.record(Step::Upgraded(vec![RemovedPackage {
+                name: rs_pkg.to_string(),
+                version: Some(installed.clone()),
+            }]));
+            println!(
+                "[OK] update: upgraded {} {} -> {}",
+                rs_pkg, installed, available.version
+            );
+        }
+        ApplyMode::DryRun => match sync_guard::check(root, rs_pkg, sync) {
+            Ok(opts) => eprintln!(
+                "[dry-run] would run: pacman {} ({} -> {})",
+                pacman_args("-S", &[rs_pkg], &opts).join(" "),
+                installed,
+                available.version
+            ),
+            Err(e) => eprintln!("[dry-run] would refuse: {}", e),
+        },
+    }
+    Ok(())
+}
+
+/// Link what the replacement now provides and the state does not record, and restore what
+/// it no longer provides. In dry-run the applets are those of the installed build.
+fn relink(
+    tx: &mut Transaction,
+    api: &Switchyard<JsonlSink, JsonlSink>,
+    root: &Path,
+    package: Package,
+    mode: ApplyMode,
+    parity: ParityLevel,
+    allow_missing: Option<String>,
+) -> Result<(), String> {
+    let mut state = State::load(root)?;
+    let Some(sel) = state.active.get(&package).cloned() else {
+        return Ok(());
+    };
+    let offline = sel.use_local.is_some();
+    let prep = prepare(root, package, offline, sel.use_local.clone(), mode)?;
+
+    // Parity against the links the updated selection will have
+    if sel.replaced {
+        enforce_replace_parity(
+            &ArchAdapter,
+            root,
+            package,
+            parity,
+            offline,
+            &sel.use_local,
+            &prep.linked_names(),
+        )?
+        .report(None);
+    } else {
+        emit_use_parity_summary(
+            root,
+            package,
+            parity,
+            allow_missing,
+            &prep.applets,
+            &prep.skipped,
+            prep.links.len(),
+        );
+    }
+
+    let diff = Diff::between(root, &prep, &sel.links);
+    eprintln!(
+        "{}",
+        json!({
+            "event":"update.exec.diff",
+            "package": format!("{:?}", package),
+            "added": diff.added.iter().map(|(a, _)| a).collect::<Vec<_>>(),
+            "changed": diff.changed.iter().map(|(a, _)| a).collect::<Vec<_>>(),
+            "removed": diff.removed.keys().collect::<Vec<_>>(),
+            "unchanged": prep.links.len() - diff.added.len() - diff.changed.len(),
+        })
+    );
+    let name = format!("{:?}", package).to_lowercase();
+    if diff.is_empty() {
+        println!("[OK] update {}: links are current", name);
+        return Ok(());
+    }
+
+    // Removed applets with a backup are restored by Switchyard; links made without one are
+    // only ours and are deleted
+    let mut restores = Vec::new();
+    let mut unbacked = Vec::new();
+    for link in diff.removed.values() {
+        let target = ensure_under_root(root, &link.target);
+        if link.backup.is_some() {
+            let sp = SafePath::from_rooted(root, &target)
+                .map_err(|e| format!("invalid target: {e:?}"))?;
+            restores.push(RestoreRequest { target: sp });
+        } else {
+            unbacked.push(target);
+        }
+    }
+    let plan = api.plan(PlanInput {
+        link: diff
+            .added
+            .iter()
+            .chain(&diff.changed)
+            .map(|(_, l)| l.clone())
+            .collect(),
+        restore: restores,
+    });
+    let _pre = api
+        .preflight(&plan)
+        .map_err(|e| format!("preflight failed: {e:?}"))?;
+    let rep = api
+        .apply(&plan, mode)
+        .map_err(|e| format!("apply failed: {e:?}"))?;
+
+    let summary = format!(
+        "link {} new applet(s) [{}], re-link {} [{}], restore {} [{}]",
+        diff.added.len(),
+        diff.added
+            .iter()
+            .map(|(a, _)| a.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        diff.changed.len(),
+        diff.changed
+            .iter()
+            .map(|(a, _)| a.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        diff.removed.len(),
+        diff.removed
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    if matches!(mode, ApplyMode::DryRun) {
+        println!("[DRY-RUN] update {}: would {}", name, summary);
+        return Ok(());
+    }
+    if !rep.errors.is_empty() {
+        return Err(format!(
+            "update apply failed: {}{}",
+            rep.errors.join("; "),
+            if rep.rolled_back {
+                " (changes rolled back)"
+            } else {
+                ""
+            }
+        ));
+    }
+    tx.record(Step::Applied(rep));
+    let mut unlinked = Vec::new();
+    for target in &unbacked {
+        if let Ok(dest) = fs::read_link(target) {
+            fs::remove_file(target)
+                .map_err(|e| format!("cannot remove {}: {e}", target.display()))?;
+            unlinked.push((target.clone(), dest));
+        }
+    }
+    tx.record(Step::Unlinked(unlinked));
+
+    // Record the new links; unchanged ones keep their original backup and timestamp
+    let mut links = record_links(root, package, offline, &prep.pairs())?;
+    for (applet, link) in links.iter_mut() {
+        if let Some(old) = sel.links.get(applet).filter(|o| o.source == link.source) {
+            link.backup = old.backup.clone();
+            link.linked_at = old.linked_at;
+        }
+    }
+    tx.record(Step::State(state.clone()));
+    if let Some(s) = state.active.get_mut(&package) {
+        s.links = links;
+    }
+    state.save(root)?;
+    println!("[OK] update {}: {}", name, summary);
+    Ok(())
+}
+
+/// Planned links compared with the recorded ones, by applet name.
+struct Diff {
+    added: Vec<(String, LinkRequest)>,
+    /// Recorded applets whose source moved (e.g. a per-applet `uu-*` binary appeared)
+    changed: Vec<(String, LinkRequest)>,
+    removed: BTreeMap<String, ManagedLink>,
+}
+
+impl Diff {
+    fn between(root: &Path, prep: &Prepared, recorded: &BTreeMap<String, ManagedLink>) -> Self {
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        let mut planned = BTreeSet::new();
+        for link in &prep.links {
+            let Some(applet) = link
+                .target
+                .as_path()
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+            else {
+                continue;
+            };
+            let source = link.source.as_path();
+            match recorded.get(&applet) {
+                None => added.push((applet.clone(), link.clone())),
+                Some(old) if ensure_under_root(root, &old.source) != source => {
+                    changed.push((applet.clone(), link.clone()))
+                }
+                Some(_) => {}
+            }
+            planned.insert(applet);
+        }
+        let removed = recorded
+            .iter()
+            .filter(|(a, _)| !planned.contains(*a))
+            .map(|(a, l)| (a.clone(), l.clone()))
+            .collect();
+        Diff {
+            added,
+            changed,
+            removed,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
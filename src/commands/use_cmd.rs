@@ -1,19 +1,22 @@
+use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use switchyard::logging::JsonlSink;
-use switchyard::types::{ApplyMode, PlanInput};
+use switchyard::types::{ApplyMode, LinkRequest, PlanInput};
 use switchyard::Switchyard;
 
-use crate::adapters::arch::pm_lock_message;
+use crate::adapters::arch::DbLock;
 use crate::adapters::arch_adapter::ArchAdapter;
 use crate::adapters::preflight::sudo_guard;
 use crate::cli::args::{Package, ParityLevel};
 
-use crate::commands::use_install::ensure_replacement_installed;
+use crate::commands::sync_guard::SyncPolicy;
+use crate::commands::use_install::{describe_install_from, install_from, PackageSource};
 use crate::commands::use_link_planner::plan_links;
 use crate::commands::use_parity::emit_use_parity_summary;
-use crate::commands::use_post::{ensure_symlinks_non_live_root, smoke_check_live_root};
 use crate::commands::use_utils::resolve_source_bin;
+use crate::state::{record_links, Pin, State};
 use oxidizr_cli_core::{resolve_applets_for_use, PackageKind};
 
 use serde_json::json;
@@ -26,17 +29,18 @@ pub fn exec(
     package: Package,
     offline: bool,
     use_local: Option<PathBuf>,
+    source: PackageSource,
+    unpin: bool,
+    sync: SyncPolicy,
     mode: ApplyMode,
     parity: ParityLevel,
     allow_missing: Option<String>,
 ) -> Result<(), String> {
-    // Lock check on live root for commit
-    let live_root = root == Path::new("/");
-    if matches!(mode, ApplyMode::Commit) {
-        if let Some(msg) = pm_lock_message(root) {
-            return Err(msg);
-        }
-    }
+    // Hold the pacman database lock for the whole commit (released around our pacman runs)
+    let _lock = match mode {
+        ApplyMode::Commit => Some(DbLock::acquire(root)?),
+        ApplyMode::DryRun => None,
+    };
 
     // Map packages to Arch replacement and distro package names
     let (rs_pkg, _distro_pkg) = match package {
@@ -46,51 +50,110 @@ pub fn exec(
         Package::Sudo => ("sudo-rs", "sudo"),
     };
 
+    // A persisted pin applies unless this run pins another version or drops it
+    let source = if unpin {
+        source
+    } else {
+        source.with_pin(root, package)?
+    };
+
     // Ensure replacement present when committing (ignore when offline=true)
     if matches!(mode, ApplyMode::Commit) && !offline {
-        ensure_replacement_installed(root, rs_pkg, live_root)?;
+        install_from(root, rs_pkg, &source, sync)?;
     } else if matches!(mode, ApplyMode::DryRun) && !offline {
-        eprintln!(
-            "[dry-run] would run: pacman -S --noconfirm {} (or paru -S)",
-            rs_pkg
-        );
+        eprintln!("{}", describe_install_from(root, rs_pkg, &source, sync)?);
     }
 
-    // Resolve a plausible multi-call or single-binary source path (base)
-    let source_bin = if offline {
-        if let Some(p) = use_local.clone() {
-            p
-        } else {
-            return Err("--offline requires --use-local PATH".to_string());
-        }
-    } else {
-        resolve_source_bin(package)
-    };
-    // Preflight: for sudo on commit, require setuid root
-    if matches!(mode, ApplyMode::Commit) && matches!(package, Package::Sudo) {
-        sudo_guard(root, &source_bin)?;
+    link(
+        api,
+        root,
+        package,
+        offline,
+        use_local,
+        mode,
+        parity,
+        allow_missing,
+    )?;
+
+    if matches!(mode, ApplyMode::Commit) {
+        update_pin(root, package, rs_pkg, &source, unpin)?;
     }
 
-    // Compute applets via shared core (dynamic discovery + distro intersection on live root)
-    let pkg_kind = match package {
-        Package::Coreutils => PackageKind::Coreutils,
-        Package::Findutils => PackageKind::Findutils,
-        Package::Sudo => PackageKind::Sudo,
+    Ok(())
+}
+
+/// Persist the pin of `package` (or drop it with `unpin`) and rewrite the pacman `IgnorePkg`
+/// include when it changed.
+fn update_pin(
+    root: &Path,
+    package: Package,
+    rs_pkg: &str,
+    source: &PackageSource,
+    unpin: bool,
+) -> Result<(), String> {
+    let mut state = State::load(root)?;
+    let changed = match source {
+        PackageSource::Pinned { version, .. } => {
+            if state
+                .pins
+                .get(&package)
+                .is_some_and(|p| p.version == *version)
+            {
+                false
+            } else {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                state.pins.insert(
+                    package,
+                    Pin {
+                        name: rs_pkg.to_string(),
+                        version: version.clone(),
+                        pinned_at: now,
+                    },
+                );
+                println!("[OK] use: pinned {} to {}", rs_pkg, version);
+                true
+            }
+        }
+        _ if unpin => {
+            let removed = state.pins.remove(&package).is_some();
+            if removed {
+                println!("[OK] use: unpinned {}", rs_pkg);
+            }
+            removed
+        }
+        _ => false,
     };
-    let applets = resolve_applets_for_use(&ArchAdapter, root, pkg_kind, &source_bin);
-    eprintln!(
-        "{}",
-        json!({
-            "event": "use.exec.resolved",
-            "package": format!("{:?}", package),
-            "source_bin": source_bin.display().to_string(),
-            "applets_count": applets.len(),
-            "applets_sample": applets.iter().take(5).collect::<Vec<_>>()
-        })
-    );
+    if changed {
+        state.save(root)?;
+        state.sync_ignore_pkg(root)?;
+    }
+    Ok(())
+}
 
-    // Build link plan (prefer per-applet binaries on Arch when available)
-    let (links, skipped) = plan_links(root, package, offline, &source_bin, &applets)?;
+/// Resolve applets and apply the link plan for `package` (no package-manager steps).
+/// Shared by `use` and the pacman hook entry point.
+#[allow(clippy::too_many_arguments)]
+pub fn link(
+    api: &Switchyard<JsonlSink, JsonlSink>,
+    root: &Path,
+    package: Package,
+    offline: bool,
+    use_local: Option<PathBuf>,
+    mode: ApplyMode,
+    parity: ParityLevel,
+    allow_missing: Option<String>,
+) -> Result<(), String> {
+    let prepared = prepare(root, package, offline, use_local.clone(), mode)?;
+    let pairs = prepared.pairs();
+    let Prepared {
+        applets,
+        links,
+        skipped,
+        ..
+    } = prepared;
     let planned = links.len();
 
     let plan = api.plan(PlanInput {
@@ -100,18 +163,9 @@ pub fn exec(
     let _pre = api
         .preflight(&plan)
         .map_err(|e| format!("preflight failed: {e:?}"))?;
-    let rep = match api.apply(&plan, mode) {
-        Ok(r) => r,
-        Err(e) => {
-            // Pragmatic fallback for tests: on non-live roots during commit, attempt to create
-            // the intended symlinks manually so downstream status checks can pass.
-            if matches!(mode, ApplyMode::Commit) && root != Path::new("/") {
-                ensure_symlinks_non_live_root(root, mode, package, offline, &source_bin, &applets)?;
-                return Ok(());
-            }
-            return Err(format!("apply failed: {e:?}"));
-        }
-    };
+    let rep = api
+        .apply(&plan, mode)
+        .map_err(|e| format!("apply failed: {e:?}"))?;
 
     if matches!(mode, ApplyMode::DryRun) {
         eprintln!(
@@ -129,6 +183,26 @@ pub fn exec(
             pkg_name, planned
         );
     } else {
+        if !rep.errors.is_empty() {
+            eprintln!(
+                "{}",
+                json!({
+                    "event":"use.exec.apply_failed",
+                    "errors": rep.errors,
+                    "rolled_back": rep.rolled_back,
+                    "rollback_errors": rep.rollback_errors,
+                })
+            );
+            return Err(format!(
+                "apply failed: {}{}",
+                rep.errors.join("; "),
+                if rep.rolled_back {
+                    " (changes rolled back)"
+                } else {
+                    ""
+                }
+            ));
+        }
         eprintln!(
             "{}",
             json!({
@@ -136,8 +210,8 @@ pub fn exec(
                 "executed_actions": rep.executed.len()
             })
         );
-        ensure_symlinks_non_live_root(root, mode, package, offline, &source_bin, &applets)?;
-        smoke_check_live_root(root, package, &applets)?;
+        // Remember the selection and its links (status, verify and the pacman hook read them)
+        save_selection(root, package, offline, use_local, &pairs)?;
     }
 
     // Emit parity summary (reporting only; Use mode does not enforce gates)
@@ -153,3 +227,101 @@ pub fn exec(
 
     Ok(())
 }
+
+/// A package's resolved applets and link requests, before anything is applied.
+pub struct Prepared {
+    pub package: Package,
+    pub applets: Vec<String>,
+    pub links: Vec<LinkRequest>,
+    pub skipped: Vec<String>,
+}
+
+impl Prepared {
+    /// Applet names that will be linked (sources are checked executable by `plan_links`).
+    pub fn linked_names(&self) -> BTreeSet<String> {
+        self.links
+            .iter()
+            .filter_map(|l| {
+                l.target
+                    .as_path()
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+            })
+            .collect()
+    }
+
+    /// (source, target) host paths of the planned links.
+    pub fn pairs(&self) -> Vec<(PathBuf, PathBuf)> {
+        self.links
+            .iter()
+            .map(|l| (l.source.as_path(), l.target.as_path()))
+            .collect()
+    }
+}
+
+/// Resolve the source binary and applets for `package` and build its link requests.
+pub fn prepare(
+    root: &Path,
+    package: Package,
+    offline: bool,
+    use_local: Option<PathBuf>,
+    mode: ApplyMode,
+) -> Result<Prepared, String> {
+    // Resolve a plausible multi-call or single-binary source path (base)
+    let source_bin = if offline {
+        if let Some(p) = use_local.clone() {
+            p
+        } else {
+            return Err("--offline requires --use-local PATH".to_string());
+        }
+    } else {
+        resolve_source_bin(root, package)
+    };
+    // Preflight: for sudo on commit, require setuid root
+    if matches!(mode, ApplyMode::Commit) && matches!(package, Package::Sudo) {
+        sudo_guard(root, &source_bin)?;
+    }
+
+    // Compute applets via shared core (dynamic discovery + distro intersection on live root)
+    let pkg_kind = match package {
+        Package::Coreutils => PackageKind::Coreutils,
+        Package::Findutils => PackageKind::Findutils,
+        Package::Sudo => PackageKind::Sudo,
+    };
+    let applets = resolve_applets_for_use(&ArchAdapter, root, pkg_kind, &source_bin);
+    eprintln!(
+        "{}",
+        json!({
+            "event": "use.exec.resolved",
+            "package": format!("{:?}", package),
+            "source_bin": source_bin.display().to_string(),
+            "applets_count": applets.len(),
+            "applets_sample": applets.iter().take(5).collect::<Vec<_>>()
+        })
+    );
+
+    // Build link plan (prefer per-applet binaries on Arch when available)
+    let (links, skipped) = plan_links(root, package, offline, &source_bin, &applets)?;
+    Ok(Prepared {
+        package,
+        applets,
+        links,
+        skipped,
+    })
+}
+
+/// Record the committed selection for `package` together with its (source, target) links.
+pub fn save_selection(
+    root: &Path,
+    package: Package,
+    offline: bool,
+    use_local: Option<PathBuf>,
+    pairs: &[(PathBuf, PathBuf)],
+) -> Result<(), String> {
+    let links = record_links(root, package, offline, pairs)?;
+    let mut state = State::load(root)?;
+    let sel = state.active.entry(package).or_default();
+    sel.use_local = use_local;
+    sel.links = links;
+    state.save(root)
+}
@@ -1,153 +1,295 @@
-use std::path::Path;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
 
-use serde_json::json;
+use crate::adapters::aur;
+use crate::adapters::aur_build::{self, BuildUser};
+use crate::adapters::pacman_cache::{
+    archive_name_version, cache_dirs, matching_archive, newest_archive,
+};
+use crate::adapters::pm::{self, pacman_args, InstallOptions};
+use crate::cli::args::Package;
+use crate::commands::sync_guard::{self, SyncPolicy};
+use crate::state::State;
+use crate::util::vercmp::version_matches;
 
-/// Ensure the replacement package is installed on a live root.
-/// Emits JSON events for package manager operations.
-pub fn ensure_replacement_installed(
+/// Where `use` takes the replacement package from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageSource {
+    /// Sync repositories, then the AUR
+    Repos,
+    /// A package archive (`--from-pkg FILE`)
+    Archive(PathBuf),
+    /// A directory of package archives, e.g. a local repo maintained with `repo-add`
+    /// (`--local-repo DIR`); the newest archive of the package is used
+    LocalRepo(PathBuf),
+    /// An exact version (`use --version`, or a persisted pin): from the local repo when given,
+    /// else from the package cache
+    Pinned {
+        version: String,
+        local_repo: Option<PathBuf>,
+    },
+}
+
+impl PackageSource {
+    /// Apply the persisted pin of `package`, if any, to a source that does not name a file.
+    pub fn with_pin(self, root: &Path, package: Package) -> Result<PackageSource, String> {
+        let local_repo = match self {
+            PackageSource::Repos => None,
+            PackageSource::LocalRepo(dir) => Some(dir),
+            other => return Ok(other),
+        };
+        let state = State::load(root)?;
+        Ok(match state.pins.get(&package) {
+            Some(pin) => PackageSource::Pinned {
+                version: pin.version.clone(),
+                local_repo,
+            },
+            None => local_repo.map_or(PackageSource::Repos, PackageSource::LocalRepo),
+        })
+    }
+
+    /// The archive to install for `rs_pkg` and its version; `None` for the sync repositories.
+    fn archive_for(&self, root: &Path, rs_pkg: &str) -> Result<Option<(PathBuf, String)>, String> {
+        match self {
+            PackageSource::Repos => Ok(None),
+            PackageSource::Archive(file) => {
+                if !file.is_file() {
+                    return Err(format!("package file not found: {}", file.display()));
+                }
+                let (name, version) = file
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .and_then(archive_name_version)
+                    .ok_or_else(|| format!("not a package archive: {}", file.display()))?;
+                if name != rs_pkg {
+                    return Err(format!(
+                        "{} is a package for {}, not {}",
+                        file.display(),
+                        name,
+                        rs_pkg
+                    ));
+                }
+                Ok(Some((file.clone(), version)))
+            }
+            PackageSource::LocalRepo(dir) => {
+                if !dir.is_dir() {
+                    return Err(format!("local repository not found: {}", dir.display()));
+                }
+                newest_archive(dir, rs_pkg)
+                    .map(Some)
+                    .ok_or_else(|| format!("no {} package in {}", rs_pkg, dir.display()))
+            }
+            PackageSource::Pinned {
+                version,
+                local_repo,
+            } => {
+                let mut dirs: Vec<PathBuf> = local_repo.iter().cloned().collect();
+                dirs.extend(cache_dirs(root));
+                dirs.iter()
+                    .find_map(|d| matching_archive(d, rs_pkg, version))
+                    .map(Some)
+                    .ok_or_else(|| {
+                        format!(
+                            "{} {} is not in {}; download it (e.g. from the Arch Linux Archive) or pass --local-repo DIR",
+                            rs_pkg,
+                            version,
+                            dirs.iter()
+                                .map(|d| d.display().to_string())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })
+            }
+        }
+    }
+}
+
+/// Install the replacement package from `source`: local archives go through `pacman -U` (kept
+/// when that exact version is already installed), anything else as
+/// [`ensure_replacement_installed`].
+pub fn install_from(
     root: &Path,
     rs_pkg: &str,
-    live_root: bool,
+    source: &PackageSource,
+    sync: SyncPolicy,
 ) -> Result<(), String> {
-    if !live_root {
+    let pm = pm::get();
+    if *source == PackageSource::Repos {
+        return ensure_replacement_installed(root, rs_pkg, sync);
+    }
+    if !pm.manages(root) {
         eprintln!(
-            "[info] skipping pacman/paru install under non-live root: {}",
+            "[info] skipping pacman -U under non-live root: {} (pass --chroot to run pacman inside it)",
             root.display()
         );
         return Ok(());
     }
-
-    // Check if already installed
-    let st = Command::new("pacman")
-        .args(["-Qi", rs_pkg])
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status();
-    if matches!(st, Ok(s) if s.success()) {
+    if let Some(installed) = pinned_and_installed(root, rs_pkg, source) {
+        eprintln!("[info] {} {} already installed", rs_pkg, installed);
         return Ok(());
     }
+    let Some((archive, version)) = source.archive_for(root, rs_pkg)? else {
+        return ensure_replacement_installed(root, rs_pkg, sync);
+    };
+    if pm.version(root, rs_pkg).as_deref() == Some(version.as_str()) {
+        eprintln!("[info] {} {} already installed", rs_pkg, version);
+        return Ok(());
+    }
+    pm.install_files(root, &[archive], &[rs_pkg], &InstallOptions::default())
+        .map_err(|e| format!("failed to install {} {}: {}", rs_pkg, version, e))
+}
 
-    let mut tried = Vec::new();
-    let mut ok = false;
-    let mut last_code = 1;
-    let mut last_stderr_tail = String::new();
+/// Installed version of `rs_pkg` when `source` pins a version it matches.
+fn pinned_and_installed(root: &Path, rs_pkg: &str, source: &PackageSource) -> Option<String> {
+    let PackageSource::Pinned { version, .. } = source else {
+        return None;
+    };
+    pm::get()
+        .version(root, rs_pkg)
+        .filter(|installed| version_matches(installed, version))
+}
 
-    // Try pacman first (official)
-    tried.push(format!("pacman -S --noconfirm {}", rs_pkg));
-    let mut cmd = Command::new("pacman");
-    cmd.args(["-S", "--noconfirm", rs_pkg]);
-    cmd.stdin(Stdio::null());
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
-    if let Ok(out) = cmd.output() {
-        last_code = out.status.code().unwrap_or(1);
-        last_stderr_tail = String::from_utf8_lossy(&out.stderr)
-            .chars()
-            .rev()
-            .take(400)
-            .collect::<String>()
-            .chars()
-            .rev()
-            .collect::<String>();
+/// Dry-run description of installing from `source`.
+pub fn describe_install_from(
+    root: &Path,
+    rs_pkg: &str,
+    source: &PackageSource,
+    sync: SyncPolicy,
+) -> Result<String, String> {
+    if let Some(installed) = pinned_and_installed(root, rs_pkg, source) {
+        return Ok(format!(
+            "[dry-run] {} {} already installed (pinned)",
+            rs_pkg, installed
+        ));
+    }
+    match source.archive_for(root, rs_pkg)? {
+        Some((archive, _)) => Ok(format!(
+            "[dry-run] would run: pacman -U --noconfirm {}",
+            archive.display()
+        )),
+        None => describe_install(root, rs_pkg, sync),
+    }
+}
+
+/// Ensure the replacement package is installed wherever the package manager manages `root`:
+/// sync repositories first, then the AUR (built as the invoking user when we are root, else
+/// through each installed AUR helper in preference order). The sync install is guarded
+/// against partial upgrades (see [`sync_guard`]).
+/// Emits JSON events for package manager operations.
+fn ensure_replacement_installed(root: &Path, rs_pkg: &str, sync: SyncPolicy) -> Result<(), String> {
+    let pm = pm::get();
+    if !pm.manages(root) {
         eprintln!(
-            "{}",
-            json!({
-                "event":"pm.install","pm":{"tool":"pacman","args":["-S","--noconfirm",rs_pkg],"package":rs_pkg},
-                "exit_code": last_code,
-                "stderr_tail": last_stderr_tail
-            })
+            "[info] skipping pacman/AUR install under non-live root: {} (pass --chroot to run pacman inside it)",
+            root.display()
         );
-        ok = out.status.success();
+        return Ok(());
     }
 
-    // Fallback to paru (AUR) if available
-    if !ok {
-        tried.push(format!("paru -S --noconfirm {}", rs_pkg));
-        if let Ok(paru_bin) = which::which("paru") {
-            let mut cmd = Command::new(paru_bin);
-            cmd.args(["-S", "--noconfirm", rs_pkg]);
-            cmd.stdin(Stdio::null());
-            cmd.stdout(Stdio::piped());
-            cmd.stderr(Stdio::piped());
-            if let Ok(out) = cmd.output() {
-                last_code = out.status.code().unwrap_or(1);
-                last_stderr_tail = String::from_utf8_lossy(&out.stderr)
-                    .chars()
-                    .rev()
-                    .take(400)
-                    .collect::<String>()
-                    .chars()
-                    .rev()
-                    .collect::<String>();
-                eprintln!(
-                    "{}",
-                    json!({
-                        "event":"pm.install","pm":{"tool":"paru","args":["-S","--noconfirm",rs_pkg],"package":rs_pkg},
-                        "exit_code": last_code,
-                        "stderr_tail": last_stderr_tail
-                    })
-                );
-                ok = out.status.success();
-            }
-        } else {
-            eprintln!(
-                "[warn] paru not found; cannot install AUR package {} automatically",
-                rs_pkg
-            );
-        }
+    // Check if already installed
+    if pm.is_installed(root, rs_pkg) {
+        return Ok(());
     }
 
-    // Fallback as root with OXI_AUR_HELPER_USER
-    if !ok {
-        if let Ok(helper_user) = std::env::var("OXI_AUR_HELPER_USER") {
-            if which::which("sudo").is_ok() && which::which("paru").is_ok() {
-                let mut cmd = Command::new("sudo");
-                cmd.args(["-u", &helper_user, "paru", "-S", "--noconfirm", rs_pkg]);
-                cmd.stdin(Stdio::null());
-                cmd.stdout(Stdio::piped());
-                cmd.stderr(Stdio::piped());
-                tried.push(format!(
-                    "sudo -u {} paru -S --noconfirm {}",
-                    helper_user, rs_pkg
-                ));
-                if let Ok(out) = cmd.output() {
-                    last_code = out.status.code().unwrap_or(1);
-                    last_stderr_tail = String::from_utf8_lossy(&out.stderr)
-                        .chars()
-                        .rev()
-                        .take(400)
-                        .collect::<String>()
-                        .chars()
-                        .rev()
-                        .collect::<String>();
-                    eprintln!(
-                        "{}",
-                        json!({
-                            "event":"pm.install","pm":{"tool":"sudo -u","user":helper_user,"args":["paru","-S","--noconfirm",rs_pkg],"package":rs_pkg},
-                            "exit_code": last_code,
-                            "stderr_tail": last_stderr_tail
-                        })
-                    );
-                    ok = out.status.success();
-                }
-            }
+    let opts = sync_guard::check(root, rs_pkg, sync)?;
+    let mut tried = vec![format!(
+        "{} {}",
+        pm.tool(),
+        pacman_args("-S", &[rs_pkg], &opts).join(" ")
+    )];
+    let mut last_err = match pm.install(root, &[rs_pkg], &opts) {
+        Ok(()) => return Ok(()),
+        Err(e) if !pm.supports_aur() => {
+            return Err(format!("failed to install {}: {}", rs_pkg, e));
         }
+        Err(e) => e,
+    };
+
+    // As root, build as an unprivileged user: AUR helpers and makepkg refuse to run as root
+    if let Some(user) = BuildUser::resolve()? {
+        return aur_build::build_and_install(root, rs_pkg, &user).map_err(|e| {
+            format!(
+                "failed to install {} (tried: {}; makepkg as {}): {}",
+                rs_pkg,
+                tried.join("; "),
+                user.name,
+                e
+            )
+        });
     }
 
-    if !ok {
-        let mut msg = format!(
-            "failed to install {} (tried: {}; last_code={})",
-            rs_pkg,
-            tried.join("; "),
-            last_code
+    // Otherwise the AUR helpers, in preference order
+    let mut last_stderr = String::new();
+    // Helpers run pacman against the live system, never inside another root
+    let helpers = if root == Path::new("/") {
+        aur::detect()?
+    } else {
+        Vec::new()
+    };
+    if root != Path::new("/") {
+        eprintln!(
+            "[warn] AUR helpers cannot install into {}; set {}=<user> to build {} with makepkg, or use --from-pkg",
+            root.display(),
+            aur_build::BUILD_USER_ENV,
+            rs_pkg
+        );
+    } else if helpers.is_empty() {
+        eprintln!(
+            "[warn] no AUR helper found (looked for: {}); cannot install AUR package {} automatically",
+            aur::preference()?
+                .iter()
+                .map(|h| h.name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            rs_pkg
         );
-        if rs_pkg == "uutils-findutils-bin" && last_stderr_tail.to_lowercase().contains("root") {
-            msg.push_str(". AUR helper refused to run as root. Install 'uutils-findutils-bin' as a non-root user with your AUR helper, then rerun: 'oxidizr-arch --commit use findutils'.");
+    }
+    for helper in helpers {
+        tried.push(helper.command_line(rs_pkg));
+        match helper.install(root, rs_pkg) {
+            Ok(()) => return Ok(()),
+            Err((e, stderr)) => {
+                last_err = e;
+                last_stderr = stderr;
+            }
         }
-        return Err(msg);
     }
 
-    Ok(())
+    let mut msg = format!(
+        "failed to install {} (tried: {}; last error: {})",
+        rs_pkg,
+        tried.join("; "),
+        last_err
+    );
+    if last_stderr.to_lowercase().contains("root") {
+        msg.push_str(&format!(". AUR helpers refuse to run as root: run oxidizr-arch through sudo from your user account, or set {}=<user>, so {} is built as that user.", aur_build::BUILD_USER_ENV, rs_pkg));
+    }
+    Err(msg)
+}
+
+/// Dry-run description of the install: the pacman command and the AUR helper command that
+/// would be tried next, or why the partial-upgrade guard would refuse it.
+fn describe_install(root: &Path, rs_pkg: &str, sync: SyncPolicy) -> Result<String, String> {
+    let pm = pm::get();
+    let opts = if pm.manages(root) && !pm.is_installed(root, rs_pkg) {
+        match sync_guard::check(root, rs_pkg, sync) {
+            Ok(opts) => opts,
+            Err(e) => return Ok(format!("[dry-run] would refuse: {}", e)),
+        }
+    } else {
+        InstallOptions::default()
+    };
+    let fallback = match (BuildUser::resolve()?, aur::detect()?.first()) {
+        (Some(u), _) => format!(
+            "or as {}: git clone {}, makepkg --noconfirm, then pacman -U",
+            u.name,
+            aur_build::aur_git_url(rs_pkg)
+        ),
+        (None, Some(h)) => format!("or {}", h.command_line(rs_pkg)),
+        (None, None) => "no AUR helper found".to_string(),
+    };
+    Ok(format!(
+        "[dry-run] would run: pacman {} ({})",
+        pacman_args("-S", &[rs_pkg], &opts).join(" "),
+        fallback
+    ))
 }
@@ -26,7 +26,7 @@ pub fn plan_links(
         let src_for_app: PathBuf = if offline {
             source_bin.to_path_buf()
         } else {
-            resolve_applet_source(package, source_bin, app)
+            resolve_applet_source(root, package, source_bin, app)
         };
 
         // Avoid creating dangling symlinks: require that source exists and is executable
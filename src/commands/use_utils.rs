@@ -1,45 +1,37 @@
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
 
+use crate::adapters::pm;
 use crate::cli::args::Package;
+use crate::util::paths::ensure_under_root;
 
-/// Query pacman filelist for a specific per-applet binary provided by the replacement package.
-pub fn pacman_query_applet(pkg: Package, applet: &str) -> Option<PathBuf> {
-    let pkg_name = match pkg {
+/// Arch replacement package name providing the applets for `pkg`.
+pub fn replacement_pkg_name(pkg: Package) -> &'static str {
+    match pkg {
         Package::Coreutils => "uutils-coreutils",
         // Arch/AUR ships findutils replacement as uutils-findutils-bin
         Package::Findutils => "uutils-findutils-bin",
-        Package::Sudo => return None,
-    };
-    let out = Command::new("pacman")
-        .args(["-Ql", pkg_name])
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()
-        .ok()?;
-    if !out.status.success() {
+        Package::Sudo => "sudo-rs",
+    }
+}
+
+/// Look up a specific per-applet binary in the replacement package's file list.
+pub fn pacman_query_applet(root: &Path, pkg: Package, applet: &str) -> Option<PathBuf> {
+    if matches!(pkg, Package::Sudo) {
         return None;
     }
+    let files = pm::get().list_files(root, replacement_pkg_name(pkg))?;
     let needle = format!("/uu-{}", applet);
-    let s = String::from_utf8_lossy(&out.stdout);
-    for line in s.lines() {
-        if let Some(path) = line.split_whitespace().nth(1) {
-            if path.ends_with(&needle) {
-                let p = PathBuf::from(path);
-                if p.exists() {
-                    return Some(p);
-                }
-            }
-        }
-    }
-    None
+    files
+        .iter()
+        .filter(|path| path.ends_with(&needle))
+        .map(|path| ensure_under_root(root, Path::new(path)))
+        .find(|p| p.exists())
 }
 
 /// Resolve the source binary for a given applet: prefer per-applet 'uu-<applet>' when present;
 /// otherwise fall back to the base dispatcher.
-pub fn resolve_applet_source(pkg: Package, base: &Path, applet: &str) -> PathBuf {
-    if let Some(p) = pacman_query_applet(pkg, applet) {
+pub fn resolve_applet_source(root: &Path, pkg: Package, base: &Path, applet: &str) -> PathBuf {
+    if let Some(p) = pacman_query_applet(root, pkg, applet) {
         return p;
     }
     let candidates: &[&str] = match pkg {
@@ -51,7 +43,7 @@ pub fn resolve_applet_source(pkg: Package, base: &Path, applet: &str) -> PathBuf
         Package::Sudo => &[],
     };
     for prefix in candidates {
-        let p = PathBuf::from(format!("{}{}", prefix, applet));
+        let p = ensure_under_root(root, Path::new(&format!("{}{}", prefix, applet)));
         if p.exists() {
             return p;
         }
@@ -59,37 +51,20 @@ pub fn resolve_applet_source(pkg: Package, base: &Path, applet: &str) -> PathBuf
     base.to_path_buf()
 }
 
-/// Query pacman file list for the dispatcher location of replacement packages.
-pub fn pacman_query_dispatcher(pkg: Package) -> Option<PathBuf> {
-    let pkg_name = match pkg {
-        Package::Coreutils => "uutils-coreutils",
-        Package::Findutils => "uutils-findutils-bin",
-        Package::Sudo => "sudo-rs",
-    };
-    let out = Command::new("pacman")
-        .args(["-Ql", pkg_name])
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()
-        .ok()?;
-    if !out.status.success() {
-        return None;
-    }
-    let stdout = String::from_utf8_lossy(&out.stdout);
+/// Look up the dispatcher location in the replacement package's file list.
+pub fn pacman_query_dispatcher(root: &Path, pkg: Package) -> Option<PathBuf> {
+    let files = pm::get().list_files(root, replacement_pkg_name(pkg))?;
     let suffixes: &[&str] = match pkg {
         Package::Coreutils => &["/uutils", "/coreutils"],
         Package::Findutils => &["/findutils", "/uutils"],
         Package::Sudo => &["/sudo-rs", "/sudo"],
     };
-    for line in stdout.lines() {
-        if let Some(path) = line.split_whitespace().nth(1) {
-            for suf in suffixes {
-                if path.ends_with(suf) {
-                    let p = PathBuf::from(path);
-                    if p.exists() {
-                        return Some(p);
-                    }
+    for path in files.iter() {
+        for suf in suffixes {
+            if path.ends_with(suf) {
+                let p = ensure_under_root(root, Path::new(path));
+                if p.exists() {
+                    return Some(p);
                 }
             }
         }
@@ -98,8 +73,8 @@ pub fn pacman_query_dispatcher(pkg: Package) -> Option<PathBuf> {
 }
 
 /// Resolve a plausible multi-call or single-binary source path (base) for a package.
-pub fn resolve_source_bin(pkg: Package) -> PathBuf {
-    if let Some(p) = pacman_query_dispatcher(pkg) {
+pub fn resolve_source_bin(root: &Path, pkg: Package) -> PathBuf {
+    if let Some(p) = pacman_query_dispatcher(root, pkg) {
         return p;
     }
     let candidates: &[&str] = match pkg {
@@ -116,26 +91,15 @@ pub fn resolve_source_bin(pkg: Package) -> PathBuf {
         Package::Sudo => &["/usr/bin/sudo-rs", "/usr/bin/sudo"],
     };
     for c in candidates {
-        let p = PathBuf::from(c);
+        let p = ensure_under_root(root, Path::new(c));
         if p.exists() {
             return p;
         }
     }
-    match pkg {
-        Package::Coreutils => PathBuf::from("/usr/bin/coreutils"),
-        Package::Findutils => PathBuf::from("/usr/bin/findutils"),
-        Package::Sudo => PathBuf::from("/usr/bin/sudo"),
-    }
-}
-
-/// Check whether a pacman package is installed.
-#[allow(dead_code)]
-pub fn pacman_installed(name: &str) -> bool {
-    let st = Command::new("pacman")
-        .args(["-Qi", name])
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status();
-    matches!(st, Ok(s) if s.success())
+    let fallback = match pkg {
+        Package::Coreutils => "/usr/bin/coreutils",
+        Package::Findutils => "/usr/bin/findutils",
+        Package::Sudo => "/usr/bin/sudo",
+    };
+    ensure_under_root(root, Path::new(fallback))
 }
@@ -0,0 +1,358 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::json;
+use switchyard::logging::JsonlSink;
+use switchyard::types::{Action, ApplyMode, LinkRequest, PlanInput};
+use switchyard::Switchyard;
+
+use crate::adapters::arch_adapter::ArchAdapter;
+use crate::cli::args::Package;
+use crate::commands::use_link_planner::plan_links;
+use crate::commands::use_utils::{resolve_applet_source, resolve_source_bin};
+use crate::state::{record_links, ManagedLink, State};
+use crate::util::paths::{ensure_under_root, strip_root};
+use oxidizr_cli_core::{dest_dir_path, resolve_applets_for_use, PackageKind};
+
+/// Exit code when drift remains (no `--repair`, or a dry-run repair).
+pub const EXIT_DRIFT: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkStatus {
+    Ok,
+    /// Nothing at the target path
+    Missing,
+    /// Symlink whose destination does not exist
+    Dangling,
+    /// Symlink resolving to something other than the expected source
+    PointsElsewhere,
+    /// A regular file (e.g. reinstalled by pacman) replaced our link
+    RegularFile,
+}
+
+impl LinkStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkStatus::Ok => "ok",
+            LinkStatus::Missing => "missing",
+            LinkStatus::Dangling => "dangling",
+            LinkStatus::PointsElsewhere => "points_elsewhere",
+            LinkStatus::RegularFile => "regular_file",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    applet: String,
+    target: PathBuf,
+    expected: PathBuf,
+    status: LinkStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+struct PackageReport {
+    package: Package,
+    entries: Vec<Entry>,
+    drifted: usize,
+    repaired: usize,
+    /// Drifted entries whose source is gone, so `--repair` cannot re-link them
+    unrepairable: usize,
+}
+
+/// One link verify expects, with the request to re-link it when its source still exists.
+struct Expected {
+    target: PathBuf,
+    source: PathBuf,
+    link: Option<LinkRequest>,
+}
+
+/// Classify `target` against the `expected` link source.
+pub fn classify(target: &Path, expected: &Path) -> (LinkStatus, Option<PathBuf>) {
+    let Ok(md) = fs::symlink_metadata(target) else {
+        return (LinkStatus::Missing, None);
+    };
+    if !md.file_type().is_symlink() {
+        return (LinkStatus::RegularFile, None);
+    }
+    let dest = fs::read_link(target).ok();
+    let Ok(actual) = fs::canonicalize(target) else {
+        return (LinkStatus::Dangling, dest);
+    };
+    let want = fs::canonicalize(expected).unwrap_or_else(|_| expected.to_path_buf());
+    if actual == want {
+        (LinkStatus::Ok, None)
+    } else {
+        (LinkStatus::PointsElsewhere, Some(actual))
+    }
+}
+
+/// Whether the symlink at `target` names `source` (relative destinations resolve against the
+/// link's directory, absolute ones under `root`).
+fn links_to(root: &Path, target: &Path, source: &Path) -> bool {
+    let Ok(dest) = fs::read_link(target) else {
+        return false;
+    };
+    let dest = if dest.is_absolute() {
+        ensure_under_root(root, &dest)
+    } else {
+        target.parent().unwrap_or(root).join(dest)
+    };
+    dest == source
+}
+
+/// Expected links for `package`, rebuilt the way `use` plans them. Applets the plan skips
+/// because their source is gone are still expected: at the source recorded in the state file,
+/// or, when never recorded, wherever a link of ours is left pointing.
+fn expected_links(
+    root: &Path,
+    package: Package,
+    use_local: Option<&Path>,
+    recorded: &BTreeMap<String, ManagedLink>,
+) -> Result<Vec<Expected>, String> {
+    let source_bin = match use_local {
+        Some(p) => p.to_path_buf(),
+        None => resolve_source_bin(root, package),
+    };
+    let kind = match package {
+        Package::Coreutils => PackageKind::Coreutils,
+        Package::Findutils => PackageKind::Findutils,
+        Package::Sudo => PackageKind::Sudo,
+    };
+    let applets = resolve_applets_for_use(&ArchAdapter, root, kind, &source_bin);
+    let (links, skipped) = plan_links(root, package, use_local.is_some(), &source_bin, &applets)?;
+    let mut expected: Vec<Expected> = links
+        .into_iter()
+        .map(|l| Expected {
+            target: l.target.as_path(),
+            source: l.source.as_path(),
+            link: Some(l),
+        })
+        .collect();
+
+    let dest_dir = ensure_under_root(root, &dest_dir_path());
+    let unplanned: BTreeSet<&String> = skipped.iter().chain(recorded.keys()).collect();
+    for app in unplanned {
+        let target = dest_dir.join(app);
+        if expected.iter().any(|e| e.target == target) {
+            continue;
+        }
+        let source = match recorded.get(app) {
+            Some(l) => ensure_under_root(root, &l.source),
+            None => {
+                let source = match use_local {
+                    Some(_) => source_bin.clone(),
+                    None => resolve_applet_source(root, package, &source_bin, app),
+                };
+                // Never recorded and not linked to us: the applet was never switched
+                if !links_to(root, &target, &source) {
+                    continue;
+                }
+                source
+            }
+        };
+        expected.push(Expected {
+            target,
+            source,
+            link: None,
+        });
+    }
+    Ok(expected)
+}
+
+/// Compare the expected topology with the filesystem; with `repair`, re-link only drifted
+/// entries. Returns `Ok(true)` when no drift remains.
+pub fn exec(
+    api: &Switchyard<JsonlSink, JsonlSink>,
+    root: &Path,
+    package: Option<Package>,
+    repair: bool,
+    mode: ApplyMode,
+    json_out: bool,
+) -> Result<bool, String> {
+    let mut state = State::load(root)?;
+    let packages: Vec<Package> = match package {
+        Some(p) if state.active.contains_key(&p) => vec![p],
+        Some(p) => {
+            return Err(format!(
+                "{} is not managed by oxidizr-arch (no committed `use`)",
+                format!("{:?}", p).to_lowercase()
+            ))
+        }
+        None => state.active.keys().copied().collect(),
+    };
+
+    let mut reports = Vec::new();
+    let mut clean = true;
+    for pkg in packages {
+        let use_local = state.active[&pkg].use_local.clone();
+        let links = expected_links(root, pkg, use_local.as_deref(), &state.active[&pkg].links)?;
+        let mut entries = Vec::new();
+        let mut drifted_links = Vec::new();
+        let mut unrepairable = 0;
+        for Expected {
+            target,
+            source: expected,
+            link,
+        } in links
+        {
+            let (status, actual) = classify(&target, &expected);
+            if status != LinkStatus::Ok {
+                match link {
+                    Some(link) => drifted_links.push(link),
+                    None => unrepairable += 1,
+                }
+            }
+            entries.push(Entry {
+                applet: target
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                target: strip_root(root, &target),
+                expected: strip_root(root, &expected),
+                status,
+                actual: actual.map(|a| strip_root(root, &a)),
+            });
+        }
+        let drifted = drifted_links.len() + unrepairable;
+
+        let mut repaired = 0;
+        if repair && !drifted_links.is_empty() {
+            // Minimal plan: only the drifted entries
+            let plan = api.plan(PlanInput {
+                link: drifted_links,
+                restore: vec![],
+            });
+            let _pre = api
+                .preflight(&plan)
+                .map_err(|e| format!("preflight failed: {e:?}"))?;
+            let rep = api
+                .apply(&plan, mode)
+                .map_err(|e| format!("apply failed: {e:?}"))?;
+            if !rep.errors.is_empty() {
+                return Err(format!("repair failed: {}", rep.errors.join("; ")));
+            }
+            if matches!(mode, ApplyMode::Commit) {
+                let pairs: Vec<(PathBuf, PathBuf)> = plan
+                    .actions
+                    .iter()
+                    .filter_map(|a| match a {
+                        Action::EnsureSymlink { source, target } => {
+                            Some((source.as_path(), target.as_path()))
+                        }
+                        Action::RestoreFromBackup { .. } => None,
+                    })
+                    .collect();
+                let fresh = record_links(root, pkg, use_local.is_some(), &pairs)?;
+                repaired = fresh.len();
+                if let Some(sel) = state.active.get_mut(&pkg) {
+                    sel.links.extend(fresh);
+                }
+            }
+        }
+        if drifted > repaired {
+            clean = false;
+        }
+        eprintln!(
+            "{}",
+            json!({
+                "event":"verify.result",
+                "package": format!("{:?}", pkg),
+                "checked": entries.len(),
+                "drifted": drifted,
+                "repaired": repaired,
+                "unrepairable": unrepairable,
+            })
+        );
+        reports.push(PackageReport {
+            package: pkg,
+            entries,
+            drifted,
+            repaired,
+            unrepairable,
+        });
+    }
+    if repair && matches!(mode, ApplyMode::Commit) {
+        state.save(root)?;
+    }
+
+    if json_out {
+        println!(
+            "{}",
+            serde_json::to_string(&reports).map_err(|e| e.to_string())?
+        );
+    } else {
+        for r in &reports {
+            let name = format!("{:?}", r.package).to_lowercase();
+            for e in r.entries.iter().filter(|e| e.status != LinkStatus::Ok) {
+                println!(
+                    "[DRIFT] {} {}: {} (expected -> {})",
+                    name,
+                    e.target.display(),
+                    e.status.as_str(),
+                    e.expected.display()
+                );
+            }
+            if r.drifted == 0 {
+                println!("[OK] verify {}: {} link(s) intact", name, r.entries.len());
+            } else if r.repaired > 0 {
+                println!("[OK] verify {}: repaired {} link(s)", name, r.repaired);
+            } else if repair && r.drifted > r.unrepairable {
+                println!(
+                    "[DRY-RUN] verify {}: would repair {} link(s)",
+                    name,
+                    r.drifted - r.unrepairable
+                );
+            }
+            if r.unrepairable > 0 {
+                eprintln!(
+                    "[warn] verify {}: {} link(s) cannot be repaired, their source is gone; reinstall the replacement with `oxidizr-arch --commit use {}`",
+                    name, r.unrepairable, name
+                );
+            }
+        }
+        if reports.is_empty() {
+            println!("[OK] verify: no packages are managed by oxidizr-arch");
+        }
+    }
+    Ok(clean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_link_drift() {
+        let t = tempfile::tempdir().unwrap();
+        let d = t.path();
+        let src = d.join("uu-ls");
+        fs::write(&src, b"bin").unwrap();
+        let other = d.join("other");
+        fs::write(&other, b"bin").unwrap();
+
+        assert_eq!(classify(&d.join("ls"), &src).0, LinkStatus::Missing);
+        std::os::unix::fs::symlink(&src, d.join("ls")).unwrap();
+        assert_eq!(classify(&d.join("ls"), &src).0, LinkStatus::Ok);
+
+        std::os::unix::fs::symlink(&other, d.join("cat")).unwrap();
+        assert_eq!(
+            classify(&d.join("cat"), &src),
+            (
+                LinkStatus::PointsElsewhere,
+                Some(fs::canonicalize(&other).unwrap())
+            )
+        );
+
+        std::os::unix::fs::symlink(d.join("gone"), d.join("mv")).unwrap();
+        assert_eq!(classify(&d.join("mv"), &src).0, LinkStatus::Dangling);
+
+        fs::write(d.join("echo"), b"gnu").unwrap();
+        assert_eq!(classify(&d.join("echo"), &src).0, LinkStatus::RegularFile);
+    }
+}
@@ -0,0 +1,109 @@
+//! Installed and candidate versions of the replacement packages, shared by `status` and
+//! `doctor`.
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::adapters::pm::{self, SyncInfo};
+use crate::cli::args::Package;
+use crate::commands::use_utils::replacement_pkg_name;
+use crate::state::State;
+use crate::util::vercmp::vercmp;
+
+/// Installed version of a replacement package, its pin and what the sync repositories offer.
+/// Every field is always serialized so the `--json` schema does not depend on the system.
+#[derive(Debug, Serialize)]
+pub struct ReplacementVersions {
+    pub package: &'static str,
+    pub installed: Option<String>,
+    pub pinned: Option<String>,
+    /// Candidate from the sync databases
+    pub available: Option<SyncInfo>,
+    /// Installed and older than the candidate
+    pub outdated: bool,
+}
+
+impl ReplacementVersions {
+    /// Candidate version from the sync databases.
+    pub fn candidate(&self) -> Option<&str> {
+        self.available.as_ref().map(|a| a.version.as_str())
+    }
+
+    /// Text hint for an outdated package, pointing at `update` (or at the pin holding it back).
+    pub fn hint(&self, package: Package) -> Option<String> {
+        if !self.outdated {
+            return None;
+        }
+        let name = format!("{:?}", package).to_lowercase();
+        let installed = self.installed.as_deref().unwrap_or_default();
+        let candidate = self.candidate().unwrap_or_default();
+        Some(match &self.pinned {
+            Some(pin) => format!(
+                "{} {} is older than {} but pinned to {}; drop the pin with 'oxidizr-arch --commit use {} --unpin' to upgrade it.",
+                self.package, installed, candidate, pin, name
+            ),
+            None => format!(
+                "{} {} is outdated ({} available); upgrade with 'oxidizr-arch --commit update {}'.",
+                self.package, installed, candidate, name
+            ),
+        })
+    }
+}
+
+/// Versions of every replacement package. The sync databases are only read for roots the
+/// package manager manages.
+pub fn check(root: &Path) -> Vec<(Package, ReplacementVersions)> {
+    let pm = pm::get();
+    let pins = State::load(root).map(|s| s.pins).unwrap_or_default();
+    [Package::Coreutils, Package::Findutils, Package::Sudo]
+        .into_iter()
+        .map(|p| {
+            let name = replacement_pkg_name(p);
+            let installed = pm.version(root, name);
+            let available = pm.manages(root).then(|| pm.sync_info(root, name)).flatten();
+            let outdated = matches!(
+                (&installed, &available),
+                (Some(i), Some(a)) if vercmp(&a.version, i) == Ordering::Greater
+            );
+            let versions = ReplacementVersions {
+                package: name,
+                installed,
+                pinned: pins.get(&p).map(|pin| pin.version.clone()),
+                available,
+                outdated,
+            };
+            (p, versions)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hint_points_at_update_or_pin() {
+        let mut v = ReplacementVersions {
+            package: "uutils-coreutils",
+            installed: Some("0.2.2-1".to_string()),
+            pinned: None,
+            available: Some(SyncInfo {
+                repo: "extra".to_string(),
+                version: "0.2.3-1".to_string(),
+            }),
+            outdated: false,
+        };
+        assert_eq!(v.hint(Package::Coreutils), None);
+        v.outdated = true;
+        assert_eq!(
+            v.hint(Package::Coreutils).unwrap(),
+            "uutils-coreutils 0.2.2-1 is outdated (0.2.3-1 available); upgrade with 'oxidizr-arch --commit update coreutils'."
+        );
+        v.pinned = Some("0.2.2".to_string());
+        assert!(v.hint(Package::Coreutils).unwrap().contains(
+            "pinned to 0.2.2; drop the pin with 'oxidizr-arch --commit use coreutils --unpin'"
+        ));
+    }
+}
@@ -1,6 +1,8 @@
 mod adapters;
 mod cli;
 mod commands;
+mod policy;
+mod state;
 mod util;
 
 use clap::Parser;
@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use switchyard::policy::types::SmokePolicy;
+use switchyard::policy::Policy;
+
+/// Policy configuration file, relative to the target root.
+const POLICY_REL: &str = "etc/oxidizr-arch/policy.toml";
+
+/// Built-in profile names.
+pub const BUILTIN_PROFILES: &[&str] = &["production", "container", "ci"];
+
+/// Knobs a profile adjusts on top of `Policy::coreutils_switch_preset()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ProfileKnobs {
+    /// Allow Commit without the lock manager holding the lock
+    pub allow_unlocked_commit: bool,
+    /// Let apply proceed despite preflight STOP gates
+    pub override_preflight: bool,
+    /// Require rescue tooling (BusyBox or GNU core set) to be present
+    pub require_rescue: bool,
+    /// Require a passing smoke run after Commit
+    pub smoke: bool,
+    /// Roll back automatically when the smoke run fails
+    pub auto_rollback: bool,
+}
+
+impl ProfileKnobs {
+    fn builtin(name: &str) -> Option<Self> {
+        match name {
+            // Fail-closed defaults for a live system
+            "production" => Some(ProfileKnobs {
+                allow_unlocked_commit: false,
+                override_preflight: false,
+                require_rescue: true,
+                smoke: true,
+                auto_rollback: true,
+            }),
+            // Ephemeral containers and staging trees: exercise link swaps without preflight
+            // STOP gates; minimal images may not carry rescue tooling
+            "container" => Some(ProfileKnobs {
+                allow_unlocked_commit: true,
+                override_preflight: true,
+                require_rescue: false,
+                smoke: false,
+                auto_rollback: false,
+            }),
+            // Like container, but a broken swap must fail the job
+            "ci" => Some(ProfileKnobs {
+                allow_unlocked_commit: true,
+                override_preflight: true,
+                require_rescue: false,
+                smoke: true,
+                auto_rollback: true,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// `[profiles.<name>]` table: a custom profile, or overrides for a built-in one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileOverride {
+    base: Option<String>,
+    allow_unlocked_commit: Option<bool>,
+    override_preflight: Option<bool>,
+    require_rescue: Option<bool>,
+    smoke: Option<bool>,
+    auto_rollback: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    profile: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileOverride>,
+}
+
+/// The policy profile in effect for this run and how it was chosen.
+#[derive(Debug, Clone, Serialize)]
+pub struct Profile {
+    pub name: String,
+    pub source: String,
+    pub knobs: ProfileKnobs,
+}
+
+pub fn policy_path(root: &Path) -> PathBuf {
+    root.join(POLICY_REL)
+}
+
+impl Profile {
+    /// Resolve the profile: `--policy` (a profile name or a policy file path), then `profile`
+    /// in `<root>/etc/oxidizr-arch/policy.toml`, then environment detection.
+    pub fn resolve(root: &Path, flag: Option<&str>) -> Result<Self, String> {
+        let path_flag = flag.filter(|f| f.contains('/') || f.ends_with(".toml"));
+        let (file, file_label) = match path_flag {
+            Some(p) => (load_file(Path::new(p))?, p.to_string()),
+            None => {
+                let p = policy_path(root);
+                let file = if p.exists() {
+                    load_file(&p)?
+                } else {
+                    PolicyFile::default()
+                };
+                (file, p.display().to_string())
+            }
+        };
+        let (name, source) = match (flag.filter(|_| path_flag.is_none()), &file.profile) {
+            (Some(n), _) => (n.to_string(), "flag".to_string()),
+            (None, Some(n)) => (n.clone(), format!("config:{}", file_label)),
+            (None, None) => {
+                let (n, why) = detect(root, in_ci(), in_container());
+                (n.to_string(), format!("auto:{}", why))
+            }
+        };
+        let knobs = knobs_for(&file, &name)?;
+        Ok(Profile {
+            name,
+            source,
+            knobs,
+        })
+    }
+
+    /// Adjust `policy` according to this profile's knobs.
+    pub fn apply(&self, policy: &mut Policy) {
+        let k = &self.knobs;
+        policy.governance.allow_unlocked_commit = k.allow_unlocked_commit;
+        policy.apply.override_preflight = k.override_preflight;
+        policy.rescue.require = k.require_rescue;
+        policy.governance.smoke = if k.smoke {
+            SmokePolicy::Require {
+                auto_rollback: k.auto_rollback,
+            }
+        } else {
+            SmokePolicy::Off
+        };
+    }
+}
+
+fn load_file(path: &Path) -> Result<PolicyFile, String> {
+    let s = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read policy file {}: {e}", path.display()))?;
+    toml::from_str(&s).map_err(|e| format!("invalid policy file {}: {e}", path.display()))
+}
+
+fn knobs_for(file: &PolicyFile, name: &str) -> Result<ProfileKnobs, String> {
+    let Some(ov) = file.profiles.get(name) else {
+        return ProfileKnobs::builtin(name).ok_or_else(|| {
+            format!(
+                "unknown policy profile '{}' (built-in: {})",
+                name,
+                BUILTIN_PROFILES.join(", ")
+            )
+        });
+    };
+    let base = ov
+        .base
+        .as_deref()
+        .unwrap_or(if ProfileKnobs::builtin(name).is_some() {
+            name
+        } else {
+            "production"
+        });
+    let mut k = ProfileKnobs::builtin(base).ok_or_else(|| {
+        format!(
+            "policy profile '{}': unknown base '{}' (built-in: {})",
+            name,
+            base,
+            BUILTIN_PROFILES.join(", ")
+        )
+    })?;
+    k.allow_unlocked_commit = ov.allow_unlocked_commit.unwrap_or(k.allow_unlocked_commit);
+    k.override_preflight = ov.override_preflight.unwrap_or(k.override_preflight);
+    k.require_rescue = ov.require_rescue.unwrap_or(k.require_rescue);
+    k.smoke = ov.smoke.unwrap_or(k.smoke);
+    k.auto_rollback = ov.auto_rollback.unwrap_or(k.auto_rollback);
+    Ok(k)
+}
+
+/// Pick a built-in profile from the environment; returns (profile, reason).
+fn detect(root: &Path, ci: bool, container: bool) -> (&'static str, &'static str) {
+    if ci {
+        ("ci", "ci-env")
+    } else if container {
+        ("container", "container-env")
+    } else if root != Path::new("/") {
+        // Staging trees are never the running system
+        ("container", "non-live-root")
+    } else {
+        ("production", "live-root")
+    }
+}
+
+fn in_ci() -> bool {
+    std::env::var("CI").is_ok_and(|v| !v.is_empty() && v != "false" && v != "0")
+}
+
+fn in_container() -> bool {
+    std::env::var_os("container").is_some()
+        || Path::new("/.dockerenv").exists()
+        || Path::new("/run/.containerenv").exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_profile_from_environment() {
+        let live = Path::new("/");
+        assert_eq!(detect(live, false, false), ("production", "live-root"));
+        assert_eq!(detect(live, false, true).0, "container");
+        assert_eq!(detect(live, true, true).0, "ci");
+        assert_eq!(
+            detect(Path::new("/srv/stage"), false, false),
+            ("container", "non-live-root")
+        );
+    }
+
+    #[test]
+    fn test_config_file_selects_and_overrides_profiles() {
+        let t = tempfile::tempdir().unwrap();
+        let p = policy_path(t.path());
+        fs::create_dir_all(p.parent().unwrap()).unwrap();
+        fs::write(
+            &p,
+            "profile = \"lab\"\n\n[profiles.lab]\nbase = \"container\"\nsmoke = true\n",
+        )
+        .unwrap();
+        let prof = Profile::resolve(t.path(), None).unwrap();
+        assert_eq!(prof.name, "lab");
+        assert!(prof.source.starts_with("config:"));
+        assert!(prof.knobs.smoke && prof.knobs.override_preflight);
+
+        // The flag wins over the file
+        let prof = Profile::resolve(t.path(), Some("production")).unwrap();
+        assert_eq!(prof.source, "flag");
+        assert_eq!(prof.knobs, ProfileKnobs::builtin("production").unwrap());
+
+        assert!(Profile::resolve(t.path(), Some("nope")).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::adapters::{ignore_pkg, pacman_db, providers};
+use crate::cli::args::Package;
+use crate::util::paths::{ensure_under_root, strip_root};
+
+/// Location of the persisted state file, relative to the target root.
+const STATE_REL: &str = "var/lib/oxidizr-arch/state.json";
+
+/// Switchyard backup tag, as set by `Policy::coreutils_switch_preset()`.
+pub const BACKUP_TAG: &str = "coreutils";
+
+/// A package whose replacement was activated by a committed `use` (or `replace`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Selection {
+    /// Local artifact when the selection was made with `--offline --use-local`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_local: Option<PathBuf>,
+    /// The distro package was removed by `replace`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replaced: bool,
+    /// Links we created, keyed by applet name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: BTreeMap<String, ManagedLink>,
+}
+
+/// One link under `/usr/bin` created by oxidizr-arch. Paths are absolute inside the root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManagedLink {
+    pub target: PathBuf,
+    pub source: PathBuf,
+    /// Package owning `source`, or `local` for `--use-local` artifacts
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_version: Option<String>,
+    /// SHA-256 of the source binary when linked
+    pub sha256: String,
+    /// Switchyard backup of the replaced file, when one was taken
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+    /// Unix timestamp (seconds)
+    pub linked_at: u64,
+}
+
+/// A replacement package held at a version by `use --version`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pin {
+    /// Replacement package name (the `IgnorePkg` entry)
+    pub name: String,
+    /// Requested version: `pkgver` or `pkgver-pkgrel`
+    pub version: String,
+    /// Unix timestamp (seconds)
+    pub pinned_at: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub active: BTreeMap<Package, Selection>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pins: BTreeMap<Package, Pin>,
+}
+
+pub fn state_path(root: &Path) -> PathBuf {
+    root.join(STATE_REL)
+}
+
+impl State {
+    /// Load the state for `root`; a missing file yields an empty state.
+    pub fn load(root: &Path) -> Result<Self, String> {
+        let p = state_path(root);
+        match fs::read_to_string(&p) {
+            Ok(s) => serde_json::from_str(&s)
+                .map_err(|e| format!("invalid state file {}: {e}", p.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(format!("cannot read state file {}: {e}", p.display())),
+        }
+    }
+
+    /// Persist the state atomically (write to a temp file, then rename).
+    pub fn save(&self, root: &Path) -> Result<(), String> {
+        let p = state_path(root);
+        if let Some(parent) = p.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("cannot create {}: {e}", parent.display()))?;
+        }
+        let tmp = p.with_extension("json.tmp");
+        let body = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(&tmp, body).map_err(|e| format!("cannot write {}: {e}", tmp.display()))?;
+        fs::rename(&tmp, &p).map_err(|e| format!("cannot write {}: {e}", p.display()))
+    }
+
+    /// Rewrite the managed pacman `IgnorePkg` include from the pins.
+    pub fn sync_ignore_pkg(&self, root: &Path) -> Result<(), String> {
+        let names: Vec<&str> = self.pins.values().map(|p| p.name.as_str()).collect();
+        ignore_pkg::sync(root, &names)
+    }
+}
+
+/// Build link records for `(source, target)` pairs (host paths under `root`) just applied.
+pub fn record_links(
+    root: &Path,
+    package: Package,
+    local: bool,
+    pairs: &[(PathBuf, PathBuf)],
+) -> Result<BTreeMap<String, ManagedLink>, String> {
+    let db = pacman_db::open(root);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut hashes: HashMap<PathBuf, String> = HashMap::new();
+    let mut out = BTreeMap::new();
+    for (source, target) in pairs {
+        let Some(applet) = target.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let src_in_root = strip_root(root, source);
+        let sha256 = match hashes.get(source) {
+            Some(h) => h.clone(),
+            None => {
+                let h = sha256_file(source)?;
+                hashes.insert(source.clone(), h.clone());
+                h
+            }
+        };
+        let owner = if local {
+            None
+        } else {
+            let needle = src_in_root.to_string_lossy();
+            providers::provider_candidates(package)
+                .iter()
+                .find(|n| db.files(n).is_some_and(|f| f.iter().any(|p| *p == needle)))
+        };
+        out.insert(
+            applet.to_string(),
+            ManagedLink {
+                target: strip_root(root, target),
+                source: src_in_root,
+                provider: owner.map_or("local", |n| n).to_string(),
+                provider_version: owner.and_then(|n| db.get(n)).map(|p| p.version.clone()),
+                sha256,
+                backup: latest_backup(target).map(|b| strip_root(root, &b)),
+                linked_at: now,
+            },
+        );
+    }
+    Ok(out)
+}
+
+/// Newest Switchyard backup payload (`.<name>.<tag>.<millis>.bak`) next to `target`.
+pub fn latest_backup(target: &Path) -> Option<PathBuf> {
+    let name = target.file_name()?.to_str()?;
+    let prefix = format!(".{}.{}.", name, BACKUP_TAG);
+    let dir = target.parent()?;
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|e| {
+            let n = e.file_name().into_string().ok()?;
+            let ts: u128 = n
+                .strip_prefix(&prefix)?
+                .strip_suffix(".bak")?
+                .parse()
+                .ok()?;
+            Some((ts, e.path()))
+        })
+        .max_by_key(|(ts, _)| *ts)
+        .map(|(_, p)| p)
+}
+
+/// Drop the newest backup of `target` when it only records that the path was absent (a
+/// tombstone, `prior_kind = "none"`) and an older backup exists. Re-linking after pacman
+/// deleted our links leaves such a tombstone, which would otherwise shadow the backup of the
+/// original file on restore. Returns whether a tombstone was removed.
+pub fn drop_tombstone_backup(target: &Path) -> Result<bool, String> {
+    let Some(newest) = latest_backup(target) else {
+        return Ok(false);
+    };
+    let meta = PathBuf::from(format!("{}.meta.json", newest.display()));
+    let kind = fs::read_to_string(&meta)
+        .ok()
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+        .and_then(|v| v["prior_kind"].as_str().map(str::to_string));
+    if kind.as_deref() != Some("none") {
+        return Ok(false);
+    }
+    let prefix = format!(
+        ".{}.{}.",
+        target.file_name().unwrap_or_default().to_string_lossy(),
+        BACKUP_TAG
+    );
+    let older = fs::read_dir(target.parent().unwrap_or(Path::new("/")))
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .any(|e| {
+            let n = e.file_name().to_string_lossy().into_owned();
+            e.path() != newest && n.starts_with(&prefix) && n.ends_with(".bak")
+        });
+    if !older {
+        return Ok(false);
+    }
+    for p in [&newest, &meta] {
+        fs::remove_file(p).map_err(|e| format!("cannot remove {}: {e}", p.display()))?;
+    }
+    Ok(true)
+}
+
+/// Hex SHA-256 of a file's contents (following symlinks).
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut f = fs::File::open(path).map_err(|e| format!("cannot open {}: {e}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = f
+            .read(&mut buf)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+impl ManagedLink {
+    /// Host path of the link under `root`.
+    pub fn target_under(&self, root: &Path) -> PathBuf {
+        ensure_under_root(root, &self.target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_roundtrip_and_missing_file() {
+        let t = tempfile::tempdir().unwrap();
+        let empty = State::load(t.path()).unwrap();
+        assert!(empty.active.is_empty());
+
+        let mut st = State::default();
+        st.active.insert(
+            Package::Coreutils,
+            Selection {
+                use_local: Some(PathBuf::from("/opt/uutils/uutils")),
+                ..Selection::default()
+            },
+        );
+        st.active.insert(Package::Sudo, Selection::default());
+        st.save(t.path()).unwrap();
+
+        let raw = fs::read_to_string(state_path(t.path())).unwrap();
+        assert!(raw.contains("\"coreutils\""));
+        let back = State::load(t.path()).unwrap();
+        assert_eq!(
+            back.active.keys().copied().collect::<Vec<_>>(),
+            vec![Package::Coreutils, Package::Sudo]
+        );
+    }
+
+    #[test]
+    fn test_record_links_captures_provider_hash_and_backup() {
+        use crate::adapters::pacman_db::tests::write_pkg;
+        let t = tempfile::tempdir().unwrap();
+        let root = t.path();
+        write_pkg(root, "uutils-coreutils", "0.2.2-1", "", &["usr/bin/uu-ls"]);
+        let bin = root.join("usr/bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join("uu-ls"), b"abc").unwrap();
+        fs::write(bin.join(".ls.coreutils.100.bak"), b"old").unwrap();
+        fs::write(bin.join(".ls.coreutils.200.bak"), b"older").unwrap();
+
+        let pairs = vec![(bin.join("uu-ls"), bin.join("ls"))];
+        let links = record_links(root, Package::Coreutils, false, &pairs).unwrap();
+        let ls = &links["ls"];
+        assert_eq!(ls.target, PathBuf::from("/usr/bin/ls"));
+        assert_eq!(ls.source, PathBuf::from("/usr/bin/uu-ls"));
+        assert_eq!(ls.provider, "uutils-coreutils");
+        assert_eq!(ls.provider_version.as_deref(), Some("0.2.2-1"));
+        assert_eq!(
+            ls.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            ls.backup,
+            Some(PathBuf::from("/usr/bin/.ls.coreutils.200.bak"))
+        );
+
+        let local = record_links(root, Package::Coreutils, true, &pairs).unwrap();
+        assert_eq!(local["ls"].provider, "local");
+        assert!(local["ls"].provider_version.is_none());
+    }
+
+    #[test]
+    fn test_drop_tombstone_backup_keeps_original() {
+        let t = tempfile::tempdir().unwrap();
+        let target = t.path().join("ls");
+        let orig = t.path().join(".ls.coreutils.100.bak");
+        fs::write(&orig, b"gnu").unwrap();
+        fs::write(
+            t.path().join(".ls.coreutils.100.bak.meta.json"),
+            r#"{"prior_kind":"file"}"#,
+        )
+        .unwrap();
+        assert!(!drop_tombstone_backup(&target).unwrap());
+
+        fs::write(t.path().join(".ls.coreutils.200.bak"), b"").unwrap();
+        fs::write(
+            t.path().join(".ls.coreutils.200.bak.meta.json"),
+            r#"{"prior_kind":"none"}"#,
+        )
+        .unwrap();
+        assert!(drop_tombstone_backup(&target).unwrap());
+        assert_eq!(latest_backup(&target), Some(orig));
+    }
+}
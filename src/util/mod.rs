@@ -1,2 +1,3 @@
 pub mod paths;
 pub mod selinux;
+pub mod vercmp;
@@ -8,3 +8,11 @@ pub fn ensure_under_root(root: &Path, p: &Path) -> PathBuf {
         root.join(p)
     }
 }
+
+/// Inverse of `ensure_under_root`: express `p` as an absolute path inside `root`.
+pub fn strip_root(root: &Path, p: &Path) -> PathBuf {
+    match p.strip_prefix(root) {
+        Ok(rel) => Path::new("/").join(rel),
+        Err(_) => p.to_path_buf(),
+    }
+}
@@ -0,0 +1,147 @@
+//! Package version ordering as pacman's `vercmp(8)`: `[epoch:]pkgver[-pkgrel]`, with
+//! rpmvercmp-style segment comparison of each part.
+
+use std::cmp::Ordering;
+
+/// Compare two package versions.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (ea, va, ra) = split_evr(a);
+    let (eb, vb, rb) = split_evr(b);
+    segments(ea, eb)
+        .then_with(|| segments(va, vb))
+        .then_with(|| match (ra, rb) {
+            // A missing pkgrel matches any pkgrel
+            (Some(ra), Some(rb)) => segments(ra, rb),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Whether `version` (`pkgver-pkgrel`) is the one `spec` asks for: the full version, or just
+/// its `pkgver` (any pkgrel).
+pub fn version_matches(version: &str, spec: &str) -> bool {
+    version == spec
+        || version
+            .strip_prefix(spec)
+            .is_some_and(|rel| rel.starts_with('-') && !rel[1..].contains('-'))
+}
+
+fn split_evr(s: &str) -> (&str, &str, Option<&str>) {
+    let (epoch, rest) = match s.split_once(':') {
+        Some((e, r)) if e.bytes().all(|c| c.is_ascii_digit()) => (e, r),
+        _ => ("0", s),
+    };
+    match rest.rsplit_once('-') {
+        Some((v, r)) => (epoch, v, Some(r)),
+        None => (epoch, rest, None),
+    }
+}
+
+/// rpmvercmp: alternate runs of digits and letters, compared run by run.
+fn segments(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let (sep_i, sep_j) = (i, j);
+        while i < a.len() && !a[i].is_ascii_alphanumeric() {
+            i += 1;
+        }
+        while j < b.len() && !b[j].is_ascii_alphanumeric() {
+            j += 1;
+        }
+        if i >= a.len() || j >= b.len() {
+            break;
+        }
+        // More separators means a newer version ("1..1" > "1.1")
+        if i - sep_i != j - sep_j {
+            return (i - sep_i).cmp(&(j - sep_j));
+        }
+        let numeric = a[i].is_ascii_digit();
+        let run = |s: &[u8], mut k: usize| {
+            let start = k;
+            while k < s.len()
+                && (if numeric {
+                    s[k].is_ascii_digit()
+                } else {
+                    s[k].is_ascii_alphabetic()
+                })
+            {
+                k += 1;
+            }
+            (start, k)
+        };
+        let (si, ei) = run(a, i);
+        let (sj, ej) = run(b, j);
+        i = ei;
+        j = ej;
+        if sj == ej {
+            // Different kinds of run: numbers are newer than letters
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        let (x, y) = (&a[si..ei], &b[sj..ej]);
+        let ord = if numeric {
+            let x = trim_zeros(x);
+            let y = trim_zeros(y);
+            x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+        } else {
+            x.cmp(y)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    let (ta, tb) = (&a[i.min(a.len())..], &b[j.min(b.len())..]);
+    if ta.is_empty() && tb.is_empty() {
+        Ordering::Equal
+    } else if (ta.is_empty() && !tb[0].is_ascii_alphabetic())
+        || (!ta.is_empty() && ta[0].is_ascii_alphabetic())
+    {
+        // "1.0" < "1.0.1", but "1.0a" < "1.0"
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+fn trim_zeros(s: &[u8]) -> &[u8] {
+    let n = s.iter().take_while(|c| **c == b'0').count();
+    &s[n..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vercmp_matches_pacman() {
+        use Ordering::*;
+        for (a, b, want) in [
+            ("0.2.2-1", "0.2.2-1", Equal),
+            ("0.2.2-1", "0.2.10-1", Less),
+            ("0.2.2-2", "0.2.2-1", Greater),
+            ("1:0.1-1", "0.9-1", Greater),
+            ("1.0", "1.0.1", Less),
+            ("1.0a", "1.0", Less),
+            ("1.0alpha", "1.0beta", Less),
+            ("1.001", "1.1", Equal),
+            ("9.5", "9.5-3", Equal),
+            ("1..0", "1.0", Greater),
+        ] {
+            assert_eq!(vercmp(a, b), want, "vercmp({a}, {b})");
+            assert_eq!(vercmp(b, a), want.reverse(), "vercmp({b}, {a})");
+        }
+        assert!(version_matches("0.2.2-1", "0.2.2"));
+        assert!(version_matches("0.2.2-1", "0.2.2-1"));
+        assert!(!version_matches("0.2.2-1", "0.2"));
+        assert!(!version_matches("0.2.20-1", "0.2.2"));
+    }
+}
@@ -27,8 +27,15 @@ async fn main() {
         root.join("tests/features")
     };
 
+    // Scenarios tagged @root drop privileges and only run as root
+    let is_root = {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::metadata("/proc/self").is_ok_and(|m| m.uid() == 0)
+    };
     world::World::cucumber()
         .fail_on_skipped()
-        .run_and_exit(features)
+        .filter_run_and_exit(features, move |_, _, sc| {
+            is_root || !sc.tags.iter().any(|t| t == "root")
+        })
         .await;
 }
@@ -0,0 +1,19 @@
+Feature: Build AUR replacements as an unprivileged user
+  As an Arch operator running oxidizr-arch as root
+  I want AUR replacements built as my user and installed as root
+
+  @root
+  Scenario: commit use builds the package as the build user and installs it with pacman -U
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And the scripted package manager falls back to the AUR
+    And a fake AUR toolchain builds "uutils-findutils-bin" 0.7.0-1 providing applets "find xargs"
+    And the environment variable OXI_AUR_HELPER_USER is set to "nobody"
+    When I run `oxidizr-arch --commit use findutils`
+    Then the command exits 0
+    And stderr contains `"event":"pm.build"`
+    And stderr contains `"user":"nobody"`
+    And the build log contains `building as nobody`
+    And the package manager ran install of "uutils-findutils-bin"
+    And package "uutils-findutils-bin" is installed
+    And `/usr/bin/find` is a symlink
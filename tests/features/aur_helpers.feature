@@ -0,0 +1,19 @@
+Feature: AUR helper selection
+  As an Arch operator
+  I want the AUR fallback to use the helper installed on my machine, in my preferred order
+
+  Scenario: dry-run prints the command for the preferred installed helper
+    Given a staging root at /tmp/fakeroot
+    And an AUR helper "yay" is on PATH
+    And an AUR helper "pamac" is on PATH
+    And the environment variable OXI_AUR_HELPERS is set to "pikaur,pamac,yay"
+    When I run `oxidizr-arch use findutils`
+    Then the command exits 0
+    And stderr contains `[dry-run] would run: pacman -S --noconfirm uutils-findutils-bin (or pamac build --no-confirm uutils-findutils-bin)`
+
+  Scenario: unknown helpers in the preference order are rejected
+    Given a staging root at /tmp/fakeroot
+    And the environment variable OXI_AUR_HELPERS is set to "trizen"
+    When I run `oxidizr-arch use findutils`
+    Then the command exits 1
+    And stderr contains `unknown AUR helper 'trizen' in OXI_AUR_HELPERS`
@@ -0,0 +1,38 @@
+Feature: Package operations inside an image tree
+  As an image builder pointing --root at a pacstrapped tree
+  I want --chroot to run pacman inside that tree instead of skipping package work
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat usr/bin/tee"
+    And a fake pacman on PATH that installs "uutils-coreutils" 0.2.2-1 with applets "ls cat tee"
+
+  Scenario: without --chroot, package operations under a non-live root are skipped
+    When I run `oxidizr-arch --commit use coreutils`
+    Then stderr contains `(pass --chroot to run pacman inside it)`
+    And the fake pacman was not run with `--sysroot $ROOT -S --noconfirm uutils-coreutils`
+
+  Scenario: use and restore run pacman --sysroot inside the tree
+    When I run `oxidizr-arch --commit --chroot use coreutils`
+    Then the command exits 0
+    And the fake pacman was run with `--sysroot $ROOT -S --noconfirm uutils-coreutils`
+    And stderr contains `"sysroot":"`
+    And package "uutils-coreutils" is installed
+    And `/usr/bin/ls` is a symlink
+    When I run `oxidizr-arch --commit --chroot restore coreutils`
+    Then the command exits 0
+    And the fake pacman was run with `--sysroot $ROOT -S --noconfirm coreutils`
+    And the fake pacman was run with `--sysroot $ROOT -R --noconfirm uutils-coreutils`
+    And package "uutils-coreutils" is not installed
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
+
+  Scenario: --chroot=root points pacman at the tree's database and cache
+    When I run `oxidizr-arch --commit --chroot=root use coreutils`
+    Then the command exits 0
+    And the fake pacman was run with `--root $ROOT --dbpath $ROOT/var/lib/pacman --cachedir $ROOT/var/cache/pacman/pkg -S --noconfirm uutils-coreutils`
+
+  Scenario: the database lock is checked inside the tree
+    Given pacman holds the db lock
+    When I run `oxidizr-arch --commit --chroot use coreutils`
+    Then the command exits 1
+    And the fake pacman was not run with `--sysroot $ROOT -S --noconfirm uutils-coreutils`
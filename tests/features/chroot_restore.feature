@@ -0,0 +1,14 @@
+Feature: Commits under a non-live root go through Switchyard
+  As an image builder pointing --root at a pacstrap tree
+  I want commits there to take backups so a later restore works
+
+  Scenario: use then restore inside a staging root brings back the original file
+    Given a staging root at /tmp/fakeroot
+    And a regular file exists at `/usr/bin/ls` with content `gnu-ls`
+    And a verified replacement artifact lists applets "ls echo mv" for package "coreutils"
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    And `/usr/bin/ls` is a symlink to the replacement
+    When I run `oxidizr-arch --commit restore coreutils --keep-replacements`
+    Then the command exits 0
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
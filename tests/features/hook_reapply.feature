@@ -0,0 +1,19 @@
+Feature: pacman hook keeps replacements active across upgrades
+  As an Arch operator
+  I want the selection to survive GNU package upgrades
+
+  Scenario: hook install writes the ALPM hook
+    Given a staging root at /tmp/fakeroot
+    When I run `oxidizr-arch --commit hook install`
+    Then the command exits 0
+    And `/etc/pacman.d/hooks/oxidizr-arch.hook` is a regular file with content `# Managed by oxidizr-arch`
+
+  Scenario: post-transaction re-links applets overwritten by an upgrade
+    Given a staging root at /tmp/fakeroot
+    And a verified replacement artifact lists applets "ls echo mv" for package "coreutils"
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    Given a regular file exists at `/usr/bin/ls` with content `gnu-ls-upgraded`
+    When I run `oxidizr-arch --commit hook post-transaction`
+    Then the command exits 0
+    And `/usr/bin/ls` is a symlink to the replacement
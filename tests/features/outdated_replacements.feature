@@ -0,0 +1,37 @@
+Feature: Report outdated replacements
+  As an Arch operator
+  I want `status` and `doctor` to tell me when a replacement package is out of date
+  So that I know when to run the upgrade flow
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And the sync repositories offer "uutils-coreutils" 0.2.2-1 with dispatcher "usr/bin/coreutils" for applets "ls cat"
+    When I run `oxidizr-arch --commit --require-parity none use coreutils`
+    Then the command exits 0
+
+  Scenario: an up-to-date replacement is not flagged
+    When I run `oxidizr-arch status --json`
+    Then the command exits 0
+    And stdout contains `"coreutils":{"package":"uutils-coreutils","installed":"0.2.2-1","pinned":null,"available":{"repo":"extra","version":"0.2.2-1"},"outdated":false}`
+    And stdout contains `"sudo":{"package":"sudo-rs","installed":null,"pinned":null,"available":null,"outdated":false}`
+    When I run `oxidizr-arch doctor`
+    Then output does not contain `outdated`
+
+  Scenario: a newer candidate in the sync repositories is reported with the update hint
+    Given the sync repositories offer "uutils-coreutils" 0.2.3-1 with dispatcher "usr/bin/coreutils" for applets "ls cat"
+    When I run `oxidizr-arch status --json`
+    Then stdout contains `"installed":"0.2.2-1","pinned":null,"available":{"repo":"extra","version":"0.2.3-1"},"outdated":true`
+    When I run `oxidizr-arch status`
+    Then stdout contains `coreutils: uutils-coreutils 0.2.2-1 is outdated (candidate 0.2.3-1)`
+    And stderr contains `Tip: uutils-coreutils 0.2.2-1 is outdated (0.2.3-1 available); upgrade with 'oxidizr-arch --commit update coreutils'.`
+    When I run `oxidizr-arch doctor --json`
+    Then stdout contains `"available":{"repo":"extra","version":"0.2.3-1"},"outdated":true`
+    When I run `oxidizr-arch doctor`
+    Then stdout contains `Replacement uutils-coreutils: 0.2.2-1 (available: extra/0.2.3-1) (outdated)`
+    And stdout contains `upgrade with 'oxidizr-arch --commit update coreutils'`
+    When I run `oxidizr-arch --commit update coreutils`
+    Then the command exits 0
+    When I run `oxidizr-arch status --json`
+    Then stdout contains `"installed":"0.2.3-1","pinned":null,"available":{"repo":"extra","version":"0.2.3-1"},"outdated":false`
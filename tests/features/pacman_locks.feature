@@ -10,3 +10,35 @@ Feature: Pacman locks prevent commit (Arch)
     When I run `oxidizr-arch --commit use coreutils`
     Then the command exits 1
     And stderr contains `Package manager busy (pacman db.lck detected); retry after current operation finishes.`
+
+  Scenario: pacman's own lock blocks commit and is left alone
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And pacman holds the db lock
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 1
+    And stderr contains `Package manager busy (pacman db.lck detected)`
+    And the pacman db lock is still present
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
+
+  Scenario: commit holds the lock, releases it for its own pacman runs and drops it at the end
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And the sync repositories offer "uutils-coreutils" 0.2.2-1 with files "usr/bin/coreutils usr/bin/uu-ls usr/bin/uu-cat"
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    And the package manager ran install of "uutils-coreutils"
+    And the pacman db lock is released
+
+  Scenario: a lock left by a crashed run is cleaned up
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And the sync repositories offer "uutils-coreutils" 0.2.2-1 with files "usr/bin/coreutils usr/bin/uu-ls usr/bin/uu-cat"
+    And a pacman db lock left behind by a crashed oxidizr-arch
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    And stderr contains `"event":"pm.lock_stale_removed"`
+    And the pacman db lock is released
@@ -0,0 +1,55 @@
+Feature: Guard replacement installs against partial upgrades
+  As an Arch operator
+  I want oxidizr-arch to refuse `pacman -S` when my system is behind the sync databases
+  So that installing a replacement never leaves me with a partial upgrade
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And package "glibc" 2.39-1 is installed with files "usr/lib/libc.so.6"
+    And the sync repositories offer "uutils-coreutils" 0.2.2-1 with files "usr/bin/coreutils usr/bin/uu-ls usr/bin/uu-cat"
+    And the sync databases were refreshed 1 days ago
+
+  Scenario: the install is refused when installed packages are older than the sync databases
+    Given the sync repositories offer "glibc" 2.40-1 with files "usr/lib/libc.so.6"
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 1
+    And stderr contains `"event":"pm.sync_check"`
+    And stderr contains `"decision":"refuse"`
+    And stderr contains `1 installed package(s) are older than the sync databases (glibc 2.39-1 -> 2.40-1)`
+    And stderr contains `--sync-upgrade`
+    And package "uutils-coreutils" is not installed
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
+
+  Scenario: --allow-partial installs against the current databases
+    Given the sync repositories offer "glibc" 2.40-1 with files "usr/lib/libc.so.6"
+    When I run `oxidizr-arch --commit --allow-partial use coreutils`
+    Then the command exits 0
+    And stderr contains `"decision":"allow_partial"`
+    And package "uutils-coreutils" is installed
+    And package "glibc" is installed at version 2.39-1
+
+  Scenario: --sync-upgrade installs with pacman -Syu and upgrades the system
+    Given the sync repositories offer "glibc" 2.40-1 with files "usr/lib/libc.so.6"
+    When I run `oxidizr-arch --commit --sync-upgrade use coreutils`
+    Then the command exits 0
+    And stderr contains `"decision":"sync_upgrade"`
+    And stderr contains `"-Syu"`
+    And package "uutils-coreutils" is installed
+    And package "glibc" is installed at version 2.40-1
+
+  Scenario: stale sync databases are refused, and a dry run says so
+    Given the sync databases were refreshed 30 days ago
+    When I run `oxidizr-arch use coreutils`
+    Then the command exits 0
+    And stderr contains `[dry-run] would refuse: refusing to install uutils-coreutils: the sync databases are 30 days old`
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 1
+    And package "uutils-coreutils" is not installed
+
+  Scenario: an up-to-date system installs normally
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    And stderr contains `"decision":"install"`
+    And package "uutils-coreutils" is installed
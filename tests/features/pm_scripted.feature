@@ -0,0 +1,69 @@
+Feature: Package-manager operations on commit (scripted backend)
+  As a maintainer
+  I want commit-path package operations to run hermetically against a scripted package manager
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And the sync repositories offer "uutils-coreutils" 0.2.2-1 with files "usr/bin/coreutils usr/bin/uu-ls usr/bin/uu-cat"
+    And the sync repositories offer "coreutils" 9.5-1 with files "usr/bin/ls usr/bin/cat"
+
+  Scenario: commit use installs the replacement package
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    And stderr contains `"event":"pm.install"`
+    And stderr contains `"package":"uutils-coreutils"`
+    And the package manager ran install of "uutils-coreutils"
+    And package "uutils-coreutils" is installed
+    And `/usr/bin/ls` is a symlink
+
+  Scenario: a failing install surfaces its exit code
+    Given the package manager fails to install "uutils-coreutils" with exit code 2
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 1
+    And stderr contains `"exit_code":2`
+    And stderr contains `failed to install uutils-coreutils`
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
+
+  Scenario: commit replace swaps the distro package for its shim and keeps the links
+    When I run `oxidizr-arch --commit --require-parity none replace coreutils`
+    Then the command exits 0
+    And package "coreutils" is not installed
+    And package "oxidizr-coreutils-provides" is installed
+    And `/usr/bin/ls` is a symlink
+    And `/usr/bin/cat` is a symlink
+
+  Scenario: replace rolls back when the package swap fails
+    Given the package manager fails to install "oxidizr-coreutils-provides" with exit code 1
+    When I run `oxidizr-arch --commit --require-parity none replace coreutils`
+    Then the command exits 1
+    And stdout contains `[UNDO] replace:`
+    And package "coreutils" is installed
+    And package "uutils-coreutils" is not installed
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
+
+  Scenario: commit restore after replace reinstalls coreutils and removes the replacement
+    When I run `oxidizr-arch --commit --require-parity none replace coreutils`
+    Then the command exits 0
+    When I run `oxidizr-arch --commit restore coreutils`
+    Then the command exits 0
+    And the package manager ran install of "coreutils"
+    And the package manager ran remove of "uutils-coreutils"
+    And package "coreutils" is installed
+    And package "oxidizr-coreutils-provides" is not installed
+    And `/usr/bin/ls` is a regular file
+
+  Scenario: commit restore findutils removes the replacement package use installed
+    Given package "findutils" 4.10.0-1 is installed with files "usr/bin/find usr/bin/xargs"
+    And the sync repositories offer "uutils-findutils-bin" 0.7.0-1 with dispatcher "usr/bin/findutils" for applets "find xargs"
+    And the sync repositories offer "findutils" 4.10.0-1 with files "usr/bin/find usr/bin/xargs"
+    When I run `oxidizr-arch --commit --require-parity none use findutils`
+    Then the command exits 0
+    And package "uutils-findutils-bin" is installed
+    And `/usr/bin/find` is a symlink
+    When I run `oxidizr-arch --commit restore findutils`
+    Then the command exits 0
+    And the package manager ran remove of "uutils-findutils-bin"
+    And package "uutils-findutils-bin" is not installed
+    And `/usr/bin/find` is a regular file
@@ -0,0 +1,23 @@
+Feature: Policy profiles
+  As an Arch operator
+  I want the policy profile in effect to be explicit and reported
+
+  Scenario: doctor reports the profile chosen with --policy
+    Given a staging root at /tmp/fakeroot
+    When I run `oxidizr-arch --policy production doctor --json`
+    Then the command exits 0
+    And stdout contains `"policy_profile":"production"`
+    And stderr contains `"event":"policy.profile"`
+
+  Scenario: profile is read from policy.toml under the root
+    Given a staging root at /tmp/fakeroot
+    And a regular file exists at `/etc/oxidizr-arch/policy.toml` with content `profile = "ci"`
+    When I run `oxidizr-arch doctor --json`
+    Then the command exits 0
+    And stdout contains `"policy_profile":"ci"`
+
+  Scenario: unknown profile is rejected
+    Given a staging root at /tmp/fakeroot
+    When I run `oxidizr-arch --policy nope status`
+    Then the command exits 1
+    And stderr contains `unknown policy profile 'nope'`
@@ -0,0 +1,34 @@
+Feature: Reverse dependencies of replaced distro packages
+  As an Arch operator
+  I want replace to show which installed packages depend on the package it removes
+  and to refuse a removal that would leave a dependency unsatisfied
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And the sync repositories offer "uutils-coreutils" 0.2.2-1 with files "usr/bin/coreutils usr/bin/uu-ls usr/bin/uu-cat"
+
+  Scenario: dependents satisfied by the provides shim are reported
+    Given package "base" 3-2 is installed depending on "coreutils findutils"
+    When I run `oxidizr-arch --commit --require-parity none replace coreutils`
+    Then the command exits 0
+    And stderr contains `[info] replace coreutils: base depends on coreutils (satisfied by oxidizr-coreutils-provides)`
+    And stderr contains `"event":"replace.exec.summary"`
+    And stderr contains `"reverse_dependencies":{"dependents":[{"depends":"coreutils","optional":false,"package":"base","satisfied_by":["oxidizr-coreutils-provides"]}]`
+    And package "coreutils" is not installed
+
+  Scenario: a dependency the shim cannot satisfy blocks the removal
+    Given package "old-tool" 1-1 is installed depending on "coreutils>=10"
+    When I run `oxidizr-arch --commit --require-parity none replace coreutils`
+    Then the command exits 1
+    And stderr contains `[info] replace coreutils: old-tool depends on coreutils>=10 (unsatisfied)`
+    And stderr contains `removing coreutils would leave dependencies unsatisfied: old-tool depends on coreutils>=10`
+    And package "coreutils" is installed
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
+
+  Scenario: dry-run reports the refusal without failing on it
+    Given package "old-tool" 1-1 is installed depending on "coreutils>=10"
+    When I run `oxidizr-arch replace coreutils`
+    Then stdout contains `[DRY-RUN] replace coreutils: old-tool depends on coreutils>=10 (unsatisfied)`
+    And stderr contains `[dry-run] would refuse: removing coreutils would leave dependencies unsatisfied`
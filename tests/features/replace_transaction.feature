@@ -0,0 +1,28 @@
+Feature: Replace runs as one transaction
+  As an Arch operator
+  I want replace to check every package before it changes anything
+
+  Scenario: parity failure leaves the staging root untouched
+    Given a staging root at /tmp/fakeroot
+    And a fakeroot with stock coreutils applets
+    And a verified replacement artifact lists applets "ls echo mv" for package "coreutils"
+    When I run `oxidizr-arch --commit replace coreutils`
+    Then the command exits 1
+    And stderr contains `replace parity(Standard) failed for Coreutils`
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
+
+  Scenario: post-verify failure after the package swap undoes the re-link
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat usr/bin/cp"
+    And the sync repositories offer "uutils-coreutils" 0.2.2-1 with files "usr/bin/uu-ls usr/bin/uu-cat"
+    And the sync repositories offer "coreutils" 9.5-1 with files "usr/bin/ls usr/bin/cat usr/bin/cp"
+    When I run `oxidizr-arch --commit --require-parity none replace coreutils`
+    Then the command exits 1
+    And stderr contains `post-verify failed for Coreutils`
+    And stdout contains `[UNDO] replace: unlinked 2 re-linked path(s)`
+    And package "coreutils" is installed
+    And package "oxidizr-coreutils-provides" is not installed
+    And stderr contains `{"step":"unlink_relinked","targets":["/usr/bin/cat","/usr/bin/ls"]}`
+    And `/usr/bin/ls` is a regular file
+    And `/usr/bin/cp` is a regular file
@@ -0,0 +1,49 @@
+Feature: Clean up cached replacement artifacts after restore
+  As an Arch operator
+  I want `restore` to remove the replacement's cached packages and AUR build directories
+  So that a restored system does not keep carrying them
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And the sync repositories offer "uutils-coreutils" 0.2.2-1 with dispatcher "usr/bin/coreutils" for applets "ls cat"
+    And the sync repositories offer "coreutils" 9.5-1 with files "usr/bin/ls usr/bin/cat"
+    When I run `oxidizr-arch --commit --require-parity none use coreutils`
+    Then the command exits 0
+    Given a regular file exists at `/var/cache/pacman/pkg/uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst` with content `archive`
+    And a regular file exists at `/var/cache/pacman/pkg/uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst.sig` with content `sig`
+    And a regular file exists at `/var/cache/pacman/pkg/coreutils-9.5-1-x86_64.pkg.tar.zst` with content `gnu`
+    And a regular file exists at `/home/alice/.cache/paru/clone/uutils-coreutils/PKGBUILD` with content `pkgname=uutils-coreutils`
+
+  Scenario: dry-run reports the cached artifacts and their sizes
+    When I run `oxidizr-arch restore coreutils`
+    Then the command exits 0
+    And stdout contains `[DRY-RUN] restore: would remove package /var/cache/pacman/pkg/uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst (7 B)`
+    And stdout contains `[DRY-RUN] restore: would remove paru /home/alice/.cache/paru/clone/uutils-coreutils (24 B)`
+    And stdout contains `[DRY-RUN] restore: would free 34 B from cached uutils-coreutils artifacts`
+    And `/var/cache/pacman/pkg/uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst` is a regular file
+
+  Scenario: commit removes them once the replacement is uninstalled
+    When I run `oxidizr-arch --commit restore coreutils`
+    Then the command exits 0
+    And package "uutils-coreutils" is not installed
+    And stdout contains `[OK] restore: removed 3 cached artifact(s) of uutils-coreutils (34 B freed)`
+    And stderr contains `"event":"restore.cache_cleanup"`
+    And `/var/cache/pacman/pkg/uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst` does not exist
+    And `/var/cache/pacman/pkg/uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst.sig` does not exist
+    And `/home/alice/.cache/paru/clone/uutils-coreutils` does not exist
+    And `/var/cache/pacman/pkg/coreutils-9.5-1-x86_64.pkg.tar.zst` is a regular file
+
+  Scenario: --keep-cache leaves the cache alone
+    When I run `oxidizr-arch --commit restore coreutils --keep-cache`
+    Then the command exits 0
+    And stdout contains `[info] restore: keeping 3 cached artifact(s) of uutils-coreutils (34 B) (--keep-cache)`
+    And `/var/cache/pacman/pkg/uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst` is a regular file
+    And `/home/alice/.cache/paru/clone/uutils-coreutils/PKGBUILD` is a regular file
+
+  Scenario: replacements kept installed keep their cache
+    When I run `oxidizr-arch --commit restore coreutils --keep-replacements`
+    Then the command exits 0
+    And output does not contain `cached artifact`
+    And `/var/cache/pacman/pkg/uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst` is a regular file
@@ -0,0 +1,34 @@
+Feature: Restore paths without a backup from the cached package archive
+  As an Arch operator
+  I want `restore` to take the GNU binaries from the package archive when their backups are gone
+  So that a restore never leaves my system pointing at the uninstalled replacement
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And a verified replacement artifact lists applets "ls cat" for package "coreutils"
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    Given the backups in `/usr/bin` are deleted
+
+  Scenario: dry-run names the archive it would extract from
+    Given the package cache holds "coreutils" 9.5-1 with files "usr/bin/ls usr/bin/cat"
+    When I run `oxidizr-arch restore coreutils`
+    Then the command exits 0
+    And stderr contains `[dry-run] restore coreutils: no backup of cat, ls; would extract them from /var/cache/pacman/pkg/coreutils-9.5-1-x86_64.pkg.tar.zst`
+    And `/usr/bin/ls` is a symlink
+
+  Scenario: commit extracts the binaries from the cached archive
+    Given the package cache holds "coreutils" 9.5-1 with files "usr/bin/ls usr/bin/cat"
+    When I run `oxidizr-arch --commit restore coreutils`
+    Then the command exits 0
+    And stdout contains `[OK] restore coreutils: extracted 2 path(s) without a backup from /var/cache/pacman/pkg/coreutils-9.5-1-x86_64.pkg.tar.zst`
+    And stderr contains `"event":"restore.extract"`
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
+    And `/usr/bin/cat` is a regular file with content `gnu-cat`
+
+  Scenario: no backup and no archive refuses before changing anything
+    When I run `oxidizr-arch --commit restore coreutils`
+    Then the command exits 1
+    And stderr contains `no backup of cat, ls and no coreutils 9.5-1 package archive`
+    And `/usr/bin/ls` is a symlink
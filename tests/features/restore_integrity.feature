@@ -0,0 +1,48 @@
+Feature: Verify restored binaries against the package mtree
+  As an Arch operator
+  I want `restore` to check the restored GNU binaries against pacman's mtree checksums
+  So that a restore never leaves me with binaries that are not the packaged files
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat usr/bin/b2sum"
+    And a verified replacement artifact lists applets "ls cat b2sum" for package "coreutils"
+
+  Scenario: restored binaries matching the mtree pass
+    Given the local database has an mtree of "coreutils" matching its files on disk
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    When I run `oxidizr-arch --commit restore coreutils`
+    Then the command exits 0
+    And stdout contains `[OK] restore coreutils: restored files match the package mtree`
+    And stderr contains `"event":"restore.verify"`
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
+
+  Scenario: a modified core binary fails the restore and rolls it back
+    Given the local database has an mtree of "coreutils" matching its files on disk
+    And a regular file exists at `/usr/bin/ls` with content `not-gnu-ls`
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    When I run `oxidizr-arch --commit restore coreutils`
+    Then the command exits 1
+    And stderr contains `[warn] restore coreutils: /usr/bin/ls: Size mismatch (expected 6, found 10), SHA256 checksum mismatch`
+    And stderr contains `restored coreutils binaries do not match the package: /usr/bin/ls`
+    And `/usr/bin/ls` is a symlink
+    And `/usr/bin/cat` is a symlink
+
+  Scenario: a mismatch outside the core binaries is reported without failing
+    Given the local database has an mtree of "coreutils" matching its files on disk
+    And a regular file exists at `/usr/bin/b2sum` with content `patched`
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    When I run `oxidizr-arch --commit restore coreutils`
+    Then the command exits 0
+    And stderr contains `[warn] restore coreutils: /usr/bin/b2sum: Size mismatch (expected 9, found 7), SHA256 checksum mismatch`
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
+
+  Scenario: packages without an mtree skip the check
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    When I run `oxidizr-arch --commit restore coreutils`
+    Then the command exits 0
+    And stderr contains `[info] restore coreutils: no mtree for coreutils in the local database; skipping the integrity check`
@@ -0,0 +1,12 @@
+Feature: Post-apply smoke checks
+  As an Arch operator
+  I want committed swaps verified before they are kept
+
+  Scenario: ci profile runs the structural smoke check on a staging root
+    Given a staging root at /tmp/fakeroot
+    And a verified replacement artifact lists applets "ls echo mv" for package "coreutils"
+    When I run `oxidizr-arch --policy ci --commit use coreutils`
+    Then the command exits 0
+    And stderr contains `"event":"smoke.result"`
+    And stderr contains `"mode":"structural"`
+    And `/usr/bin/ls` is a symlink to the replacement
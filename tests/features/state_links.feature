@@ -0,0 +1,22 @@
+Feature: Managed link state
+  As an Arch operator
+  I want oxidizr-arch to remember exactly which links it created
+
+  Scenario: commit use records each managed link in the state file
+    Given a staging root at /tmp/fakeroot
+    And a verified replacement artifact lists applets "ls echo mv" for package "coreutils"
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    And `/var/lib/oxidizr-arch/state.json` contains `"target": "/usr/bin/ls"`
+    And `/var/lib/oxidizr-arch/state.json` contains `"sha256"`
+    And `/var/lib/oxidizr-arch/state.json` contains `"linked_at"`
+
+  Scenario: status reads the state file after restore
+    Given a staging root at /tmp/fakeroot
+    And a verified replacement artifact lists applets "ls echo mv" for package "coreutils"
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    When I run `oxidizr-arch --commit restore coreutils --keep-replacements`
+    Then the command exits 0
+    When I run `oxidizr-arch status --json`
+    Then stdout contains `"coreutils":"unset"`
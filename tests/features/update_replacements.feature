@@ -0,0 +1,64 @@
+Feature: Update active replacements
+  As an Arch operator
+  I want `update` to upgrade a replacement and link the applets a new release adds
+  without re-linking everything
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat usr/bin/tee usr/bin/b2sum"
+    And the sync repositories offer "uutils-coreutils" 0.2.2-1 with dispatcher "usr/bin/coreutils" for applets "ls cat tee"
+    When I run `oxidizr-arch --commit --require-parity none use coreutils`
+    Then the command exits 0
+
+  Scenario: a new release's applets are linked after the upgrade
+    Given the sync repositories offer "uutils-coreutils" 0.2.3-1 with dispatcher "usr/bin/coreutils" for applets "ls cat tee b2sum"
+    When I run `oxidizr-arch update coreutils`
+    Then the command exits 0
+    And stderr contains `[dry-run] would run: pacman -S --noconfirm uutils-coreutils (0.2.2-1 -> 0.2.3-1)`
+    And `/usr/bin/b2sum` is a regular file with content `gnu-b2sum`
+    When I run `oxidizr-arch --commit update coreutils`
+    Then the command exits 0
+    And stdout contains `[OK] update: upgraded uutils-coreutils 0.2.2-1 -> 0.2.3-1`
+    And stdout contains `[OK] update coreutils: link 1 new applet(s) [b2sum], re-link 0 [], restore 0 []`
+    And stderr contains `"event":"update.exec.diff"`
+    And package "uutils-coreutils" is installed at version 0.2.3-1
+    And `/usr/bin/b2sum` is a symlink
+    And `/usr/bin/ls` is a symlink
+    When I run `oxidizr-arch verify coreutils`
+    Then the command exits 0
+
+  Scenario: applets a release drops are restored
+    Given the sync repositories offer "uutils-coreutils" 0.2.3-1 with dispatcher "usr/bin/coreutils" for applets "ls cat b2sum"
+    When I run `oxidizr-arch --commit update --all`
+    Then the command exits 0
+    And stdout contains `link 1 new applet(s) [b2sum], re-link 0 [], restore 1 [tee]`
+    And `/usr/bin/tee` is a regular file with content `gnu-tee`
+    And `/usr/bin/b2sum` is a symlink
+    When I run `oxidizr-arch verify coreutils`
+    Then the command exits 0
+
+  Scenario: an up-to-date replacement with current links changes nothing
+    When I run `oxidizr-arch --commit update coreutils`
+    Then the command exits 0
+    And stdout contains `[info] update uutils-coreutils: 0.2.2-1 is up to date`
+    And stdout contains `[OK] update coreutils: links are current`
+
+  Scenario: updating an inactive package is an error
+    When I run `oxidizr-arch update sudo`
+    Then the command exits 1
+    And stderr contains `sudo is not active; run `
+
+  Scenario: a parity failure after the upgrade downgrades the replacement again
+    When I run `oxidizr-arch --commit --require-parity none replace coreutils`
+    Then the command exits 0
+    Given the package cache holds "uutils-coreutils" 0.2.2-1 with files "usr/bin/coreutils"
+    And the sync repositories offer "uutils-coreutils" 0.2.3-1 with dispatcher "usr/bin/coreutils" for applets "ls cat tee b2sum"
+    When I run `oxidizr-arch --commit update coreutils`
+    Then the command exits 1
+    And stdout contains `[OK] update: upgraded uutils-coreutils 0.2.2-1 -> 0.2.3-1`
+    And stderr contains `replace parity(Standard) failed for Coreutils`
+    And stdout contains `[UNDO] update: downgraded to uutils-coreutils 0.2.2-1`
+    And package "uutils-coreutils" is installed at version 0.2.2-1
+    And `/usr/bin/ls` is a symlink
+    And `/usr/bin/b2sum` does not exist
@@ -0,0 +1,40 @@
+Feature: Install replacements from local package archives
+  As an operator of air-gapped machines
+  I want `use` to install the replacement from a package file or a local package directory
+  So that it is tracked by pacman like an online install
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And a package archive "uutils-coreutils" 0.2.0-1 with dispatcher "usr/bin/coreutils" for applets "ls cat" in `/srv/pkgs`
+    And a package archive "uutils-coreutils" 0.2.2-1 with dispatcher "usr/bin/coreutils" for applets "ls cat" in `/srv/pkgs`
+    And a package archive "sudo-rs" 0.2.4-1 with dispatcher "usr/bin/sudo-rs" for applets "sudo" in `/srv/pkgs`
+
+  Scenario: commit use --from-pkg installs the archive with pacman -U and links the applets
+    When I run `oxidizr-arch --commit --require-parity none use coreutils --from-pkg $ROOT/srv/pkgs/uutils-coreutils-0.2.0-1-x86_64.pkg.tar.zst`
+    Then the command exits 0
+    And stderr contains `"event":"pm.install"`
+    And stderr contains `"-U"`
+    And the package manager ran install of "uutils-coreutils"
+    And package "uutils-coreutils" is installed
+    And `/usr/bin/ls` is a symlink
+    And `/usr/bin/cat` is a symlink
+
+  Scenario: --local-repo picks the newest archive of the replacement
+    When I run `oxidizr-arch use coreutils --local-repo $ROOT/srv/pkgs`
+    Then the command exits 0
+    And stderr contains `pacman -U --noconfirm`
+    And stderr contains `uutils-coreutils-0.2.2-1-x86_64.pkg.tar.zst`
+
+  Scenario: commit use --local-repo installs the replacement
+    When I run `oxidizr-arch --commit --require-parity none use coreutils --local-repo $ROOT/srv/pkgs`
+    Then the command exits 0
+    And package "uutils-coreutils" is installed
+    And `/usr/bin/ls` is a symlink
+
+  Scenario: an archive of another package is rejected
+    When I run `oxidizr-arch --commit use coreutils --from-pkg $ROOT/srv/pkgs/sudo-rs-0.2.4-1-x86_64.pkg.tar.zst`
+    Then the command exits 1
+    And stderr contains `is a package for sudo-rs, not uutils-coreutils`
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
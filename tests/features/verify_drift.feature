@@ -0,0 +1,44 @@
+Feature: Verify managed links
+  As an Arch operator
+  I want to detect and repair drift in the links oxidizr-arch manages
+
+  Scenario: verify reports a link replaced by a regular file and repairs it
+    Given a staging root at /tmp/fakeroot
+    And a verified replacement artifact lists applets "ls echo mv" for package "coreutils"
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    When I run `oxidizr-arch verify coreutils`
+    Then the command exits 0
+    And stdout contains `[OK] verify coreutils`
+    Given a regular file exists at `/usr/bin/ls` with content `gnu`
+    When I run `oxidizr-arch verify coreutils --json`
+    Then the command exits 2
+    And stdout contains `"status":"regular_file"`
+    When I run `oxidizr-arch verify coreutils --repair`
+    Then the command exits 2
+    And `/usr/bin/ls` is a regular file
+    When I run `oxidizr-arch --commit verify coreutils --repair`
+    Then the command exits 0
+    And `/usr/bin/ls` is a symlink to the replacement
+    When I run `oxidizr-arch verify`
+    Then the command exits 0
+
+  Scenario: verify reports links left dangling by a deleted replacement binary
+    Given a staging root at /tmp/fakeroot
+    And a verified replacement artifact lists applets "ls echo mv" for package "coreutils"
+    When I run `oxidizr-arch --commit use coreutils`
+    Then the command exits 0
+    Given the replacement artifact is deleted
+    When I run `oxidizr-arch verify coreutils --json`
+    Then the command exits 2
+    And stdout contains `"status":"dangling"`
+    And stdout contains `"unrepairable":3`
+    When I run `oxidizr-arch --commit verify coreutils --repair`
+    Then the command exits 2
+    And stderr contains `[warn] verify coreutils: 3 link(s) cannot be repaired`
+    And `/usr/bin/ls` is a symlink
+
+  Scenario: verify rejects packages that are not managed
+    Given a staging root at /tmp/fakeroot
+    When I run `oxidizr-arch verify sudo`
+    Then the command exits 1
@@ -0,0 +1,44 @@
+Feature: Pin replacement packages to a version
+  As a fleet operator
+  I want `use --version` to install an exact replacement version and keep it
+  So that a regressing upstream release does not reach my machines
+
+  Background:
+    Given a staging root at /tmp/fakeroot
+    And a scripted package manager
+    And package "coreutils" 9.5-1 is installed with files "usr/bin/ls usr/bin/cat"
+    And a regular file exists at `/etc/pacman.conf` with content `[options]`
+    And the sync repositories offer "uutils-coreutils" 0.2.3-1 with files "usr/bin/coreutils"
+    And a package archive "uutils-coreutils" 0.2.2-1 with dispatcher "usr/bin/coreutils" for applets "ls cat" in `/var/cache/pacman/pkg`
+    And a package archive "uutils-coreutils" 0.2.3-1 with dispatcher "usr/bin/coreutils" for applets "ls cat" in `/var/cache/pacman/pkg`
+
+  Scenario: use --version installs the pinned version from the cache and holds it back
+    When I run `oxidizr-arch --commit --require-parity none use coreutils --version 0.2.2`
+    Then the command exits 0
+    And stdout contains `[OK] use: pinned uutils-coreutils to 0.2.2`
+    And package "uutils-coreutils" is installed at version 0.2.2-1
+    And `/usr/bin/ls` is a symlink
+    And `/etc/pacman.d/oxidizr-arch-ignore.conf` contains `IgnorePkg = uutils-coreutils`
+    And `/etc/pacman.conf` contains `Include = /etc/pacman.d/oxidizr-arch-ignore.conf`
+    When I run `oxidizr-arch status`
+    Then stdout contains `uutils-coreutils pinned to 0.2.2 (installed 0.2.2-1, available extra/0.2.3-1)`
+    When I run `oxidizr-arch doctor --json`
+    Then stdout contains `"pinned":"0.2.2"`
+
+  Scenario: the pin persists until it is dropped
+    When I run `oxidizr-arch --commit --require-parity none use coreutils --version 0.2.2`
+    Then the command exits 0
+    When I run `oxidizr-arch use coreutils`
+    Then stderr contains `uutils-coreutils 0.2.2-1 already installed (pinned)`
+    When I run `oxidizr-arch --commit --require-parity none use coreutils --unpin`
+    Then the command exits 0
+    And stdout contains `[OK] use: unpinned uutils-coreutils`
+    And `/etc/pacman.d/oxidizr-arch-ignore.conf` is a regular file
+    When I run `oxidizr-arch doctor --json`
+    Then output does not contain `"pinned":"0.2.2"`
+
+  Scenario: a version missing from the cache is an error
+    When I run `oxidizr-arch --commit use coreutils --version 0.1.0`
+    Then the command exits 1
+    And stderr contains `uutils-coreutils 0.1.0 is not in`
+    And `/usr/bin/ls` is a regular file with content `gnu-ls`
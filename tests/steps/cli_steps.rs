@@ -5,7 +5,11 @@ use crate::world::World;
 
 #[when(regex = r"^I run `oxidizr-arch (.+)`$")]
 pub async fn i_run_oxidizr_arch(world: &mut World, cmd: String) {
-    let args: Vec<String> = Shlex::new(&cmd).collect();
+    // `$ROOT` stands for the staging root, for host paths of files staged under it
+    let root = world.ensure_root().display().to_string();
+    let args: Vec<String> = Shlex::new(&cmd)
+        .map(|a| a.replace("$ROOT", &root))
+        .collect();
     let mut final_args: Vec<String> = Vec::new();
 
     // Inject --root if missing
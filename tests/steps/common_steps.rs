@@ -94,3 +94,19 @@ pub async fn sudo_artifact_setuid(world: &mut World) {
         let _ = std::fs::set_permissions(&abs, perms);
     }
 }
+
+#[given(regex = r"^the backups in `(/.+)` are deleted$")]
+pub async fn delete_backups(world: &mut World, dir: String) {
+    for e in std::fs::read_dir(world.under_root(&dir)).unwrap().flatten() {
+        let name = e.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') && (name.ends_with(".bak") || name.ends_with(".bak.meta.json")) {
+            std::fs::remove_file(e.path()).unwrap();
+        }
+    }
+}
+
+#[given(regex = r"^the replacement artifact is deleted$")]
+pub async fn delete_artifact(world: &mut World) {
+    let rel = world.artifact_path.clone().expect("a replacement artifact");
+    std::fs::remove_file(world.under_root(&rel)).unwrap();
+}
@@ -32,6 +32,16 @@ pub async fn path_is_regular(world: &mut World, path: String) {
     );
 }
 
+#[then(regex = r"^`(/.+)` does not exist$")]
+pub async fn path_does_not_exist(world: &mut World, path: String) {
+    let abs = world.under_root(&path);
+    assert!(
+        fs::symlink_metadata(&abs).is_err(),
+        "expected nothing at {}",
+        abs.display()
+    );
+}
+
 #[then(regex = r"^`(/.+)` is a symlink$")]
 pub async fn path_is_symlink(world: &mut World, path: String) {
     let abs = world.under_root(&path);
@@ -67,3 +77,16 @@ pub async fn path_is_symlink_to_replacement(world: &mut World, path: String) {
         expected_abs.display()
     );
 }
+
+#[then(regex = r"^`(/.+)` contains `(.+)`$")]
+pub async fn file_contains(world: &mut World, path: String, needle: String) {
+    let abs = world.under_root(&path);
+    let s = fs::read_to_string(&abs).expect("read file");
+    assert!(
+        s.contains(&needle),
+        "expected {} to contain {:?}, got:\n{}",
+        abs.display(),
+        needle,
+        s
+    );
+}
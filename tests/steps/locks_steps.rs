@@ -1,4 +1,4 @@
-use cucumber::given;
+use cucumber::{given, then};
 use fs2::FileExt;
 use std::fs::OpenOptions;
 
@@ -19,3 +19,26 @@ pub async fn pacman_db_lock_held(world: &mut World) {
     f.lock_exclusive().unwrap();
     world.pacman_lock = Some(f);
 }
+
+#[given(regex = r"^a pacman db lock left behind by a crashed oxidizr-arch$")]
+pub async fn stale_db_lock(world: &mut World) {
+    // No process can have this PID
+    world.write_file("/var/lib/pacman/db.lck", b"4294967295\n", false);
+}
+
+#[given(regex = r"^pacman holds the db lock$")]
+pub async fn pacman_db_lock_file(world: &mut World) {
+    // pacman's lock is an empty file that exists for the length of its transaction
+    world.write_file("/var/lib/pacman/db.lck", b"", false);
+}
+
+#[then(regex = r"^the pacman db lock is (released|still present)$")]
+pub async fn db_lock_state(world: &mut World, state: String) {
+    let present = world.under_root("/var/lib/pacman/db.lck").exists();
+    assert_eq!(
+        present,
+        state == "still present",
+        "db.lck present: {}",
+        present
+    );
+}
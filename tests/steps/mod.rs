@@ -3,3 +3,4 @@ pub mod cli_steps;
 pub mod common_steps;
 pub mod fs_steps;
 pub mod locks_steps;
+pub mod pm_steps;
@@ -0,0 +1,434 @@
+use cucumber::{given, then};
+use serde_json::{json, Value};
+
+use crate::world::World;
+
+fn split(list: &str) -> Vec<String> {
+    list.split_whitespace().map(str::to_string).collect()
+}
+
+/// Read-modify-write the scripted package manager's JSON file.
+fn edit_script(world: &mut World, f: impl FnOnce(&mut Value)) {
+    let path = world.pm_script.clone().expect("a scripted package manager");
+    let mut script: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    f(&mut script);
+    std::fs::write(&path, serde_json::to_string_pretty(&script).unwrap()).unwrap();
+}
+
+fn read_script(world: &World) -> Value {
+    let path = world
+        .pm_script
+        .as_ref()
+        .expect("a scripted package manager");
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[given(regex = r"^a scripted package manager$")]
+pub async fn scripted_package_manager(world: &mut World) {
+    let path = world.under_root("/pm-script.json");
+    std::fs::write(&path, r#"{"sync": {}, "fail": []}"#).unwrap();
+    world
+        .envs
+        .push(("OXI_PM_SCRIPT".into(), path.display().to_string()));
+    world.pm_script = Some(path);
+}
+
+#[given(regex = r#"^the sync repositories offer "([^"]+)" ([^ ]+) with files "([^"]*)"$"#)]
+pub async fn sync_offers(world: &mut World, name: String, version: String, files: String) {
+    edit_script(world, |s| {
+        s["sync"][name] = json!({"version": version, "files": split(&files)});
+    });
+}
+
+#[given(
+    regex = r#"^the sync repositories offer "([^"]+)" ([^ ]+) with dispatcher "([^"]+)" for applets "([^"]+)"$"#
+)]
+pub async fn sync_offers_dispatcher(
+    world: &mut World,
+    name: String,
+    version: String,
+    dispatcher: String,
+    applets: String,
+) {
+    let script = format!("#!/bin/sh\necho {}\n", applets);
+    edit_script(world, |s| {
+        s["sync"][name] = json!({
+            "version": version,
+            "files": [dispatcher],
+            "contents": {dispatcher.as_str(): script},
+        });
+    });
+}
+
+#[given(regex = r#"^package "([^"]+)" ([^ ]+) is installed with files "([^"]*)"$"#)]
+pub async fn package_installed(world: &mut World, name: String, version: String, files: String) {
+    let files = split(&files);
+    let dir = world.under_root(format!("/var/lib/pacman/local/{}-{}", name, version));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("desc"),
+        format!("%NAME%\n{}\n\n%VERSION%\n{}\n\n", name, version),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("files"),
+        format!("%FILES%\n{}\n", files.join("\n")),
+    )
+    .unwrap();
+    for f in &files {
+        let base = f.rsplit('/').next().unwrap();
+        world.write_file(format!("/{}", f), format!("gnu-{}", base).as_bytes(), true);
+    }
+}
+
+#[given(regex = r#"^the local database has an mtree of "([^"]+)" matching its files on disk$"#)]
+pub async fn package_mtree(world: &mut World, name: String) {
+    use sha2::{Digest, Sha256};
+    use std::io::Write as _;
+    use std::os::unix::fs::PermissionsExt;
+
+    let local = world.under_root("/var/lib/pacman/local");
+    let dir = std::fs::read_dir(&local)
+        .unwrap()
+        .flatten()
+        .map(|e| e.path())
+        .find(|d| {
+            std::fs::read_to_string(d.join("desc"))
+                .is_ok_and(|s| s.starts_with(&format!("%NAME%\n{}\n", name)))
+        })
+        .expect("package in the local database");
+    let files = std::fs::read_to_string(dir.join("files")).unwrap();
+    let mut mtree = String::from("#mtree\n/set type=file uid=0 gid=0 mode=644\n");
+    for f in files.lines().skip(1).filter(|l| !l.is_empty()) {
+        let abs = world.under_root(format!("/{}", f));
+        let data = std::fs::read(&abs).unwrap();
+        let mode = std::fs::metadata(&abs).unwrap().permissions().mode() & 0o7777;
+        let digest: String = Sha256::digest(&data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        mtree.push_str(&format!(
+            "./{} time=0 mode={:o} size={} sha256digest={}\n",
+            f,
+            mode,
+            data.len(),
+            digest
+        ));
+    }
+    let mut gz = flate2::write::GzEncoder::new(
+        std::fs::File::create(dir.join("mtree")).unwrap(),
+        flate2::Compression::default(),
+    );
+    gz.write_all(mtree.as_bytes()).unwrap();
+    gz.finish().unwrap();
+}
+
+#[given(regex = r#"^the package cache holds "([^"]+)" ([^ ]+) with files "([^"]*)"$"#)]
+pub async fn package_cache_archive(
+    world: &mut World,
+    name: String,
+    version: String,
+    files: String,
+) {
+    let dir = world.under_root("/var/cache/pacman/pkg");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}-{}-x86_64.pkg.tar.zst", name, version));
+    let enc = zstd::Encoder::new(std::fs::File::create(path).unwrap(), 0).unwrap();
+    let mut ar = tar::Builder::new(enc);
+    let pkginfo = format!("pkgname = {}\npkgver = {}\n", name, version);
+    let mut members = vec![(".PKGINFO".to_string(), pkginfo, 0o644)];
+    for f in split(&files) {
+        let base = f.rsplit('/').next().unwrap().to_string();
+        members.push((f, format!("gnu-{}", base), 0o755));
+    }
+    for (member, data, mode) in members {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(mode);
+        header.set_cksum();
+        ar.append_data(&mut header, member, data.as_bytes())
+            .unwrap();
+    }
+    ar.into_inner().unwrap().finish().unwrap();
+}
+
+#[given(regex = r#"^package "([^"]+)" ([^ ]+) is installed depending on "([^"]*)"$"#)]
+pub async fn package_installed_depending(
+    world: &mut World,
+    name: String,
+    version: String,
+    deps: String,
+) {
+    let dir = world.under_root(format!("/var/lib/pacman/local/{}-{}", name, version));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("desc"),
+        format!(
+            "%NAME%\n{}\n\n%VERSION%\n{}\n\n%DEPENDS%\n{}\n\n",
+            name,
+            version,
+            split(&deps).join("\n")
+        ),
+    )
+    .unwrap();
+    std::fs::write(dir.join("files"), "%FILES%\n").unwrap();
+}
+
+#[given(
+    regex = r#"^the package manager fails to (install|remove) "([^"]+)" with exit code (\d+)$"#
+)]
+pub async fn pm_fails(world: &mut World, op: String, name: String, code: i32) {
+    edit_script(world, |s| {
+        s["fail"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({"op": op, "package": name, "exit_code": code}));
+    });
+}
+
+#[then(regex = r#"^the package manager ran (install|remove) of "([^"]+)"$"#)]
+pub async fn pm_ran(world: &mut World, op: String, name: String) {
+    let script = read_script(world);
+    let log = script["log"].as_array().cloned().unwrap_or_default();
+    assert!(
+        log.iter().any(|e| e["op"] == op.as_str()
+            && e["packages"]
+                .as_array()
+                .is_some_and(|p| p.iter().any(|p| p == name.as_str()))),
+        "expected {} of {} in the package manager log: {:#?}",
+        op,
+        name,
+        log
+    );
+}
+
+#[then(regex = r#"^package "([^"]+)" is (installed|not installed)$"#)]
+pub async fn package_state(world: &mut World, name: String, state: String) {
+    let dir = world.under_root("/var/lib/pacman/local");
+    let installed = std::fs::read_dir(&dir)
+        .map(|rd| {
+            rd.flatten().any(|e| {
+                std::fs::read_to_string(e.path().join("desc"))
+                    .is_ok_and(|d| d.starts_with(&format!("%NAME%\n{}\n", name)))
+            })
+        })
+        .unwrap_or(false);
+    assert_eq!(
+        installed,
+        state == "installed",
+        "expected package {} to be {}",
+        name,
+        state
+    );
+}
+
+#[then(regex = r#"^package "([^"]+)" is installed at version ([^ ]+)$"#)]
+pub async fn package_version(world: &mut World, name: String, version: String) {
+    let desc = world.under_root(format!("/var/lib/pacman/local/{}-{}/desc", name, version));
+    assert!(
+        std::fs::read_to_string(&desc).is_ok_and(|d| d.starts_with(&format!("%NAME%\n{}\n", name))),
+        "expected {} {} in the local database",
+        name,
+        version
+    );
+}
+
+#[given(regex = r"^the sync databases were refreshed (\d+) days ago$")]
+pub async fn sync_db_refreshed(world: &mut World, days: u64) {
+    let db = world.under_root("/var/lib/pacman/sync/extra.db");
+    std::fs::create_dir_all(db.parent().unwrap()).unwrap();
+    let when = std::time::SystemTime::now() - std::time::Duration::from_secs(days * 86_400);
+    std::fs::File::create(&db)
+        .unwrap()
+        .set_modified(when)
+        .unwrap();
+}
+
+/// Put `<root>/.helpers` first on the PATH of the commands we run.
+fn helpers_on_path(world: &mut World) {
+    let dir = world.under_root("/.helpers");
+    if !world.envs.iter().any(|(k, _)| k == "PATH") {
+        let path = std::env::var("PATH").unwrap_or_default();
+        world
+            .envs
+            .push(("PATH".into(), format!("{}:{}", dir.display(), path)));
+    }
+}
+
+#[given(regex = r#"^an AUR helper "([^"]+)" is on PATH$"#)]
+pub async fn aur_helper_on_path(world: &mut World, name: String) {
+    world.write_file(format!("/.helpers/{}", name), b"#!/bin/sh\nexit 1\n", true);
+    helpers_on_path(world);
+}
+
+/// A `pacman` on PATH that logs its arguments to `/.helpers/pacman.log` and applies `-S`/`-R`
+/// to the local database of the root it is pointed at (`--sysroot`, or `--root`).
+#[given(
+    regex = r#"^a fake pacman on PATH that installs "([^"]+)" ([^ ]+) with applets "([^"]+)"$"#
+)]
+pub async fn fake_pacman(world: &mut World, name: String, version: String, applets: String) {
+    let script = format!(
+        r#"#!/bin/sh
+echo "$@" >> "$(dirname "$0")/pacman.log"
+root=/
+while [ $# -gt 0 ]; do
+  case "$1" in
+    --sysroot|--root) root=$2; shift 2 ;;
+    --dbpath|--cachedir) shift 2 ;;
+    *) break ;;
+  esac
+done
+op=$1; shift
+case "$op" in
+  -Si) [ "$1" = {name} ] || exit 1
+       printf 'Repository      : extra
+Version         : {version}
+' ;;
+  -Qu) exit 1 ;;
+  -S)
+    for p; do
+      case "$p" in -*) continue ;; esac
+      # Other packages already installed are left as they are (a reinstall)
+      if [ "$p" != {name} ] && ls -d "$root/var/lib/pacman/local/$p"-* >/dev/null 2>&1; then
+        continue
+      fi
+      db="$root/var/lib/pacman/local/$p-{version}"
+      mkdir -p "$db"
+      printf '%%NAME%%
+%s
+
+%%VERSION%%
+{version}
+
+' "$p" > "$db/desc"
+      if [ "$p" = {name} ]; then
+        mkdir -p "$root/usr/bin"
+        printf '#!/bin/sh
+echo {applets}
+' > "$root/usr/bin/coreutils"
+        chmod 755 "$root/usr/bin/coreutils"
+        printf '%%FILES%%
+usr/bin/coreutils
+' > "$db/files"
+      else
+        printf '%%FILES%%
+' > "$db/files"
+      fi
+    done ;;
+  -R)
+    for p; do
+      case "$p" in -*) continue ;; esac
+      rm -rf "$root/var/lib/pacman/local/$p"-*
+    done ;;
+esac
+exit 0
+"#
+    );
+    world.write_file("/.helpers/pacman", script.as_bytes(), true);
+    helpers_on_path(world);
+}
+
+#[then(regex = r"^the fake pacman (was|was not) run with `(.+)`$")]
+pub async fn fake_pacman_ran(world: &mut World, was: String, args: String) {
+    let root = world.root_pathbuf().display().to_string();
+    let args = args.replace("$ROOT", &root);
+    let log = std::fs::read_to_string(world.under_root("/.helpers/pacman.log")).unwrap_or_default();
+    assert_eq!(
+        log.lines().any(|l| l == args),
+        was == "was",
+        "pacman log:\n{}",
+        log
+    );
+}
+
+#[given(regex = r#"^the environment variable ([A-Z_]+) is set to "([^"]*)"$"#)]
+pub async fn env_var_set(world: &mut World, key: String, value: String) {
+    world.envs.push((key, value));
+}
+
+#[given(regex = r"^the scripted package manager falls back to the AUR$")]
+pub async fn scripted_aur(world: &mut World) {
+    edit_script(world, |s| s["aur"] = json!(true));
+}
+
+#[given(regex = r#"^a fake AUR toolchain builds "([^"]+)" ([^ ]+) providing applets "([^"]+)"$"#)]
+pub async fn fake_aur_toolchain(world: &mut World, name: String, version: String, applets: String) {
+    // The build runs as another user: it must be able to reach the helpers under the root
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let root = world.root_pathbuf();
+        std::fs::set_permissions(&root, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    world.write_file(
+        "/.helpers/git",
+        b"#!/bin/sh\n# git clone --depth 1 <url> <dir>\nfor last; do :; done\nmkdir -p \"$last\"\n",
+        true,
+    );
+    let makepkg = format!(
+        r#"#!/bin/sh
+if [ "$(id -u)" = 0 ]; then echo "==> ERROR: Running makepkg as root is not allowed" >&2; exit 1; fi
+echo "building as $(id -un)"
+mkdir -p pkg/usr/bin
+printf '#!/bin/sh\necho {applets}\n' > pkg/usr/bin/{bin}
+chmod 755 pkg/usr/bin/{bin}
+printf 'pkgname = {name}\npkgver = {version}\n' > pkg/.PKGINFO
+tar -cf {name}-{version}-x86_64.pkg.tar.zst -C pkg .PKGINFO usr
+"#,
+        applets = applets,
+        bin = "findutils",
+        name = name,
+        version = version,
+    );
+    world.write_file("/.helpers/makepkg", makepkg.as_bytes(), true);
+    let dir = world.under_root("/.helpers");
+    if !world.envs.iter().any(|(k, _)| k == "PATH") {
+        let path = std::env::var("PATH").unwrap_or_default();
+        world
+            .envs
+            .push(("PATH".into(), format!("{}:{}", dir.display(), path)));
+    }
+}
+
+#[then(regex = r#"^the build log contains `(.+)`$"#)]
+pub async fn build_log_contains(world: &mut World, needle: String) {
+    let out = world.last_output.as_ref().expect("command ran");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let event: Value = stderr
+        .lines()
+        .filter_map(|l| serde_json::from_str::<Value>(l).ok())
+        .find(|v| v["event"] == "pm.build")
+        .expect("a pm.build event");
+    let log = std::fs::read_to_string(event["log"].as_str().unwrap()).unwrap();
+    assert!(log.contains(&needle), "build log:\n{}", log);
+}
+
+#[given(
+    regex = r#"^a package archive "([^"]+)" ([^ ]+) with dispatcher "([^"]+)" for applets "([^"]+)" in `([^`]+)`$"#
+)]
+pub async fn package_archive(
+    world: &mut World,
+    name: String,
+    version: String,
+    dispatcher: String,
+    applets: String,
+    dir: String,
+) {
+    let dir = world.under_root(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}-{}-x86_64.pkg.tar.zst", name, version));
+    let mut ar = tar::Builder::new(std::fs::File::create(&path).unwrap());
+    let pkginfo = format!("pkgname = {}\npkgver = {}\n", name, version);
+    let script = format!("#!/bin/sh\necho {}\n", applets);
+    for (member, data, mode) in [
+        (".PKGINFO", pkginfo.as_bytes(), 0o644),
+        (dispatcher.as_str(), script.as_bytes(), 0o755),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(mode);
+        header.set_cksum();
+        ar.append_data(&mut header, member, data).unwrap();
+    }
+    ar.finish().unwrap();
+}
@@ -6,6 +6,7 @@ pub struct World {
     pub artifact_path: Option<std::path::PathBuf>,
     pub envs: Vec<(String, String)>,
     pub pacman_lock: Option<std::fs::File>,
+    pub pm_script: Option<std::path::PathBuf>,
 }
 
 #[cfg(feature = "bdd")]
@@ -41,6 +42,10 @@ impl World {
         if let Some(parent) = p.parent() {
             std::fs::create_dir_all(parent).unwrap();
         }
+        // Replace (rather than write through) an existing symlink at the path
+        if std::fs::symlink_metadata(&p).is_ok_and(|m| m.file_type().is_symlink()) {
+            std::fs::remove_file(&p).expect("remove existing symlink");
+        }
         std::fs::write(&p, contents).expect("write file");
         if exec {
             #[cfg(unix)]